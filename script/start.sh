@@ -3,15 +3,20 @@
 unset http_proxy
 unset https_proxy
 
-cd /root/frp/frp_0.65.0_linux_amd64
-./frpc -c ./frpc.toml &
+# frpc 由 game_master 启动和监管（frpc_supervise），路径见配置里的 frpc_exe_path / frpc_toml_path
 cd /root/game_master
 
-export DATA_SERVER_IP_ADDR=192.168.8.88
+# 没有内置的 frps 和 API token，必须有配置文件，可以从 game_master.example.toml 复制
+CONFIG=/root/game_master/game_master.toml
+if [ ! -f "$CONFIG" ]; then
+    echo "missing $CONFIG, copy script/game_master.example.toml and set frps_addr, frps_token and api_tokens" >&2
+    exit 1
+fi
+
 export RUSTFS_REGION=cn-east-1
 export RUSTFS_ACCESS_KEY_ID=2yv03sZrLW9iaAwKm8uO
 export RUSTFS_SECRET_ACCESS_KEY=thj@13835720054
 export RUSTFS_ENDPOINT_URL=http://192.168.8.168:9001/
 
 chmod +x ./game_master
-./game_master
+./game_master --config "$CONFIG"
@@ -1,5 +1,5 @@
 use std::io::{Read, Seek, Write};
-use anyhow::{Context};
+use anyhow::{bail, Context};
 use zip::{result::ZipError, write::SimpleFileOptions, CompressionMethod, ZipArchive};
 
 use std::fs::File;
@@ -12,7 +12,12 @@ pub fn unzip(archive_path: &str, extract_to: &str) -> anyhow::Result<()> {
 
     for i in 0..archive.len() {
         let mut file = archive.by_index(i)?;
-        let outpath = Path::new(extract_to).join(file.name());
+        // 拒绝 ../ 和绝对路径，防止解压到目标目录之外
+        let name = match file.enclosed_name() {
+            Some(name) => name,
+            None => bail!("unsafe path in archive: {:?}", file.name()),
+        };
+        let outpath = Path::new(extract_to).join(name);
 
         let parent = outpath.parent().expect("no parent");
         if !parent.exists() {
@@ -111,15 +116,60 @@ async fn async_unzip(archive_path: String, extract_to: String) -> anyhow::Result
 
 #[cfg(test)]
 mod tests {
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use tempfile::tempdir;
     use crate::archive::{unzip, zip};
 
+    #[test]
+    fn test_unzip_rejects_traversal() {
+        let temp = tempdir().unwrap();
+        let dir = temp.path();
+        let archive_path = dir.join("evil.zip");
+
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&archive_path).unwrap());
+        writer.start_file("../escaped.txt", SimpleFileOptions::default()).unwrap();
+        writer.write_all(b"evil").unwrap();
+        writer.finish().unwrap();
+
+        let extract_to = dir.join("out");
+        assert!(unzip(archive_path.to_str().unwrap(), extract_to.to_str().unwrap()).is_err());
+        assert!(!dir.join("escaped.txt").exists());
+    }
+
+    // 按存档目录的样子造一个小目录
+    fn make_save(dir: &std::path::Path) -> std::path::PathBuf {
+        let save = dir.join("MyGame");
+        std::fs::create_dir_all(save.join("Region")).unwrap();
+        std::fs::write(save.join("main.ttw"), "world").unwrap();
+        std::fs::write(save.join("Region").join("r.0.0.7rg"), [0u8, 1, 2, 3]).unwrap();
+        save
+    }
+
     #[test]
     fn test_unzip() {
-        unzip("C:\\program1\\Data\\Saves\\Navezgane\\MyGame.zip", "C:\\program1\\Data\\Saves\\Navezgane\\MyGame").unwrap();
+        let temp = tempdir().unwrap();
+        let dir = temp.path();
+        let archive_path = dir.join("MyGame.zip");
+        zip(make_save(dir).to_str().unwrap(), archive_path.to_str().unwrap()).unwrap();
+
+        let extract_to = dir.join("restored");
+        unzip(archive_path.to_str().unwrap(), extract_to.to_str().unwrap()).unwrap();
+        assert_eq!(std::fs::read_to_string(extract_to.join("main.ttw")).unwrap(), "world");
+        assert_eq!(std::fs::read(extract_to.join("Region").join("r.0.0.7rg")).unwrap(), vec![0u8, 1, 2, 3]);
     }
 
     #[test]
     fn test_zip() {
-        zip("C:\\program1\\Data\\Saves\\Navezgane\\MyGame", "C:\\program1\\Data\\Saves\\Navezgane\\MyGame.zip").unwrap();
+        let temp = tempdir().unwrap();
+        let dir = temp.path();
+        let archive_path = dir.join("MyGame.zip");
+        zip(make_save(dir).to_str().unwrap(), archive_path.to_str().unwrap()).unwrap();
+
+        let archive = zip::ZipArchive::new(std::fs::File::open(&archive_path).unwrap()).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort();
+        assert!(names.contains(&"main.ttw"));
+        assert!(names.iter().any(|n| n.ends_with("r.0.0.7rg")));
     }
 }
@@ -0,0 +1,475 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use anyhow::{anyhow, bail};
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{header, HeaderMap, Method};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use crate::common::hmac_sha256_hex;
+use crate::config_util::AppConfig;
+use crate::error::AppError;
+use crate::lease_util::now_secs;
+use crate::registration_util::node_id;
+
+// WebSocket 在浏览器里加不了 Authorization 头，允许放在查询参数里
+pub const ACCESS_TOKEN_PARAM: &str = "access_token";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMode {
+    // 不校验（只适合监听在本机）
+    None,
+    // api_tokens 里配置的静态 token
+    Token,
+    // data server 用 jwt_secret 签发的 HS256 JWT，scope 放在 "scope" 声明里，空格分隔
+    Jwt,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    // 查看状态、配置、mod、任务
+    Read,
+    // 启停服务器、改 serveradmin、装 mod 和游戏
+    Control,
+    // frpc.toml、隧道代理和端口租约
+    Tunnel,
+    // 游戏日志和 frpc 日志流
+    Console,
+}
+
+impl Scope {
+    const ALL: [Scope; 4] = [Scope::Read, Scope::Control, Scope::Tunnel, Scope::Console];
+
+    fn parse(raw: &str) -> Option<Scope> {
+        Scope::ALL.into_iter().find(|scope| scope.name() == raw)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Control => "control",
+            Scope::Tunnel => "tunnel",
+            Scope::Console => "console",
+        }
+    }
+}
+
+// "*" 表示所有 scope
+fn parse_scopes<'a>(raw: impl Iterator<Item = &'a str>) -> anyhow::Result<Vec<Scope>> {
+    let mut scopes = Vec::new();
+    for name in raw.filter(|s| !s.is_empty()) {
+        if name == "*" {
+            return Ok(Scope::ALL.to_vec());
+        }
+        let scope = Scope::parse(name).ok_or_else(|| anyhow!("unknown scope {:?}", name))?;
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    Ok(scopes)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiToken {
+    pub name: String,
+    pub token: String,
+    pub scopes: Vec<Scope>,
+}
+
+// "<name>:<token>:<scope>+<scope>"，例如 "panel:s3cret:read+control"
+pub fn parse_api_token(raw: &str) -> anyhow::Result<ApiToken> {
+    let mut parts = raw.splitn(3, ':');
+    let (Some(name), Some(token), Some(scopes)) = (parts.next(), parts.next(), parts.next()) else {
+        bail!("expected <name>:<token>:<scope>+<scope>");
+    };
+    if name.is_empty() || token.is_empty() {
+        bail!("name and token must not be empty");
+    }
+    let scopes = parse_scopes(scopes.split('+'))?;
+    if scopes.is_empty() {
+        bail!("token {:?} has no scopes", name);
+    }
+    Ok(ApiToken { name: name.to_string(), token: token.to_string(), scopes })
+}
+
+// 路由需要的 scope，None 表示公开
+pub fn required_scope(method: &Method, path: &str) -> Option<Scope> {
+    let read = method == Method::GET || method == Method::HEAD;
+    match path {
+        "/hello" => None,
+        "/7daysserverlog" | "/frpclog" => Some(Scope::Console),
+        // 只比较，不写入
+        "/serverconfig/diff" => Some(Scope::Read),
+        "/tunnel/status" | "/tunnel/ports" if read => Some(Scope::Read),
+        "/get_frpc_toml" | "/reset_frpc_toml" | "/reset_frpc_toml_by_index" => Some(Scope::Tunnel),
+        p if p.starts_with("/tunnel/") || p == "/port_leases" || p.starts_with("/port_leases/") => Some(Scope::Tunnel),
+        "/start_7days" | "/stop_7days" => Some(Scope::Control),
+        _ if read => Some(Scope::Read),
+        _ => Some(Scope::Control),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Principal {
+    pub subject: String,
+    pub scopes: Vec<Scope>,
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+}
+
+#[derive(Deserialize)]
+struct JwtClaims {
+    #[serde(default)]
+    sub: String,
+    exp: u64,
+    nbf: Option<u64>,
+    // 字符串或字符串数组
+    aud: Option<Value>,
+    #[serde(default)]
+    scope: String,
+}
+
+fn base64url_decode(input: &str) -> Option<Vec<u8>> {
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    for c in input.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'-' => 62,
+            b'_' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6) | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            out.push((bits >> bit_count) as u8);
+        }
+    }
+    Some(out)
+}
+
+// 先取摘要再逐字节比较，耗时和内容、长度无关
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let (a, b) = (Sha256::digest(a), Sha256::digest(b));
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// 只接受 HS256；带 aud 时必须包含本节点的 node_id
+pub fn verify_jwt(secret: &str, audience: &str, token: &str, now: u64) -> anyhow::Result<Principal> {
+    let mut parts = token.split('.');
+    let (Some(header), Some(claims), Some(signature), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+        bail!("malformed jwt");
+    };
+    let decode = |part: &str| base64url_decode(part).ok_or_else(|| anyhow!("malformed jwt"));
+    let jwt_header: JwtHeader = serde_json::from_slice(&decode(header)?)?;
+    if jwt_header.alg != "HS256" {
+        bail!("unsupported jwt alg {:?}", jwt_header.alg);
+    }
+    let signature: String = decode(signature)?.iter().map(|b| format!("{:02x}", b)).collect();
+    let expected = hmac_sha256_hex(secret.as_bytes(), format!("{}.{}", header, claims).as_bytes());
+    if !constant_time_eq(signature.as_bytes(), expected.as_bytes()) {
+        bail!("bad jwt signature");
+    }
+    let claims: JwtClaims = serde_json::from_slice(&decode(claims)?)?;
+    if now >= claims.exp {
+        bail!("jwt expired");
+    }
+    if claims.nbf.is_some_and(|nbf| now < nbf) {
+        bail!("jwt not yet valid");
+    }
+    let audience_ok = match &claims.aud {
+        None => true,
+        Some(Value::String(aud)) => aud == audience,
+        Some(Value::Array(auds)) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+        Some(_) => false,
+    };
+    if !audience_ok {
+        bail!("jwt audience does not include {:?}", audience);
+    }
+    let scopes = parse_scopes(claims.scope.split_whitespace())?;
+    Ok(Principal { subject: if claims.sub.is_empty() { "jwt".to_string() } else { claims.sub }, scopes })
+}
+
+// 审计日志一行一条 JSON
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    pub at: u64,
+    pub remote: String,
+    pub method: String,
+    // 不含查询参数，避免把 access_token 写进日志
+    pub path: String,
+    pub subject: Option<String>,
+    pub scope: Option<Scope>,
+    pub outcome: String,
+    pub reason: Option<String>,
+}
+
+pub struct Authenticator {
+    mode: AuthMode,
+    tokens: Vec<ApiToken>,
+    jwt_secret: String,
+    audience: String,
+    audit_log_path: PathBuf,
+}
+
+impl Authenticator {
+    pub fn from_config(config: &AppConfig) -> anyhow::Result<Self> {
+        let tokens = config.api_tokens.iter().map(|raw| parse_api_token(raw)).collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Authenticator {
+            mode: config.auth_mode,
+            tokens,
+            jwt_secret: config.jwt_secret.clone(),
+            audience: node_id(config),
+            audit_log_path: config.audit_log_path.clone(),
+        })
+    }
+
+    pub fn authenticate(&self, credential: &str, now: u64) -> anyhow::Result<Principal> {
+        match self.mode {
+            AuthMode::None => Ok(Principal { subject: "anonymous".to_string(), scopes: Scope::ALL.to_vec() }),
+            AuthMode::Token => self.tokens.iter()
+                .find(|token| constant_time_eq(token.token.as_bytes(), credential.as_bytes()))
+                .map(|token| Principal { subject: token.name.clone(), scopes: token.scopes.clone() })
+                .ok_or_else(|| anyhow!("unknown token")),
+            AuthMode::Jwt => verify_jwt(&self.jwt_secret, &self.audience, credential, now),
+        }
+    }
+
+    async fn audit(&self, entry: AuditEntry) {
+        eprintln!("audit: {} {} {} from {} subject={:?} reason={:?}", entry.outcome, entry.method, entry.path, entry.remote,
+                  entry.subject, entry.reason);
+        let mut line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        line.push('\n');
+        let written = async {
+            if let Some(parent) = self.audit_log_path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(&self.audit_log_path).await?;
+            file.write_all(line.as_bytes()).await?;
+            // tokio 的 File 在后台线程写，不 flush 的话返回时可能还没落盘
+            file.flush().await
+        };
+        if let Err(e) = written.await {
+            eprintln!("write audit log {:?} error: {}", self.audit_log_path, e);
+        }
+    }
+}
+
+// Authorization: Bearer <credential>，或查询参数 access_token=<credential>
+fn credential(headers: &HeaderMap, query: Option<&str>) -> Option<String> {
+    if let Some(value) = headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok()) {
+        return value.strip_prefix("Bearer ").map(|c| c.trim().to_string());
+    }
+    query?.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == ACCESS_TOKEN_PARAM)
+        .map(|(_, value)| value.to_string())
+}
+
+// 所有路由（包括 WebSocket 升级）先经过这里；拒绝的请求和非只读的操作写审计日志
+pub async fn authorize(
+    State(auth): State<Arc<Authenticator>>,
+    ConnectInfo(remote): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(scope) = required_scope(request.method(), request.uri().path()) else {
+        return next.run(request).await;
+    };
+    if auth.mode == AuthMode::None {
+        return next.run(request).await;
+    }
+    let mut entry = AuditEntry {
+        at: now_secs(),
+        remote: remote.to_string(),
+        method: request.method().to_string(),
+        path: request.uri().path().to_string(),
+        subject: None,
+        scope: Some(scope),
+        outcome: "allowed".to_string(),
+        reason: None,
+    };
+    let principal = match credential(request.headers(), request.uri().query()) {
+        Some(credential) => auth.authenticate(&credential, entry.at),
+        None => Err(anyhow!("missing credential")),
+    };
+    let error = match principal {
+        Err(e) => {
+            entry.outcome = "unauthorized".to_string();
+            entry.reason = Some(e.to_string());
+            AppError::Unauthorized(e.to_string())
+        }
+        Ok(principal) => {
+            entry.subject = Some(principal.subject);
+            if principal.scopes.contains(&scope) {
+                if scope != Scope::Read {
+                    auth.audit(entry).await;
+                }
+                return next.run(request).await;
+            }
+            entry.outcome = "forbidden".to_string();
+            entry.reason = Some(format!("missing scope {}", scope.name()));
+            AppError::Forbidden(format!("missing scope {}", scope.name()))
+        }
+    };
+    auth.audit(entry).await;
+    error.into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use axum::http::{Method, StatusCode};
+    use axum::routing::get;
+    use axum::Router;
+    use tempfile::tempdir;
+    use crate::auth_util::{authorize, parse_api_token, required_scope, verify_jwt, AuditEntry, AuthMode, Authenticator, Scope};
+    use crate::common::hmac_sha256_hex;
+    use crate::config_util::AppConfig;
+
+    fn base64url_encode(input: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+        let mut out = String::new();
+        for chunk in input.chunks(3) {
+            let n = chunk.iter().enumerate().fold(0u32, |acc, (i, b)| acc | (*b as u32) << (16 - 8 * i));
+            for i in 0..=chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            }
+        }
+        out
+    }
+
+    fn sign_jwt(secret: &str, claims: serde_json::Value) -> String {
+        let signing_input = format!("{}.{}", base64url_encode(br#"{"alg":"HS256","typ":"JWT"}"#),
+                                    base64url_encode(claims.to_string().as_bytes()));
+        let signature = hmac_sha256_hex(secret.as_bytes(), signing_input.as_bytes());
+        let bytes: Vec<u8> = (0..signature.len()).step_by(2).map(|i| u8::from_str_radix(&signature[i..i + 2], 16).unwrap()).collect();
+        format!("{}.{}", signing_input, base64url_encode(&bytes))
+    }
+
+    #[test]
+    fn parse_api_token_test() {
+        let token = parse_api_token("panel:s3:cret:read+control").unwrap_err();
+        assert!(token.to_string().contains("unknown scope"));
+        let token = parse_api_token("panel:s3cret:read+control+read").unwrap();
+        assert_eq!((token.name.as_str(), token.token.as_str(), token.scopes), ("panel", "s3cret", vec![Scope::Read, Scope::Control]));
+        assert_eq!(parse_api_token("ops:x:*").unwrap().scopes.len(), 4);
+        assert!(parse_api_token("panel:s3cret").is_err());
+        assert!(parse_api_token("panel:s3cret:").is_err());
+        assert!(parse_api_token(":s3cret:read").is_err());
+    }
+
+    #[test]
+    fn required_scope_test() {
+        assert_eq!(required_scope(&Method::GET, "/hello"), None);
+        assert_eq!(required_scope(&Method::GET, "/status"), Some(Scope::Read));
+        assert_eq!(required_scope(&Method::GET, "/start_7days"), Some(Scope::Control));
+        assert_eq!(required_scope(&Method::GET, "/stop_7days"), Some(Scope::Control));
+        assert_eq!(required_scope(&Method::POST, "/mods"), Some(Scope::Control));
+        assert_eq!(required_scope(&Method::DELETE, "/serveradmin/admins/Steam/1"), Some(Scope::Control));
+        assert_eq!(required_scope(&Method::POST, "/serverconfig/diff"), Some(Scope::Read));
+        assert_eq!(required_scope(&Method::GET, "/tunnel/status"), Some(Scope::Read));
+        assert_eq!(required_scope(&Method::GET, "/tunnel/config"), Some(Scope::Tunnel));
+        assert_eq!(required_scope(&Method::POST, "/tunnel/reload"), Some(Scope::Tunnel));
+        assert_eq!(required_scope(&Method::GET, "/get_frpc_toml"), Some(Scope::Tunnel));
+        assert_eq!(required_scope(&Method::POST, "/port_leases/abc/renew"), Some(Scope::Tunnel));
+        assert_eq!(required_scope(&Method::GET, "/7daysserverlog"), Some(Scope::Console));
+        assert_eq!(required_scope(&Method::GET, "/frpclog"), Some(Scope::Console));
+    }
+
+    #[test]
+    fn verify_jwt_test() {
+        let token = sign_jwt("secret", serde_json::json!({"sub": "data-server", "exp": 2000, "aud": ["node-1"], "scope": "read console"}));
+        let principal = verify_jwt("secret", "node-1", &token, 1000).unwrap();
+        assert_eq!((principal.subject.as_str(), principal.scopes), ("data-server", vec![Scope::Read, Scope::Console]));
+        assert!(verify_jwt("other", "node-1", &token, 1000).unwrap_err().to_string().contains("signature"));
+        assert!(verify_jwt("secret", "node-1", &token, 2000).unwrap_err().to_string().contains("expired"));
+        assert!(verify_jwt("secret", "node-2", &token, 1000).unwrap_err().to_string().contains("audience"));
+
+        let not_yet = sign_jwt("secret", serde_json::json!({"exp": 2000, "nbf": 1500, "scope": "read"}));
+        assert!(verify_jwt("secret", "node-1", &not_yet, 1000).is_err());
+        assert_eq!(verify_jwt("secret", "node-1", &not_yet, 1600).unwrap().subject, "jwt");
+
+        // alg=none 和篡改过的 payload
+        let (_, rest) = token.split_once('.').unwrap();
+        let none = format!("{}.{}", base64url_encode(br#"{"alg":"none"}"#), rest);
+        assert!(verify_jwt("secret", "node-1", &none, 1000).is_err());
+        let parts: Vec<&str> = token.split('.').collect();
+        let forged = format!("{}.{}.{}", parts[0], base64url_encode(br#"{"exp":2000,"scope":"tunnel"}"#), parts[2]);
+        assert!(verify_jwt("secret", "node-1", &forged, 1000).is_err());
+    }
+
+    #[tokio::test]
+    async fn authorize_test() {
+        let temp = tempdir().unwrap();
+        let audit_log_path = temp.path().join("logs/audit.log");
+        let config = AppConfig {
+            auth_mode: AuthMode::Token,
+            api_tokens: vec!["panel:panel-token:read+control".to_string(), "viewer:viewer-token:read".to_string()],
+            audit_log_path: audit_log_path.clone(),
+            ..AppConfig::default()
+        };
+        let auth = Arc::new(Authenticator::from_config(&config).unwrap());
+        let app = Router::new()
+            .route("/hello", get(|| async { "hello" }))
+            .route("/status", get(|| async { "ok" }))
+            .route("/stop_7days", get(|| async { "stopped" }))
+            .route("/7daysserverlog", get(|| async { "log" }))
+            .layer(axum::middleware::from_fn_with_state(auth, authorize));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap()
+        });
+
+        let client = reqwest::Client::new();
+        let status = |path: &str, token: Option<&str>| {
+            let mut request = client.get(format!("{}{}", base_url, path));
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+            async move { request.send().await.unwrap().status() }
+        };
+        assert_eq!(status("/hello", None).await, StatusCode::OK);
+        assert_eq!(status("/status", None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status("/status", Some("wrong")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status("/status", Some("viewer-token")).await, StatusCode::OK);
+        assert_eq!(status("/stop_7days", Some("viewer-token")).await, StatusCode::FORBIDDEN);
+        assert_eq!(status("/stop_7days", Some("panel-token")).await, StatusCode::OK);
+        assert_eq!(status("/7daysserverlog", Some("panel-token")).await, StatusCode::FORBIDDEN);
+        assert_eq!(status("/7daysserverlog?access_token=viewer-token", None).await, StatusCode::FORBIDDEN);
+        assert_eq!(status("/status?access_token=viewer-token", None).await, StatusCode::OK);
+
+        let audit: Vec<AuditEntry> = std::fs::read_to_string(&audit_log_path).unwrap()
+            .lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        let outcomes: Vec<(&str, &str, Option<&str>)> = audit.iter()
+            .map(|e| (e.outcome.as_str(), e.path.as_str(), e.subject.as_deref())).collect();
+        assert_eq!(outcomes, vec![
+            ("unauthorized", "/status", None),
+            ("unauthorized", "/status", None),
+            ("forbidden", "/stop_7days", Some("viewer")),
+            ("allowed", "/stop_7days", Some("panel")),
+            ("forbidden", "/7daysserverlog", Some("panel")),
+            ("forbidden", "/7daysserverlog", Some("viewer")),
+        ]);
+        assert!(audit.iter().all(|e| e.remote.starts_with("127.0.0.1:")));
+        assert!(!std::fs::read_to_string(&audit_log_path).unwrap().contains("viewer-token"));
+    }
+}
@@ -1,14 +1,390 @@
-use std::io;
+use std::collections::{BTreeMap, HashMap};
 use std::io::Write;
-use std::thread::sleep;
-use std::time::Duration;
-
-fn main() {
-    let mut i = 0;
-    loop {
-        sleep(Duration::from_secs(1));
-        println!("{} Starting game server...", i);
-        i += 1;
-        io::stdout().flush().unwrap();
-    }
-}
\ No newline at end of file
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, bail};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Notify;
+
+// 模拟 7DaysToDieServer，给集成测试用：
+// 读 serverconfig.xml，往 -logfile 写和真实服务器一样格式的日志，监听游戏端口和 telnet 端口，
+// 写存档目录，收到 SIGINT/SIGTERM 或控制台 shutdown 后延迟退出。
+// 连接游戏端口并发送一行名字就算一个玩家进入，断开连接算离开。
+
+// 游戏第 1 天 07:00 开始
+const START_WORLD_MINUTES: f64 = 7.0 * 60.0;
+const WORLD_FILE_NAME: &str = "main.ttw";
+
+#[derive(Debug, Clone, PartialEq)]
+struct Args {
+    logfile: Option<PathBuf>,
+    configfile: PathBuf,
+    // 以下是模拟器自己的参数
+    shutdown_delay: Duration,
+    autosave_interval: Duration,
+}
+
+fn parse_args(args: &[String]) -> anyhow::Result<Args> {
+    let mut parsed = Args {
+        logfile: None,
+        configfile: PathBuf::from("serverconfig.xml"),
+        shutdown_delay: Duration::from_secs(1),
+        autosave_interval: Duration::from_secs(300),
+    };
+    let secs = |value: &str| value.parse::<u64>().map(Duration::from_secs).map_err(|_| anyhow!("{:?} is not a number of seconds", value));
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "-logfile" {
+            parsed.logfile = Some(PathBuf::from(iter.next().ok_or_else(|| anyhow!("-logfile needs a path"))?));
+        } else if let Some(value) = arg.strip_prefix("-configfile=") {
+            parsed.configfile = PathBuf::from(value);
+        } else if let Some(value) = arg.strip_prefix("-folk-shutdown-delay=") {
+            parsed.shutdown_delay = secs(value)?;
+        } else if let Some(value) = arg.strip_prefix("-folk-autosave=") {
+            parsed.autosave_interval = secs(value)?;
+        } else if !arg.starts_with('-') {
+            bail!("unexpected argument {:?}", arg);
+        }
+        // -quit -batchmode -nographics -dedicated 等照单全收
+    }
+    Ok(parsed)
+}
+
+fn read_properties(path: &Path) -> anyhow::Result<HashMap<String, String>> {
+    let contents = std::fs::read_to_string(path).map_err(|e| anyhow!("read {:?}: {}", path, e))?;
+    let document = roxmltree::Document::parse(&contents)?;
+    Ok(document.descendants()
+        .filter(|node| node.has_tag_name("property"))
+        .filter_map(|node| Some((node.attribute("name")?.to_string(), node.attribute("value")?.to_string())))
+        .collect())
+}
+
+// 1970-01-01 起的天数转公历日期
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (if month <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 }, month, day)
+}
+
+// "2024-07-25T12:00:00"
+fn format_timestamp(unix_secs: u64) -> String {
+    let (year, month, day) = civil_from_days((unix_secs / 86400) as i64);
+    let secs = unix_secs % 86400;
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", year, month, day, secs / 3600, secs % 3600 / 60, secs % 60)
+}
+
+// 控制台 gettime 的格式: "Day 12, 13:45"
+fn format_game_time(world_minutes: f64) -> String {
+    let minutes = world_minutes as u64;
+    format!("Day {}, {:02}:{:02}", minutes / 1440 + 1, minutes % 1440 / 60, minutes % 60)
+}
+
+struct Logger {
+    file: Option<std::fs::File>,
+    started: Instant,
+}
+
+impl Logger {
+    // 和游戏日志一样: "<时间> <运行秒数> INF <消息>"
+    fn log(&mut self, level: &str, message: &str) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let line = format!("{} {:.3} {} {}", format_timestamp(now), self.started.elapsed().as_secs_f64(), level, message);
+        println!("{}", line);
+        if let Some(file) = self.file.as_mut() {
+            let _ = writeln!(file, "{}", line);
+            let _ = file.flush();
+        }
+    }
+}
+
+struct Player {
+    name: String,
+    platform_id: String,
+}
+
+struct World {
+    log: Logger,
+    // 游戏内分钟数，每个真实秒前进 minutes_per_sec
+    minutes: f64,
+    minutes_per_sec: f64,
+    last_tick: Instant,
+    players: BTreeMap<u32, Player>,
+    next_entity_id: u32,
+    max_players: usize,
+    save_dir: PathBuf,
+}
+
+impl World {
+    fn tick(&mut self) -> f64 {
+        self.minutes += self.last_tick.elapsed().as_secs_f64() * self.minutes_per_sec;
+        self.last_tick = Instant::now();
+        self.minutes
+    }
+
+    fn save(&mut self) {
+        let minutes = self.tick();
+        let result = std::fs::create_dir_all(self.save_dir.join("Player"))
+            .and_then(|_| std::fs::write(self.save_dir.join(WORLD_FILE_NAME), format!("folk_server world\nworld_minutes={}\n", minutes as u64)));
+        match result {
+            Ok(()) => self.log.log("INF", &format!("World saved to {}", self.save_dir.display())),
+            Err(e) => self.log.log("ERR", &format!("Saving world failed: {}", e)),
+        }
+    }
+}
+
+type SharedWorld = Arc<Mutex<World>>;
+
+// 已有存档时沿用里面的游戏时间
+fn saved_world_minutes(save_dir: &Path) -> Option<f64> {
+    std::fs::read_to_string(save_dir.join(WORLD_FILE_NAME)).ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("world_minutes="))?
+        .parse()
+        .ok()
+}
+
+fn save_dir(properties: &HashMap<String, String>, config_dir: &Path) -> PathBuf {
+    let property = |name: &str, default: &str| properties.get(name).cloned().unwrap_or_else(|| default.to_string());
+    let user_data = config_dir.join(property("UserDataFolder", "Data"));
+    let world = property("GameWorld", "Navezgane");
+    // RWG 世界目录名由种子决定
+    let world_dir = if world == "RWG" { property("WorldGenSeed", "folk") } else { world };
+    user_data.join("Saves").join(world_dir).join(property("GameName", "MyGame"))
+}
+
+// 游戏端口：客户端发送一行名字后算进入游戏，断开连接算离开
+async fn handle_player(mut stream: TcpStream, world: SharedWorld) {
+    let mut reader = BufReader::new(&mut stream);
+    let mut name = String::new();
+    if reader.read_line(&mut name).await.unwrap_or(0) == 0 {
+        return;
+    }
+    let name = name.trim().to_string();
+    let entity_id = {
+        let mut world = world.lock().unwrap();
+        if world.players.len() >= world.max_players {
+            world.log.log("INF", &format!("Kicking player {}: server is full", name));
+            None
+        } else {
+            world.next_entity_id += 1;
+            let entity_id = world.next_entity_id;
+            let platform_id = format!("Steam_7656119{:010}", entity_id);
+            world.log.log("INF", &format!("Player connected, entityid={}, name={}, pltfmid={}, crossid=EOS_{:032x}, steamOwner={}, ip=127.0.0.1",
+                                          entity_id, name, platform_id, entity_id, platform_id));
+            world.players.insert(entity_id, Player { name: name.clone(), platform_id });
+            Some(entity_id)
+        }
+    };
+    let Some(entity_id) = entity_id else {
+        let _ = stream.write_all(b"Server is full\n").await;
+        return;
+    };
+    let _ = stream.write_all(format!("Welcome {}\n", name).as_bytes()).await;
+
+    let mut buf = String::new();
+    let mut reader = BufReader::new(&mut stream);
+    while reader.read_line(&mut buf).await.map(|n| n > 0).unwrap_or(false) {
+        buf.clear();
+    }
+
+    let mut world = world.lock().unwrap();
+    if let Some(player) = world.players.remove(&entity_id) {
+        world.log.log("INF", &format!("Player disconnected: EntId={}, PlayerID='{}', OwnerID='{}', PlayerName='{}', ClientNumber='{}'",
+                                      entity_id, player.platform_id, player.platform_id, player.name, entity_id));
+    }
+}
+
+// 控制台命令，返回输出和是否请求关服
+fn console_command(world: &SharedWorld, command: &str) -> (String, bool) {
+    let mut world = world.lock().unwrap();
+    let name = command.split_whitespace().next().unwrap_or_default();
+    world.log.log("INF", &format!("Executing command '{}' by Telnet from 127.0.0.1", command));
+    match name {
+        "lp" | "listplayers" => {
+            let mut output = String::new();
+            for (i, (entity_id, player)) in world.players.iter().enumerate() {
+                output.push_str(&format!("{}. id={}, {}, pos=(0.0, 61.0, 0.0), pltfmid={}\r\n", i + 1, entity_id, player.name, player.platform_id));
+            }
+            output.push_str(&format!("Total of {} in the game\r\n", world.players.len()));
+            (output, false)
+        }
+        "gettime" | "gt" => (format!("{}\r\n", format_game_time(world.tick())), false),
+        "saveworld" | "sa" => {
+            world.save();
+            ("World saved\r\n".to_string(), false)
+        }
+        "version" => ("Game version: folk_server 0.1 (b1) Compatibility Version: V 1.0\r\n".to_string(), false),
+        "shutdown" => ("Shutting server down...\r\n".to_string(), true),
+        // 模拟崩溃，测试 server_crashed
+        "folk-crash" => {
+            world.log.log("ERR", "Simulated crash");
+            std::process::exit(134);
+        }
+        "" => (String::new(), false),
+        _ => (format!("*** ERROR: unknown command '{}'\r\n", name), false),
+    }
+}
+
+async fn handle_telnet(stream: TcpStream, world: SharedWorld, password: String, shutdown: Arc<Notify>) -> anyhow::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    if !password.is_empty() {
+        writer.write_all(b"Please enter password:\r\n").await?;
+        if lines.next_line().await?.map(|line| line.trim().to_string()) != Some(password) {
+            writer.write_all(b"Password incorrect, please enter password:\r\n").await?;
+            return Ok(());
+        }
+        writer.write_all(b"Logon successful.\r\n").await?;
+    }
+    writer.write_all(b"*** Connected with 7DTD server.\r\n*** Server version: folk_server 0.1\r\n\r\n").await?;
+    while let Some(line) = lines.next_line().await? {
+        let command = line.trim();
+        if command == "exit" {
+            break;
+        }
+        let (output, stop) = console_command(&world, command);
+        writer.write_all(output.as_bytes()).await?;
+        if stop {
+            shutdown.notify_one();
+        }
+    }
+    Ok(())
+}
+
+async fn wait_for_signal(shutdown: Arc<Notify>) -> &'static str {
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => "SIGINT",
+        _ = terminate => "SIGTERM",
+        _ = shutdown.notified() => "console",
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = parse_args(&std::env::args().skip(1).collect::<Vec<_>>())?;
+    let file = match &args.logfile {
+        Some(path) => Some(std::fs::File::create(path).map_err(|e| anyhow!("create log {:?}: {}", path, e))?),
+        None => None,
+    };
+    let mut log = Logger { file, started: Instant::now() };
+    log.log("INF", "Starting folk_server (7 Days to Die dedicated server simulator)");
+
+    let properties = read_properties(&args.configfile)?;
+    let property = |name: &str| properties.get(name).cloned().unwrap_or_default();
+    log.log("INF", &format!("Loaded serverconfig {}", args.configfile.display()));
+    let config_dir = args.configfile.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let save_dir = save_dir(&properties, config_dir);
+    let game_port: u16 = property("ServerPort").parse().unwrap_or(26900);
+    let day_length: f64 = property("DayNightLength").parse().unwrap_or(60.0);
+
+    let game_listener = TcpListener::bind(("0.0.0.0", game_port)).await.map_err(|e| anyhow!("bind game port {}: {}", game_port, e))?;
+    log.log("INF", &format!("NET: Starting server protocols on port {}", game_port));
+    let telnet_listener = if property("TelnetEnabled").eq_ignore_ascii_case("true") {
+        let port: u16 = property("TelnetPort").parse().unwrap_or(8081);
+        let listener = TcpListener::bind(("127.0.0.1", port)).await.map_err(|e| anyhow!("bind telnet port {}: {}", port, e))?;
+        log.log("INF", &format!("Started Telnet on {}", port));
+        Some(listener)
+    } else {
+        None
+    };
+
+    let world = Arc::new(Mutex::new(World {
+        log,
+        minutes: saved_world_minutes(&save_dir).unwrap_or(START_WORLD_MINUTES),
+        minutes_per_sec: 24.0 / day_length.max(1.0),
+        last_tick: Instant::now(),
+        players: BTreeMap::new(),
+        next_entity_id: 170,
+        max_players: property("ServerMaxPlayerCount").parse().unwrap_or(8),
+        save_dir,
+    }));
+    {
+        let mut world = world.lock().unwrap();
+        let message = format!("Loading world {} game {}", property("GameWorld"), property("GameName"));
+        world.log.log("INF", &message);
+        world.save();
+        world.log.log("INF", "StartGame done");
+    }
+
+    let players = world.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = game_listener.accept().await {
+            tokio::spawn(handle_player(stream, players.clone()));
+        }
+    });
+    let shutdown = Arc::new(Notify::new());
+    if let Some(listener) = telnet_listener {
+        let (console, password, shutdown) = (world.clone(), property("TelnetPassword"), shutdown.clone());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (console, password, shutdown) = (console.clone(), password.clone(), shutdown.clone());
+                tokio::spawn(async move {
+                    if let Err(e) = handle_telnet(stream, console, password, shutdown).await {
+                        eprintln!("telnet error: {}", e);
+                    }
+                });
+            }
+        });
+    }
+    let autosave = world.clone();
+    let interval = args.autosave_interval;
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            autosave.lock().unwrap().save();
+        }
+    });
+
+    let source = wait_for_signal(shutdown).await;
+    world.lock().unwrap().log.log("INF", &format!("Shutdown game from {}", source));
+    tokio::time::sleep(args.shutdown_delay).await;
+    let mut world = world.lock().unwrap();
+    world.save();
+    world.log.log("INF", "Game server stopped");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use crate::{format_game_time, format_timestamp, parse_args};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_args_test() {
+        let parsed = parse_args(&args(&["-logfile", "/tmp/output_log.txt", "-quit", "-batchmode", "-configfile=serverconfig.xml",
+                                        "-folk-shutdown-delay=3"])).unwrap();
+        assert_eq!(parsed.logfile, Some(PathBuf::from("/tmp/output_log.txt")));
+        assert_eq!(parsed.configfile, PathBuf::from("serverconfig.xml"));
+        assert_eq!(parsed.shutdown_delay, Duration::from_secs(3));
+        assert!(parse_args(&args(&["-logfile"])).is_err());
+        assert!(parse_args(&args(&["-folk-autosave=soon"])).is_err());
+    }
+
+    #[test]
+    fn format_test() {
+        assert_eq!(format_timestamp(1721908800), "2024-07-25T12:00:00");
+        assert_eq!(format_game_time(420.0), "Day 1, 07:00");
+        assert_eq!(format_game_time(11.0 * 1440.0 + 13.0 * 60.0 + 45.0), "Day 12, 13:45");
+    }
+}
@@ -0,0 +1,29 @@
+// spawn 只在测试里用
+#[allow(dead_code)]
+#[path = "../mock_data_server_util.rs"]
+mod mock_data_server_util;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use crate::mock_data_server_util::{router, FIXTURES_DIR};
+
+// 本地开发用的 data server：mock_data_server [--listen 127.0.0.1:3000] [--fixtures <dir>]
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut listen = "127.0.0.1:3000".to_string();
+    let mut fixtures = PathBuf::from(FIXTURES_DIR);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => listen = args.next().ok_or_else(|| anyhow::anyhow!("--listen needs a value"))?,
+            "--fixtures" => fixtures = PathBuf::from(args.next().ok_or_else(|| anyhow::anyhow!("--fixtures needs a value"))?),
+            _ => anyhow::bail!("unknown argument {}", arg),
+        }
+    }
+
+    println!("mock data server on {} with fixtures {:?}", listen, fixtures);
+    let listener = tokio::net::TcpListener::bind(&listen).await?;
+    axum::serve(listener, router(fixtures, Arc::new(Mutex::new(Vec::new())))).await?;
+    Ok(())
+}
@@ -1,15 +1,32 @@
 use std::num::ParseIntError;
-use anyhow::bail;
+use std::path::Path;
+use anyhow::{anyhow, bail};
 use local_ip_address::{list_afinet_netifas};
-use crate::const_value::{INDEX_OFFSET,  NET_INTERFACE_NAME};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::config_util::AppConfig;
 
-pub fn get_local_ip() -> Option<String> {
+// 节点编号的来源
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexStrategy {
+    // 配置里的 node_index
+    Explicit,
+    // 主机名末尾的数字，例如 game-node-3
+    Hostname,
+    // 网卡 IPv4 最后一段减去 index_offset
+    IpOctet,
+    // 注册时由 data server 分配
+    DataServer,
+}
+
+pub fn get_local_ip(interface_name: &str) -> Option<String> {
     match list_afinet_netifas() {
         Ok(network_interfaces) => {
             println!("find ip:");
             for (name, ip) in network_interfaces.iter() {
                 println!("  {}:\t{}", name, ip);
-                if name == NET_INTERFACE_NAME && ip.is_ipv4() {
+                if name == interface_name && ip.is_ipv4() {
                     return Some(ip.to_string());
                 }
             }
@@ -30,12 +47,128 @@ pub fn splite_ip(ip_str: &str) -> Result<Vec<u8>, ParseIntError> {
     parts
 }
 
-pub fn get_index() -> anyhow::Result<u8> {
-    let ip = get_local_ip().unwrap();
-    let ip_vec = splite_ip(&ip).unwrap();
-    let last_number = *ip_vec.get(3).unwrap();
-    if last_number < 200 {
-        bail!("last ip address is less than 200 ");
+pub fn index_from_ip(ip: &str, index_offset: u8) -> anyhow::Result<u8> {
+    let ip_vec = splite_ip(ip).map_err(|e| anyhow!("invalid ip {:?}: {}", ip, e))?;
+    let Some(&last_number) = ip_vec.get(3) else {
+        bail!("invalid ip {:?}", ip);
+    };
+    if last_number < index_offset {
+        bail!("last ip address {} is less than {}", last_number, index_offset);
+    }
+    Ok(last_number - index_offset)
+}
+
+pub fn get_index(interface_name: &str, index_offset: u8) -> anyhow::Result<u8> {
+    let Some(ip) = get_local_ip(interface_name) else {
+        bail!("no ipv4 address on interface {}", interface_name);
+    };
+    index_from_ip(&ip, index_offset)
+}
+
+pub fn index_from_hostname(hostname: &str) -> anyhow::Result<u8> {
+    let hostname = hostname.trim();
+    let digits = &hostname[hostname.trim_end_matches(|c: char| c.is_ascii_digit()).len()..];
+    if digits.is_empty() {
+        bail!("hostname {:?} does not end with a number", hostname);
+    }
+    digits.parse::<u8>().map_err(|_| anyhow!("hostname {:?}: index {} is out of range", hostname, digits))
+}
+
+pub fn local_hostname() -> anyhow::Result<String> {
+    match std::fs::read_to_string("/proc/sys/kernel/hostname") {
+        Ok(hostname) => Ok(hostname.trim().to_string()),
+        Err(e) => std::env::var("HOSTNAME").map_err(|_| anyhow!("read hostname: {}", e)),
+    }
+}
+
+// Ok(None) 表示编号要等注册时由 data server 分配
+pub fn discover_index(config: &AppConfig) -> anyhow::Result<Option<u8>> {
+    let index = match config.index_strategy {
+        IndexStrategy::Explicit => match config.node_index {
+            Some(index) => index,
+            None => bail!("index_strategy is explicit but node_index is not set"),
+        },
+        IndexStrategy::Hostname => index_from_hostname(&local_hostname()?)?,
+        IndexStrategy::IpOctet => get_index(&config.net_interface_name, config.index_offset)?,
+        IndexStrategy::DataServer => return Ok(None),
+    };
+    Ok(Some(index))
+}
+
+// 文件的 SHA-256，返回小写十六进制
+pub fn sha256_file_hex(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+// HMAC-SHA256（RFC 2104），返回小写十六进制
+pub fn hmac_sha256_hex(key: &[u8], message: &[u8]) -> String {
+    const BLOCK_SIZE: usize = 64;
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    inner.update(message);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+    use crate::common::{discover_index, hmac_sha256_hex, index_from_hostname, sha256_file_hex, index_from_ip, IndexStrategy};
+    use crate::config_util::AppConfig;
+
+    #[test]
+    fn index_from_hostname_test() {
+        assert_eq!(index_from_hostname("game-node-3\n").unwrap(), 3);
+        assert_eq!(index_from_hostname("node12").unwrap(), 12);
+        assert!(index_from_hostname("game-node").is_err());
+        assert!(index_from_hostname("node-300").is_err());
+    }
+
+    #[test]
+    fn index_from_ip_test() {
+        assert_eq!(index_from_ip("192.168.8.203", 200).unwrap(), 3);
+        assert_eq!(index_from_ip("10.0.0.7", 0).unwrap(), 7);
+        assert!(index_from_ip("192.168.8.17", 200).is_err());
+        assert!(index_from_ip("fe80::1", 0).is_err());
+    }
+
+    #[test]
+    fn discover_index_test() {
+        let explicit = AppConfig { index_strategy: IndexStrategy::Explicit, node_index: Some(5), ..AppConfig::default() };
+        assert_eq!(discover_index(&explicit).unwrap(), Some(5));
+
+        let missing = AppConfig { index_strategy: IndexStrategy::Explicit, node_index: None, ..AppConfig::default() };
+        assert!(discover_index(&missing).is_err());
+
+        let assigned = AppConfig { index_strategy: IndexStrategy::DataServer, ..AppConfig::default() };
+        assert_eq!(discover_index(&assigned).unwrap(), None);
+    }
+
+    #[test]
+    fn hmac_sha256_test() {
+        // RFC 4231 test case 2
+        assert_eq!(hmac_sha256_hex(b"Jefe", b"what do ya want for nothing?"),
+                   "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+        // 超过 64 字节的 key 先做一次哈希（test case 6）
+        assert_eq!(hmac_sha256_hex(&[0xaa; 131], b"Test Using Larger Than Block-Size Key - Hash Key First"),
+                   "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54");
+    }
+
+    #[test]
+    fn sha256_file_test() {
+        let temp = tempdir().unwrap();
+        let path = temp.path().join("abc.txt");
+        std::fs::write(&path, "abc").unwrap();
+        assert_eq!(sha256_file_hex(&path).unwrap(), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
     }
-    Ok(last_number - INDEX_OFFSET)
-}
\ No newline at end of file
+}
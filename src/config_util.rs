@@ -0,0 +1,593 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use crate::const_value::{CONFIG_PATH, DATA_SERVER_PORT, DATA_SERVER_RETRIES, DATA_SERVER_TIMEOUT, HEARTBEAT_INTERVAL, FRPC_ADMIN_ADDR, FRPC_BANDWIDTH_LIMIT, FRPC_ADMIN_PORT, FRPC_EXE_PATH, FRPC_TOML_PATH, FRPS_PORT,
+                         INDEX_OFFSET, LISTEN_ADDR, NET_INTERFACE_NAME, PRESETS_DIR, SEVENDAYS_INSTALLS_PATH,
+                         SEVENDAYS_SERVER_PATH, SEVENDAYS_STOP_TIME, STEAMCMD_PATH, TEMP_DIR,
+                         TELNET_LOCAL_PORT, TEMP_SEVENDAYS_SAVEFILE_ZIP_NAME, WEB_DASHBOARD_LOCAL_PORT,
+                         REMOTE_PORT_BASE, REMOTE_PORT_STRIDE, SERVER_PORT, PORT_LEASE_TTL, PORT_ALLOCATOR_MIN, PORT_ALLOCATOR_MAX,
+                         WEBHOOK_OUTBOX_PATH, WEBHOOK_MAX_ATTEMPTS, LOCAL_STORAGE_DIR, AUDIT_LOG_PATH};
+use crate::auth_util::{parse_api_token, AuthMode};
+use crate::common::IndexStrategy;
+use crate::data_server_util::DataServerAuth;
+use crate::frp_util::GAME_PORT_SLOTS;
+use crate::game_config_util::Violation;
+use crate::lease_util::PortLeaseMode;
+use crate::storage_util::StorageBackend;
+use crate::tunnel_util::TunnelProviderKind;
+
+pub const ENV_PREFIX: &str = "GAME_MASTER_";
+pub const CONFIG_PATH_ENV: &str = "GAME_MASTER_CONFIG";
+const REDACTED: &str = "******";
+// 兼容 script/start.sh 里已有的环境变量
+const LEGACY_ENV: [(&str, &str); 1] = [("DATA_SERVER_IP_ADDR", "data_server_addr")];
+
+// 叠加顺序（后者覆盖前者）：默认值 < TOML 文件 < 环境变量 GAME_MASTER_<FIELD> < 命令行 --<field>
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    pub listen_addr: String,
+
+    // 节点编号
+    pub index_strategy: IndexStrategy,
+    pub node_index: Option<u8>,
+    pub net_interface_name: String,
+    pub index_offset: u8,
+
+    // 游戏
+    pub sevendays_server_path: PathBuf,
+    pub sevendays_installs_path: PathBuf,
+    pub steamcmd_path: PathBuf,
+    pub presets_dir: PathBuf,
+    pub temp_dir: PathBuf,
+    pub sevendays_stop_time: u64,
+    // 为空时用安装目录里的 7DaysToDieServer.x86_64；测试时指向 folk_server
+    pub game_server_exe: PathBuf,
+    pub storage_backend: StorageBackend,
+    pub local_storage_dir: PathBuf,
+    // 写进 serverconfig.xml 的本地端口，隧道按这些端口转发
+    pub server_port: u16,
+    pub web_dashboard_local_port: u16,
+    pub telnet_local_port: u16,
+
+    // 隧道
+    pub tunnel_provider: TunnelProviderKind,
+    // direct 模式对外报告的地址，为空时用 net_interface_name 网卡的地址
+    pub public_host: String,
+
+    // frp
+    // 由 game_master 启动并监管 frpc；为 false 时沿用外部启动的 frpc，只做 reload
+    pub frpc_supervise: bool,
+    pub frpc_toml_path: PathBuf,
+    pub frpc_exe_path: PathBuf,
+    // frpc webServer（管理接口）
+    pub frpc_admin_addr: String,
+    pub frpc_admin_port: u16,
+    pub frpc_admin_user: String,
+    pub frpc_admin_password: String,
+    pub frps_addr: String,
+    pub frps_port: u16,
+    pub frps_token: String,
+    pub remote_port_base: u16,
+    pub remote_port_stride: u16,
+    pub frpc_bandwidth_limit: String,
+    pub frpc_expose_web_dashboard: bool,
+    pub frpc_expose_telnet: bool,
+
+    // 远端端口租约
+    pub port_lease_mode: PortLeaseMode,
+    // 为空时用 data server 的 /api/game_master
+    pub port_allocator_url: String,
+    pub port_lease_ttl: u64,
+    // 同一个 index 上跑多条隧道时用来区分，写进代理名和租约 holder
+    pub tunnel_name: String,
+    // 控制面模式：本进程提供 /port_leases 分配接口
+    pub port_allocator_enabled: bool,
+    pub port_allocator_min: u16,
+    pub port_allocator_max: u16,
+
+    // data server
+    // 完整地址，例如 https://data.example.com；为空时用 http://data_server_addr:data_server_port
+    pub data_server_url: String,
+    pub data_server_addr: String,
+    pub data_server_port: u16,
+    pub data_server_auth: DataServerAuth,
+    // bearer token 或 HMAC 密钥
+    pub data_server_secret: String,
+    // 单次请求超时（秒）
+    pub data_server_timeout: u64,
+    // 连接失败和 5xx 的重试次数
+    pub data_server_retries: u32,
+
+    // 节点注册和心跳（未配置 data server 时跳过）
+    pub node_registration: bool,
+    // 为空时用主机名
+    pub node_id: String,
+    pub heartbeat_interval: u64,
+
+    // webhook 事件推送（为空时不推送）
+    pub webhook_urls: Vec<String>,
+    // HMAC-SHA256 签名密钥
+    pub webhook_secret: String,
+    // 未送达的事件，重启后继续投递
+    pub webhook_outbox_path: PathBuf,
+    pub webhook_max_attempts: u32,
+
+    // HTTP 接口认证
+    pub auth_mode: AuthMode,
+    // "<name>:<token>:<scope>+<scope>"，scope 为 read/control/tunnel/console 或 *；
+    // 其它节点来申请端口租约时用的是它们的 data_server_secret（bearer），需要在这里配一个 tunnel scope 的 token
+    pub api_tokens: Vec<String>,
+    // 和 data server 共享的 HS256 密钥
+    pub jwt_secret: String,
+    // 被拒绝的请求和非只读操作
+    pub audit_log_path: PathBuf,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            listen_addr: LISTEN_ADDR.to_string(),
+            index_strategy: IndexStrategy::IpOctet,
+            node_index: None,
+            net_interface_name: NET_INTERFACE_NAME.to_string(),
+            index_offset: INDEX_OFFSET,
+            sevendays_server_path: PathBuf::from(SEVENDAYS_SERVER_PATH),
+            sevendays_installs_path: PathBuf::from(SEVENDAYS_INSTALLS_PATH),
+            steamcmd_path: PathBuf::from(STEAMCMD_PATH),
+            presets_dir: PathBuf::from(PRESETS_DIR),
+            temp_dir: PathBuf::from(TEMP_DIR),
+            sevendays_stop_time: SEVENDAYS_STOP_TIME,
+            game_server_exe: PathBuf::new(),
+            storage_backend: StorageBackend::S3,
+            local_storage_dir: PathBuf::from(LOCAL_STORAGE_DIR),
+            server_port: SERVER_PORT,
+            web_dashboard_local_port: WEB_DASHBOARD_LOCAL_PORT,
+            telnet_local_port: TELNET_LOCAL_PORT,
+            tunnel_provider: TunnelProviderKind::Frp,
+            public_host: String::new(),
+            frpc_supervise: true,
+            frpc_toml_path: PathBuf::from(FRPC_TOML_PATH),
+            frpc_exe_path: PathBuf::from(FRPC_EXE_PATH),
+            frpc_admin_addr: FRPC_ADMIN_ADDR.to_string(),
+            frpc_admin_port: FRPC_ADMIN_PORT,
+            frpc_admin_user: String::new(),
+            frpc_admin_password: String::new(),
+            frps_addr: String::new(),
+            frps_port: FRPS_PORT,
+            frps_token: String::new(),
+            remote_port_base: REMOTE_PORT_BASE,
+            remote_port_stride: REMOTE_PORT_STRIDE,
+            frpc_bandwidth_limit: FRPC_BANDWIDTH_LIMIT.to_string(),
+            frpc_expose_web_dashboard: false,
+            frpc_expose_telnet: false,
+            port_lease_mode: PortLeaseMode::Static,
+            port_allocator_url: String::new(),
+            port_lease_ttl: PORT_LEASE_TTL,
+            tunnel_name: String::new(),
+            port_allocator_enabled: false,
+            port_allocator_min: PORT_ALLOCATOR_MIN,
+            port_allocator_max: PORT_ALLOCATOR_MAX,
+            data_server_url: String::new(),
+            data_server_addr: String::new(),
+            data_server_port: DATA_SERVER_PORT,
+            data_server_auth: DataServerAuth::None,
+            data_server_secret: String::new(),
+            data_server_timeout: DATA_SERVER_TIMEOUT,
+            data_server_retries: DATA_SERVER_RETRIES,
+            node_registration: true,
+            node_id: String::new(),
+            heartbeat_interval: HEARTBEAT_INTERVAL,
+            webhook_urls: Vec::new(),
+            webhook_secret: String::new(),
+            webhook_outbox_path: PathBuf::from(WEBHOOK_OUTBOX_PATH),
+            webhook_max_attempts: WEBHOOK_MAX_ATTEMPTS,
+            auth_mode: AuthMode::None,
+            api_tokens: Vec::new(),
+            jwt_secret: String::new(),
+            audit_log_path: PathBuf::from(AUDIT_LOG_PATH),
+        }
+    }
+}
+
+impl AppConfig {
+    pub fn savefile_zip_path(&self) -> PathBuf {
+        self.temp_dir.join(TEMP_SEVENDAYS_SAVEFILE_ZIP_NAME)
+    }
+
+    pub fn data_server_base_url(&self) -> anyhow::Result<String> {
+        if !self.data_server_url.is_empty() {
+            return Ok(self.data_server_url.trim_end_matches('/').to_string());
+        }
+        if self.data_server_addr.is_empty() {
+            bail!("data_server_addr is not configured");
+        }
+        Ok(format!("http://{}:{}", self.data_server_addr, self.data_server_port))
+    }
+
+    pub fn port_allocator_base_url(&self) -> anyhow::Result<String> {
+        if !self.port_allocator_url.is_empty() {
+            return Ok(self.port_allocator_url.trim_end_matches('/').to_string());
+        }
+        Ok(format!("{}/api/game_master", self.data_server_base_url()?))
+    }
+
+    // 代理名和租约 holder 用的隧道标识
+    pub fn tunnel_label(&self, index: u8) -> String {
+        if self.tunnel_name.is_empty() {
+            index.to_string()
+        } else {
+            format!("{}-{}", index, self.tunnel_name)
+        }
+    }
+
+    pub fn redacted(&self) -> AppConfig {
+        let mut config = self.clone();
+        for secret in [&mut config.frps_token, &mut config.frpc_admin_password, &mut config.data_server_secret,
+                       &mut config.webhook_secret, &mut config.jwt_secret] {
+            if !secret.is_empty() {
+                *secret = REDACTED.to_string();
+            }
+        }
+        for token in config.api_tokens.iter_mut() {
+            if let Ok(parsed) = parse_api_token(token) {
+                *token = token.replacen(&format!(":{}:", parsed.token), &format!(":{}:", REDACTED), 1);
+            } else {
+                *token = REDACTED.to_string();
+            }
+        }
+        config
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<Violation>> {
+        let mut violations = Vec::new();
+        let mut push = |field: &str, message: String| violations.push(Violation { field: field.to_string(), message });
+
+        if self.listen_addr.parse::<SocketAddr>().is_err() {
+            push("listen_addr", format!("{:?} is not a socket address", self.listen_addr));
+        }
+        if self.index_strategy == IndexStrategy::Explicit && self.node_index.is_none() {
+            push("node_index", "required when index_strategy is explicit".to_string());
+        }
+        if self.net_interface_name.is_empty() {
+            push("net_interface_name", "must not be empty".to_string());
+        }
+        let paths = [
+            ("sevendays_server_path", &self.sevendays_server_path),
+            ("sevendays_installs_path", &self.sevendays_installs_path),
+            ("steamcmd_path", &self.steamcmd_path),
+            ("presets_dir", &self.presets_dir),
+            ("temp_dir", &self.temp_dir),
+            ("frpc_toml_path", &self.frpc_toml_path),
+            ("frpc_exe_path", &self.frpc_exe_path),
+            ("webhook_outbox_path", &self.webhook_outbox_path),
+            ("local_storage_dir", &self.local_storage_dir),
+            ("audit_log_path", &self.audit_log_path),
+        ];
+        for (field, path) in paths {
+            if !path.is_absolute() {
+                push(field, format!("{:?} is not an absolute path", path));
+            }
+        }
+        if !self.game_server_exe.as_os_str().is_empty() && !self.game_server_exe.is_absolute() {
+            push("game_server_exe", format!("{:?} is not an absolute path", self.game_server_exe));
+        }
+        for (field, port) in [("server_port", self.server_port), ("remote_port_base", self.remote_port_base),
+                              ("web_dashboard_local_port", self.web_dashboard_local_port), ("telnet_local_port", self.telnet_local_port),
+                              ("frps_port", self.frps_port), ("frpc_admin_port", self.frpc_admin_port),
+                              ("data_server_port", self.data_server_port)] {
+            if port == 0 {
+                push(field, "port must not be 0".to_string());
+            }
+        }
+        if self.server_port > u16::MAX - 3 {
+            push("server_port", "server_port..=server_port+3 must fit in 65535".to_string());
+        }
+        for (field, port) in [("web_dashboard_local_port", self.web_dashboard_local_port), ("telnet_local_port", self.telnet_local_port)] {
+            if (self.server_port..=self.server_port.saturating_add(3)).contains(&port) {
+                push(field, format!("{} overlaps the game ports {}..={}", port, self.server_port, self.server_port.saturating_add(3)));
+            }
+        }
+        if self.web_dashboard_local_port == self.telnet_local_port {
+            push("telnet_local_port", "must differ from web_dashboard_local_port".to_string());
+        }
+        if self.remote_port_stride < GAME_PORT_SLOTS {
+            push("remote_port_stride", format!("must be at least {}", GAME_PORT_SLOTS));
+        }
+        // index 最大 255
+        if self.remote_port_base as u32 + 256 * self.remote_port_stride as u32 > u16::MAX as u32 + 1 {
+            push("remote_port_base", "remote ports for index 255 do not fit in 65535".to_string());
+        }
+        // 没有内置的 frps，用 frp 隧道时必须显式配置
+        if self.tunnel_provider == TunnelProviderKind::Frp {
+            if self.frps_addr.is_empty() {
+                push("frps_addr", "required when tunnel_provider is frp".to_string());
+            }
+            if self.frps_token.is_empty() {
+                push("frps_token", "required when tunnel_provider is frp".to_string());
+            }
+        }
+        if self.port_lease_mode == PortLeaseMode::Remote && self.port_allocator_url.is_empty() && self.data_server_base_url().is_err() {
+            push("port_allocator_url", "required when port_lease_mode is remote and no data server is configured".to_string());
+        }
+        if self.tunnel_provider == TunnelProviderKind::Direct && self.port_lease_mode == PortLeaseMode::Remote {
+            push("port_lease_mode", "remote port leases need the frp tunnel provider".to_string());
+        }
+        // 每 ttl/3 续约一次
+        if self.port_lease_ttl < 10 {
+            push("port_lease_ttl", "must be at least 10 seconds".to_string());
+        }
+        if !self.tunnel_name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            push("tunnel_name", format!("{:?} may only contain letters, digits, '-' and '_'", self.tunnel_name));
+        }
+        if self.port_allocator_enabled
+            && (self.port_allocator_min == 0 || self.port_allocator_max < self.port_allocator_min
+                || self.port_allocator_max - self.port_allocator_min + 1 < GAME_PORT_SLOTS) {
+            push("port_allocator_max", format!("port_allocator_min..=port_allocator_max must hold at least {} ports", GAME_PORT_SLOTS));
+        }
+
+        if !self.data_server_url.is_empty() && !self.data_server_url.starts_with("http://") && !self.data_server_url.starts_with("https://") {
+            push("data_server_url", format!("{:?} must start with http:// or https://", self.data_server_url));
+        }
+        if self.data_server_auth != DataServerAuth::None && self.data_server_secret.is_empty() {
+            push("data_server_secret", format!("required when data_server_auth is {:?}", self.data_server_auth));
+        }
+        if self.data_server_timeout == 0 {
+            push("data_server_timeout", "must be at least 1 second".to_string());
+        }
+        if !self.node_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.') {
+            push("node_id", format!("{:?} may only contain letters, digits, '-', '_' and '.'", self.node_id));
+        }
+        if self.heartbeat_interval == 0 {
+            push("heartbeat_interval", "must be at least 1 second".to_string());
+        }
+        for url in self.webhook_urls.iter().filter(|u| !u.starts_with("http://") && !u.starts_with("https://")) {
+            push("webhook_urls", format!("{:?} must start with http:// or https://", url));
+        }
+        if !self.webhook_urls.is_empty() && self.webhook_secret.is_empty() {
+            push("webhook_secret", "required when webhook_urls is set".to_string());
+        }
+        if self.webhook_max_attempts == 0 {
+            push("webhook_max_attempts", "must be at least 1".to_string());
+        }
+        for token in &self.api_tokens {
+            if let Err(e) = parse_api_token(token) {
+                push("api_tokens", e.to_string());
+            }
+        }
+        if self.auth_mode == AuthMode::Token && self.api_tokens.is_empty() {
+            push("api_tokens", "required when auth_mode is token".to_string());
+        }
+        if self.auth_mode == AuthMode::Jwt && self.jwt_secret.is_empty() {
+            push("jwt_secret", "required when auth_mode is jwt".to_string());
+        }
+        // 不带认证时只允许监听本机
+        if self.auth_mode == AuthMode::None && let Ok(addr) = self.listen_addr.parse::<SocketAddr>() && !addr.ip().is_loopback() {
+            push("auth_mode", format!("must not be none when listening on {}; configure auth or listen on 127.0.0.1", addr));
+        }
+
+        if violations.is_empty() { Ok(()) } else { Err(violations) }
+    }
+}
+
+// 环境变量和命令行只有字符串，按默认值的类型转换
+fn coerce(default: &Value, field: &str, raw: &str) -> anyhow::Result<Value> {
+    match default {
+        Value::Number(_) => raw.parse::<u64>()
+            .map(Value::from)
+            .map_err(|_| anyhow!("{}: {:?} is not a number", field, raw)),
+        Value::Bool(_) => raw.parse::<bool>()
+            .map(Value::from)
+            .map_err(|_| anyhow!("{}: {:?} is not a bool", field, raw)),
+        // 逗号分隔的列表
+        Value::Array(_) => Ok(Value::Array(raw.split(',').map(str::trim).filter(|s| !s.is_empty()).map(Value::from).collect())),
+        // 默认为空的可选项，数字按数字处理
+        Value::Null => Ok(raw.parse::<u64>().map(Value::from).unwrap_or_else(|_| Value::String(raw.to_string()))),
+        _ => Ok(Value::String(raw.to_string())),
+    }
+}
+
+// 支持 --listen-addr 0.0.0.0:3005 和 --listen-addr=0.0.0.0:3005
+fn parse_args(args: &[String]) -> anyhow::Result<Vec<(String, String)>> {
+    let mut flags = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            bail!("unexpected argument {:?}", arg);
+        };
+        let (name, value) = match flag.split_once('=') {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None => match iter.next() {
+                Some(value) => (flag.to_string(), value.clone()),
+                None => bail!("missing value for --{}", flag),
+            },
+        };
+        flags.push((name.replace('-', "_"), value));
+    }
+    Ok(flags)
+}
+
+// args 不含程序名；env 一般是 std::env::vars()
+pub fn load_config(args: &[String], env: &HashMap<String, String>) -> anyhow::Result<AppConfig> {
+    let flags = parse_args(args)?;
+    let defaults = match serde_json::to_value(AppConfig::default())? {
+        Value::Object(map) => map,
+        _ => unreachable!("AppConfig serializes to an object"),
+    };
+    let mut values: Map<String, Value> = defaults.clone();
+
+    // 配置文件：--config > GAME_MASTER_CONFIG > 默认路径（默认路径不存在时跳过）
+    let explicit_path = flags.iter().rev().find(|(name, _)| name == "config").map(|(_, v)| v.clone())
+        .or_else(|| env.get(CONFIG_PATH_ENV).cloned());
+    let path = explicit_path.clone().unwrap_or_else(|| CONFIG_PATH.to_string());
+    if explicit_path.is_some() || Path::new(&path).is_file() {
+        let contents = std::fs::read_to_string(&path).map_err(|e| anyhow!("read config {}: {}", path, e))?;
+        let table: toml::Table = toml::from_str(&contents).map_err(|e| anyhow!("parse config {}: {}", path, e))?;
+        for (key, value) in table {
+            if !defaults.contains_key(&key) {
+                bail!("{}: unknown config key {:?}", path, key);
+            }
+            values.insert(key, serde_json::to_value(value)?);
+        }
+        println!("load config from {}", path);
+    }
+
+    for (legacy, field) in LEGACY_ENV {
+        if let Some(raw) = env.get(legacy) {
+            values.insert(field.to_string(), coerce(&defaults[field], field, raw)?);
+        }
+    }
+    for (field, default) in &defaults {
+        if let Some(raw) = env.get(&format!("{}{}", ENV_PREFIX, field.to_uppercase())) {
+            values.insert(field.clone(), coerce(default, field, raw)?);
+        }
+    }
+
+    for (name, raw) in flags {
+        if name == "config" {
+            continue;
+        }
+        match defaults.get(&name) {
+            Some(default) => {
+                values.insert(name.clone(), coerce(default, &name, &raw)?);
+            }
+            None => bail!("unknown flag --{}", name.replace('_', "-")),
+        }
+    }
+
+    Ok(serde_json::from_value(Value::Object(values))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+    use crate::auth_util::AuthMode;
+    use crate::common::IndexStrategy;
+    use crate::config_util::{load_config, AppConfig};
+    use crate::data_server_util::DataServerAuth;
+    use crate::lease_util::PortLeaseMode;
+    use crate::tunnel_util::TunnelProviderKind;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn layered_config_test() {
+        let temp = tempdir().unwrap();
+        let dir = temp.path();
+        let path = dir.join("game_master.toml");
+        std::fs::write(&path, "temp_dir = \"/data/temp\"\nfrps_port = 7100\nlisten_addr = \"127.0.0.1:4000\"\n").unwrap();
+
+        let env = HashMap::from([
+            ("GAME_MASTER_FRPS_PORT".to_string(), "7200".to_string()),
+            ("GAME_MASTER_INDEX_STRATEGY".to_string(), "explicit".to_string()),
+            ("GAME_MASTER_NODE_INDEX".to_string(), "4".to_string()),
+            ("GAME_MASTER_FRPC_SUPERVISE".to_string(), "false".to_string()),
+            ("GAME_MASTER_FRPS_ADDR".to_string(), "frps.example.com".to_string()),
+            ("GAME_MASTER_FRPS_TOKEN".to_string(), "secret".to_string()),
+            ("DATA_SERVER_IP_ADDR".to_string(), "192.168.8.88".to_string()),
+            ("GAME_MASTER_WEBHOOK_URLS".to_string(), "http://a.example.com/hook, https://b.example.com/hook".to_string()),
+            ("GAME_MASTER_WEBHOOK_SECRET".to_string(), "hook-secret".to_string()),
+            ("GAME_MASTER_AUTH_MODE".to_string(), "token".to_string()),
+            ("GAME_MASTER_API_TOKENS".to_string(), "panel:s3cret:read+control,ops:0ps:*".to_string()),
+        ]);
+        let config = load_config(&args(&["--config", path.to_str().unwrap(), "--listen-addr=0.0.0.0:5000"]), &env).unwrap();
+        assert_eq!(config.temp_dir, PathBuf::from("/data/temp"));
+        assert_eq!(config.frps_port, 7200);
+        assert_eq!(config.listen_addr, "0.0.0.0:5000");
+        assert_eq!(config.index_strategy, IndexStrategy::Explicit);
+        assert_eq!(config.node_index, Some(4));
+        assert!(!config.frpc_supervise);
+        assert_eq!(config.data_server_base_url().unwrap(), "http://192.168.8.88:3000");
+        assert_eq!(config.savefile_zip_path(), PathBuf::from("/data/temp/MyGame.zip"));
+        assert_eq!(config.redacted().frps_token, "******");
+        assert_eq!(config.webhook_urls, vec!["http://a.example.com/hook", "https://b.example.com/hook"]);
+        assert_eq!(config.redacted().webhook_secret, "******");
+        assert_eq!(config.auth_mode, AuthMode::Token);
+        assert_eq!(config.redacted().api_tokens, vec!["panel:******:read+control", "ops:******:*"]);
+        assert!(config.validate().is_ok());
+        assert_eq!(config.port_allocator_base_url().unwrap(), "http://192.168.8.88:3000/api/game_master");
+
+        let https = AppConfig { data_server_url: "https://data.example.com/".to_string(), ..config };
+        assert_eq!(https.data_server_base_url().unwrap(), "https://data.example.com");
+    }
+
+    // script/start.sh 用的示例配置必须能通过校验
+    #[test]
+    fn example_config_test() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/script/game_master.example.toml");
+        let config = load_config(&args(&["--config", path]), &HashMap::new()).unwrap();
+        assert_eq!(config.auth_mode, AuthMode::Token);
+        assert!(!config.frpc_expose_telnet);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn invalid_config_test() {
+        let env = HashMap::new();
+        assert!(load_config(&args(&["--no-such-flag", "1"]), &env).is_err());
+        assert!(load_config(&args(&["--frps-port", "abc"]), &env).is_err());
+        assert!(load_config(&args(&["--config", "/no/such/game_master.toml"]), &env).is_err());
+
+        let fields: Vec<String> = AppConfig::default().validate().unwrap_err().into_iter().map(|v| v.field).collect();
+        assert_eq!(fields, vec!["frps_addr", "frps_token", "auth_mode"]);
+
+        let base = AppConfig { listen_addr: "127.0.0.1:3005".to_string(), frps_addr: "frps.example.com".to_string(),
+                               frps_token: "secret".to_string(), ..AppConfig::default() };
+        assert!(base.validate().is_ok());
+        let config = AppConfig { listen_addr: "nope".to_string(), temp_dir: PathBuf::from("temp"), ..base.clone() };
+        let fields: Vec<String> = config.validate().unwrap_err().into_iter().map(|v| v.field).collect();
+        assert_eq!(fields, vec!["listen_addr", "temp_dir"]);
+
+        let config = AppConfig { game_server_exe: PathBuf::from("folk_server"), local_storage_dir: PathBuf::from("storage"), ..base.clone() };
+        let fields: Vec<String> = config.validate().unwrap_err().into_iter().map(|v| v.field).collect();
+        assert_eq!(fields, vec!["local_storage_dir", "game_server_exe"]);
+
+        let config = AppConfig { telnet_local_port: 26902, remote_port_stride: 4, remote_port_base: 65000, ..base.clone() };
+        let fields: Vec<String> = config.validate().unwrap_err().into_iter().map(|v| v.field).collect();
+        assert_eq!(fields, vec!["telnet_local_port", "remote_port_stride", "remote_port_base"]);
+
+        let config = AppConfig { port_lease_mode: PortLeaseMode::Remote, tunnel_name: "a b".to_string(), port_allocator_enabled: true,
+                                 port_allocator_min: 30000, port_allocator_max: 30002, ..base.clone() };
+        let fields: Vec<String> = config.validate().unwrap_err().into_iter().map(|v| v.field).collect();
+        assert_eq!(fields, vec!["port_allocator_url", "tunnel_name", "port_allocator_max"]);
+
+        let config = AppConfig { tunnel_provider: TunnelProviderKind::Direct, port_lease_mode: PortLeaseMode::Remote,
+                                 port_allocator_url: "http://127.0.0.1:3005".to_string(), ..base.clone() };
+        let fields: Vec<String> = config.validate().unwrap_err().into_iter().map(|v| v.field).collect();
+        assert_eq!(fields, vec!["port_lease_mode"]);
+
+        let config = AppConfig { data_server_url: "ftp://data".to_string(), data_server_auth: DataServerAuth::Hmac, ..base.clone() };
+        let fields: Vec<String> = config.validate().unwrap_err().into_iter().map(|v| v.field).collect();
+        assert_eq!(fields, vec!["data_server_url", "data_server_secret"]);
+
+        let config = AppConfig { webhook_urls: vec!["hook.example.com".to_string()], webhook_outbox_path: PathBuf::from("outbox.json"),
+                                 ..base.clone() };
+        let fields: Vec<String> = config.validate().unwrap_err().into_iter().map(|v| v.field).collect();
+        assert_eq!(fields, vec!["webhook_outbox_path", "webhook_urls", "webhook_secret"]);
+
+        let config = AppConfig { auth_mode: AuthMode::Token, audit_log_path: PathBuf::from("audit.log"), ..base.clone() };
+        let fields: Vec<String> = config.validate().unwrap_err().into_iter().map(|v| v.field).collect();
+        assert_eq!(fields, vec!["audit_log_path", "api_tokens"]);
+
+        let config = AppConfig { auth_mode: AuthMode::Jwt, api_tokens: vec!["panel:s3cret:admin".to_string()], ..base.clone() };
+        let fields: Vec<String> = config.validate().unwrap_err().into_iter().map(|v| v.field).collect();
+        assert_eq!(fields, vec!["api_tokens", "jwt_secret"]);
+
+        // 对外监听必须开认证
+        let config = AppConfig { listen_addr: "0.0.0.0:3005".to_string(), ..base.clone() };
+        let fields: Vec<String> = config.validate().unwrap_err().into_iter().map(|v| v.field).collect();
+        assert_eq!(fields, vec!["auth_mode"]);
+        let config = AppConfig { listen_addr: "[::]:3005".to_string(), ..base.clone() };
+        assert!(config.validate().is_err());
+        let config = AppConfig { listen_addr: "0.0.0.0:3005".to_string(), auth_mode: AuthMode::Token,
+                                 api_tokens: vec!["panel:s3cret:*".to_string()], ..base.clone() };
+        assert!(config.validate().is_ok());
+    }
+}
@@ -1,16 +1,40 @@
+// 运行时配置（config_util::AppConfig）的默认值
+pub const CONFIG_PATH: &str = "/root/game_master/game_master.toml";
+pub const LISTEN_ADDR: &str = "0.0.0.0:3005";
 pub const FRPC_TOML_PATH: &str = "/root/frp/frp_0.65.0_linux_amd64/frpc.toml";
 pub const FRPC_EXE_PATH: &str = "/root/frp/frp_0.65.0_linux_amd64/frpc";
+pub const FRPC_ADMIN_ADDR: &str = "127.0.0.1";
+pub const FRPC_ADMIN_PORT: u16 = 7400;
+pub const FRPS_PORT: u16 = 7000;
 pub const SEVENDAYS_SERVER_PATH: &str = "/root/7DaysToDieServer";
-pub const SERVERCONFIG_XML_PATH: &str = "/root/7DaysToDieServer/serverconfig.xml";
-pub const SEVENDAYS_SERVER_SAVEFILE_PARENT_PATH: &str = "/root/7DaysToDieServer/Data/Saves/Navezgane";
-pub const SEVENDAYS_SERVER_SAVEFILE_PATH: &str = "/root/7DaysToDieServer/Data/Saves/Navezgane/MyGame";
-pub const SEVENDAYS_EXE_PATH: &str = "/root/7DaysToDieServer/7DaysToDieServer.x86_64";
-pub const SEVENDAYS_LOG_PATH: &str = "/root/7DaysToDieServer/output_log.txt";
+pub const SEVENDAYS_INSTALLS_PATH: &str = "/root/7DaysToDieServers";
 pub const NET_INTERFACE_NAME: &str = "eth0";
 pub const INDEX_OFFSET: u8 = 200;
-pub const TCP_LOCAL_PORT: u16 = 26900;
-pub const UDP_LOCAL_PORT: u16 = 26902;
+// 游戏 ServerPort，TCP 用这一个端口，UDP 用 ServerPort..=ServerPort+3
+pub const SERVER_PORT: u16 = 26900;
+// 节点 index 的远端端口从 REMOTE_PORT_BASE + index * REMOTE_PORT_STRIDE 开始
+pub const REMOTE_PORT_BASE: u16 = 26900;
+pub const REMOTE_PORT_STRIDE: u16 = 10;
+pub const WEB_DASHBOARD_LOCAL_PORT: u16 = 8080;
+pub const TELNET_LOCAL_PORT: u16 = 8081;
+// 远端端口租约（port_lease_mode = remote）和控制面分配器的端口范围
+pub const PORT_LEASE_TTL: u64 = 120;
+pub const PORT_ALLOCATOR_MIN: u16 = 30000;
+pub const PORT_ALLOCATOR_MAX: u16 = 39999;
+pub const FRPC_BANDWIDTH_LIMIT: &str = "50KB";
 pub const DATA_SERVER_PORT: u16 = 3000;
+pub const DATA_SERVER_TIMEOUT: u64 = 10;
+pub const DATA_SERVER_RETRIES: u32 = 3;
+// 节点向 data server 发心跳的间隔（秒）
+pub const HEARTBEAT_INTERVAL: u64 = 30;
+// webhook 事件投递
+pub const WEBHOOK_OUTBOX_PATH: &str = "/root/game_master/webhook_outbox.json";
+pub const WEBHOOK_MAX_ATTEMPTS: u32 = 20;
+// storage_backend = local 时存档和 mod 包的目录
+pub const LOCAL_STORAGE_DIR: &str = "/root/game_master/storage";
+pub const AUDIT_LOG_PATH: &str = "/root/game_master/audit.log";
+pub const STEAMCMD_PATH: &str = "/root/steamcmd/steamcmd.sh";
+pub const PRESETS_DIR: &str = "/root/game_master/presets";
 pub const TEMP_DIR: &str = "/temp";
-pub const TEMP_SEVENDAYS_SAVEFILE_ZIP: &str = "/temp/MyGame.zip";
+pub const TEMP_SEVENDAYS_SAVEFILE_ZIP_NAME: &str = "MyGame.zip";
 pub const SEVENDAYS_STOP_TIME: u64 = 2;
@@ -1,25 +1,206 @@
-use crate::game_config_util::{ServerSettings};
-use std::env;
+use std::fmt;
+use std::time::Duration;
+use reqwest::{Client, Method, Request, Response, StatusCode};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use crate::const_value::DATA_SERVER_PORT;
-
-pub async fn get_game_config_by_serverconfig_id(serverconfig_id: i32) -> anyhow::Result<ServerSettings> {
-    let data_server_ip_address = env::var("DATA_SERVER_IP_ADDR")?;
-    let url = format!("http://{}:{}/api/game_master/game_config?serverconfig_id={}",
-        data_server_ip_address, DATA_SERVER_PORT, serverconfig_id,);
-    println!("get_game_config_by_serverconfig_id url: {}", url);
-    let response = reqwest::get(url).await?;
-
-    if response.status().is_success() {
-        let dog_api_response = response.json::<ServerSettings>().await?;
-        println!("get serverconfig: {:?}", dog_api_response);
-        Ok(dog_api_response)
-    } else {
-        println!("请求失败，状态码: {}", response.status());
-        Err(anyhow::anyhow!("请求失败，状态码: {}", response.status()))
+use serde_json::{Map, Value};
+use tokio::time::sleep;
+use crate::common::hmac_sha256_hex;
+use crate::config_util::AppConfig;
+use crate::frpc_supervisor_util::backoff_delay;
+use crate::lease_util::now_secs;
+use crate::savefile_util::GameTime;
+
+const RETRY_BACKOFF: Duration = Duration::from_millis(200);
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+pub const TIMESTAMP_HEADER: &str = "x-game-master-timestamp";
+pub const SIGNATURE_HEADER: &str = "x-game-master-signature";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DataServerAuth {
+    None,
+    // Authorization: Bearer <secret>
+    Bearer,
+    // 用 secret 对 "<timestamp>\n<METHOD>\n<path?query>\n<body>" 做 HMAC-SHA256，
+    // 放在 x-game-master-timestamp / x-game-master-signature 头里
+    Hmac,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataServerError {
+    // 没有配置 data_server_url / data_server_addr
+    NotConfigured,
+    NotFound(String),
+    // 401/403，凭据有误
+    Unauthorized(String),
+    // 连接失败、超时，或重试后仍是 5xx
+    Unavailable(String),
+    // 其它 4xx，或响应无法解析
+    BadResponse(String),
+}
+
+impl fmt::Display for DataServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataServerError::NotConfigured => write!(f, "data server is not configured"),
+            DataServerError::NotFound(msg) => write!(f, "not found on data server: {}", msg),
+            DataServerError::Unauthorized(msg) => write!(f, "data server rejected credentials: {}", msg),
+            DataServerError::Unavailable(msg) => write!(f, "data server unavailable: {}", msg),
+            DataServerError::BadResponse(msg) => write!(f, "bad data server response: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DataServerError {}
+
+pub fn sign_request(secret: &str, timestamp: u64, method: &str, path_and_query: &str, body: &[u8]) -> String {
+    let mut message = format!("{}\n{}\n{}\n", timestamp, method, path_and_query).into_bytes();
+    message.extend_from_slice(body);
+    hmac_sha256_hex(secret.as_bytes(), &message)
+}
+
+pub struct DataServerClient {
+    base_url: String,
+    auth: DataServerAuth,
+    secret: String,
+    retries: u32,
+    client: Client,
+}
+
+impl DataServerClient {
+    pub fn new(base_url: &str, auth: DataServerAuth, secret: &str, timeout: Duration, retries: u32) -> anyhow::Result<Self> {
+        Ok(DataServerClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            auth,
+            secret: secret.to_string(),
+            retries,
+            client: Client::builder().timeout(timeout).build()?,
+        })
+    }
+
+    pub fn from_config(config: &AppConfig) -> Result<Self, DataServerError> {
+        let base_url = config.data_server_base_url().map_err(|_| DataServerError::NotConfigured)?;
+        DataServerClient::with_base_url(config, &base_url)
+    }
+
+    // 用 data server 的认证设置访问别的地址，例如端口分配器
+    pub fn with_base_url(config: &AppConfig, base_url: &str) -> Result<Self, DataServerError> {
+        DataServerClient::new(base_url, config.data_server_auth, &config.data_server_secret,
+                              Duration::from_secs(config.data_server_timeout), config.data_server_retries)
+            .map_err(|e| DataServerError::Unavailable(e.to_string()))
+    }
+
+    fn authorize(&self, request: &mut Request) {
+        let value = match self.auth {
+            DataServerAuth::None => return,
+            DataServerAuth::Bearer => format!("Bearer {}", self.secret),
+            DataServerAuth::Hmac => {
+                let timestamp = now_secs();
+                let url = request.url();
+                let path_and_query = match url.query() {
+                    Some(query) => format!("{}?{}", url.path(), query),
+                    None => url.path().to_string(),
+                };
+                let body = request.body().and_then(|b| b.as_bytes()).unwrap_or_default();
+                let signature = sign_request(&self.secret, timestamp, request.method().as_str(), &path_and_query, body);
+                let headers = request.headers_mut();
+                headers.insert(TIMESTAMP_HEADER, timestamp.into());
+                if let Ok(signature) = signature.parse() {
+                    headers.insert(SIGNATURE_HEADER, signature);
+                }
+                return;
+            }
+        };
+        if let Ok(value) = value.parse() {
+            request.headers_mut().insert(reqwest::header::AUTHORIZATION, value);
+        }
+    }
+
+    // 连接失败、超时和 5xx 按退避时间重试，其它错误直接返回
+    pub async fn send(&self, method: Method, path: &str, query: &[(&str, String)], body: Option<&Value>) -> Result<Response, DataServerError> {
+        let url = format!("{}{}", self.base_url, path);
+        let mut attempt = 0;
+        loop {
+            let mut builder = self.client.request(method.clone(), &url).query(query);
+            if let Some(body) = body {
+                builder = builder.json(body);
+            }
+            let mut request = builder.build().map_err(|e| DataServerError::BadResponse(e.to_string()))?;
+            self.authorize(&mut request);
+
+            let outcome = self.client.execute(request).await;
+            let retryable = match &outcome {
+                Ok(response) => response.status().is_server_error(),
+                Err(_) => true,
+            };
+            if !retryable || attempt >= self.retries {
+                return match outcome {
+                    Err(e) => Err(DataServerError::Unavailable(format!("{} {}: {}", method, path, e))),
+                    Ok(response) => classify(response, &method, path).await,
+                };
+            }
+            let delay = backoff_delay(attempt, RETRY_BACKOFF, MAX_RETRY_BACKOFF);
+            println!("data server {} {} failed, retry in {:?}", method, path, delay);
+            attempt += 1;
+            sleep(delay).await;
+        }
+    }
+
+    pub async fn get_json<T: DeserializeOwned>(&self, path: &str, query: &[(&str, String)]) -> Result<T, DataServerError> {
+        let response = self.send(Method::GET, path, query, None).await?;
+        response.json::<T>().await.map_err(|e| DataServerError::BadResponse(format!("{}: {}", path, e)))
+    }
+
+    // 返回原始字段，由 preset_util 和预设、请求覆盖项叠加
+    pub async fn game_config(&self, serverconfig_id: i32) -> Result<Map<String, Value>, DataServerError> {
+        println!("get game_config serverconfig_id: {}", serverconfig_id);
+        let config = self.get_json("/api/game_master/game_config", &[("serverconfig_id", serverconfig_id.to_string())]).await?;
+        println!("get serverconfig: {:?}", config);
+        Ok(config)
+    }
+
+    pub async fn preset(&self, name: &str) -> Result<Option<Map<String, Value>>, DataServerError> {
+        match self.get_json("/api/game_master/preset", &[("name", name.to_string())]).await {
+            Ok(preset) => {
+                println!("get preset {}: {:?}", name, preset);
+                Ok(Some(preset))
+            }
+            Err(DataServerError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn savefile_info(&self, save_file_id: i32) -> Result<SaveFileInfo, DataServerError> {
+        let info = self.get_json("/api/game_master/download_savefile", &[("save_file_id", save_file_id.to_string())]).await?;
+        println!("get savefile info: {:?}", info);
+        Ok(info)
+    }
+
+    pub async fn report_savefile_upload(&self, report: &SaveUploadReport) -> Result<(), DataServerError> {
+        let body = serde_json::to_value(report).map_err(|e| DataServerError::BadResponse(e.to_string()))?;
+        self.send(Method::POST, "/api/game_master/savefile_uploaded", &[], Some(&body)).await?;
+        println!("report savefile {} uploaded to {}", report.save_file_id, report.object_key);
+        Ok(())
     }
 }
 
+async fn classify(response: Response, method: &Method, path: &str) -> Result<Response, DataServerError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    let message = format!("{} {} returned {}: {}", method, path, status, body.trim());
+    println!("请求失败，{}", message);
+    Err(match status {
+        StatusCode::NOT_FOUND => DataServerError::NotFound(message),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => DataServerError::Unauthorized(message),
+        _ if status.is_server_error() => DataServerError::Unavailable(message),
+        _ => DataServerError::BadResponse(message),
+    })
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct  SaveFileInfo {
     pub id: i32,
@@ -30,32 +211,47 @@ pub struct  SaveFileInfo {
     pub createdAt: String,
     pub updatedAt: String
 }
-pub async fn get_savefile_info_by_save_file_id(save_file_id: i32) -> anyhow::Result<SaveFileInfo> {
-    let data_server_ip_address = env::var("DATA_SERVER_IP_ADDR")?;
-    let url = format!("http://{}:{}/api/game_master/download_savefile?save_file_id={}",
-                      data_server_ip_address, DATA_SERVER_PORT, save_file_id);
-    println!("get_savefile_info_by_save_file_id url: {}", url);
-    let response = reqwest::get(url).await?;
-
-    if response.status().is_success() {
-        let dog_api_response = response.json::<SaveFileInfo>().await?;
-        println!("get savefile info: {:?}", dog_api_response);
-        Ok(dog_api_response)
-    } else {
-        println!("请求失败，状态码: {}", response.status());
-        Err(anyhow::anyhow!("请求失败，状态码: {}", response.status()))
-    }
+
+// stop_7days 上传存档后回报给 data server，用于更新存档列表
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SaveUploadReport {
+    pub save_file_id: i32,
+    pub bucket_name: String,
+    pub object_key: String,
+    pub size_bytes: u64,
+    // zip 文件的 SHA-256，小写十六进制
+    pub sha256: String,
+    // 停服前通过 telnet gettime 读取，telnet 不可用时为 None
+    pub game_time: Option<GameTime>,
+    pub game_version: Option<String>,
+    pub world: String,
+    pub game_name: String,
+    // unix 时间戳（秒）
+    pub uploaded_at: u64,
 }
 
 #[cfg(test)]
 mod tests {
-    use dotenv::dotenv;
-    use crate::data_server_util::{get_savefile_info_by_save_file_id, SaveFileInfo};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use axum::extract::Query;
+    use axum::http::{HeaderMap, StatusCode, Uri};
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use serde_json::json;
+    use tokio::sync::Mutex;
+    use crate::config_util::load_config;
+    use crate::data_server_util::{sign_request, DataServerAuth, DataServerClient, DataServerError, SaveFileInfo, SaveUploadReport,
+                                  SIGNATURE_HEADER, TIMESTAMP_HEADER};
+    use crate::savefile_util::GameTime;
     use crate::game_config_util::ServerSettings;
+    use crate::mock_data_server_util::{spawn, FIXTURES_DIR};
 
     #[tokio::test]
     async fn test_get_game_config_by_serverconfig_id() {
-        let url = "http://localhost:3000/api/game_master/game_config?serverconfig_id=1";
+        let mock = spawn(FIXTURES_DIR).await.unwrap();
+        let url = format!("{}/api/game_master/game_config?serverconfig_id=1", mock.base_url);
         let response = reqwest::get(url).await.unwrap();
 
         if response.status().is_success() {
@@ -68,7 +264,8 @@ mod tests {
 
     #[tokio::test]
     async fn retest_get_savefile_info_by_save_file_id() {
-        let url = "http://localhost:3000/api/game_master/download_savefile?save_file_id=1";
+        let mock = spawn(FIXTURES_DIR).await.unwrap();
+        let url = format!("{}/api/game_master/download_savefile?save_file_id=1", mock.base_url);
         let response = reqwest::get(url).await.unwrap();
 
         if response.status().is_success() {
@@ -81,7 +278,94 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_savefile_info_by_save_file_id() {
-        let _ = dotenv();
-        get_savefile_info_by_save_file_id(3).await.unwrap();
+        let mock = spawn(FIXTURES_DIR).await.unwrap();
+        let env = HashMap::from([("GAME_MASTER_DATA_SERVER_URL".to_string(), mock.base_url.clone())]);
+        let config = load_config(&[], &env).unwrap();
+        let client = DataServerClient::from_config(&config).unwrap();
+        assert_eq!(client.savefile_info(1).await.unwrap().bucket_name, "days7server");
+        assert!(matches!(client.savefile_info(404).await, Err(DataServerError::NotFound(_))));
+    }
+
+    // 模拟 data server：game_config 前两次返回 503，preset 只有 hardcore，校验 HMAC 签名
+    async fn stub_data_server() -> (String, Arc<Mutex<u32>>) {
+        let calls = Arc::new(Mutex::new(0));
+        let game_config_calls = calls.clone();
+        let verified = |headers: &HeaderMap, uri: &Uri| {
+            let timestamp = headers.get(TIMESTAMP_HEADER).and_then(|v| v.to_str().ok()).and_then(|v| v.parse::<u64>().ok());
+            let signature = headers.get(SIGNATURE_HEADER).and_then(|v| v.to_str().ok());
+            match (timestamp, signature) {
+                (Some(timestamp), Some(signature)) => signature == sign_request("secret", timestamp, "GET", &uri.to_string(), b""),
+                _ => false,
+            }
+        };
+        let app = Router::new()
+            .route("/api/game_master/game_config", get(move |headers: HeaderMap, uri: Uri| async move {
+                if !verified(&headers, &uri) {
+                    return Err(StatusCode::UNAUTHORIZED);
+                }
+                let mut calls = game_config_calls.lock().await;
+                *calls += 1;
+                if *calls <= 2 {
+                    return Err(StatusCode::SERVICE_UNAVAILABLE);
+                }
+                Ok(Json(json!({"server_name": "stub", "id": 1})))
+            }))
+            .route("/api/game_master/preset", get(|Query(params): Query<HashMap<String, String>>| async move {
+                match params.get("name").map(String::as_str) {
+                    Some("hardcore") => Ok(Json(json!({"game_difficulty": 5}))),
+                    _ => Err(StatusCode::NOT_FOUND),
+                }
+            }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}", addr), calls)
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn data_server_client_test() {
+        let (base_url, calls) = stub_data_server().await;
+        let timeout = Duration::from_secs(2);
+        let client = DataServerClient::new(&base_url, DataServerAuth::Hmac, "secret", timeout, 3).unwrap();
+
+        // 两次 503 之后成功
+        let config = client.game_config(1).await.unwrap();
+        assert_eq!(config["server_name"], "stub");
+        assert_eq!(*calls.lock().await, 3);
+
+        assert_eq!(client.preset("hardcore").await.unwrap().unwrap()["game_difficulty"], 5);
+        assert_eq!(client.preset("no such preset").await.unwrap(), None);
+
+        let wrong_secret = DataServerClient::new(&base_url, DataServerAuth::Hmac, "wrong", timeout, 3).unwrap();
+        assert!(matches!(wrong_secret.game_config(1).await, Err(DataServerError::Unauthorized(_))));
+
+        // 重试用完仍然 503
+        *calls.lock().await = 0;
+        let no_retry = DataServerClient::new(&base_url, DataServerAuth::Hmac, "secret", timeout, 0).unwrap();
+        assert!(matches!(no_retry.game_config(1).await, Err(DataServerError::Unavailable(_))));
+    }
+
+    #[tokio::test]
+    async fn report_savefile_upload_test() {
+        let mock = spawn(FIXTURES_DIR).await.unwrap();
+
+        let report = SaveUploadReport {
+            save_file_id: 3, bucket_name: "saves".to_string(), object_key: "user-1/Friends.zip".to_string(), size_bytes: 1024,
+            sha256: "ab".repeat(32), game_time: Some(GameTime { day: 12, hour: 13, minute: 45 }), game_version: Some("public/123".to_string()),
+            world: "Navezgane".to_string(), game_name: "Friends".to_string(), uploaded_at: 1_700_000_000,
+        };
+        let client = DataServerClient::new(&mock.base_url, DataServerAuth::None, "", Duration::from_secs(2), 0).unwrap();
+        client.report_savefile_upload(&report).await.unwrap();
+        let uploads = mock.uploads.lock().await;
+        assert_eq!(serde_json::from_value::<SaveUploadReport>(uploads[0].clone()).unwrap(), report);
+    }
+
+    #[tokio::test]
+    async fn data_server_unreachable_test() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let client = DataServerClient::new(&base_url, DataServerAuth::Bearer, "token", Duration::from_secs(1), 1).unwrap();
+        assert!(matches!(client.savefile_info(1).await, Err(DataServerError::Unavailable(_))));
+    }
+}
@@ -1,6 +1,10 @@
 use axum::http::StatusCode;
+use axum::Json;
 use axum::response::{IntoResponse, Response};
+use crate::data_server_util::DataServerError;
+use crate::game_config_util::{ServerConfigXmlError, Violation};
 
+#[derive(Debug)]
 pub enum AppError {
     // 专门用于处理读取配置文件失败的错误
     ConfigReadError(String),
@@ -12,13 +16,32 @@ pub enum AppError {
     DataServerFucError(String),
     SetServerConfigXmlErrror(String),
     StopProcessError(String),
-    GetS3ClientError(String),
     UnzipError(String),
     ZipError(String),
     DownloadError(String),
     UploadError(String),
     IOError(std::io::Error),
-    KillCommandError(String)
+    KillCommandError(String),
+    SavefileError(String),
+    InvalidServerSettings(Vec<Violation>),
+    PresetError(String),
+    InvalidParamError(String),
+    NotFoundError(String),
+    ServerAdminError(String),
+    ConsoleCommandError(String),
+    ModError(String),
+    GameIsInstalling,
+    InstallError(String),
+    TunnelError(String),
+    PortLeaseError(String),
+    // data server 连不上或重试后仍失败
+    DataServerUnavailable(String),
+    // data server 拒绝了凭据或返回了无法处理的响应
+    DataServerBadResponse(String),
+    // 没有凭据或凭据无效
+    Unauthorized(String),
+    // 凭据有效但缺少该路由需要的 scope
+    Forbidden(String)
 }
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
@@ -59,10 +82,6 @@ impl IntoResponse for AppError {
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("stop process error: {}", msg),
             ),
-            AppError::GetS3ClientError(msg) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("get s3 client error: {}", msg),
-            ),
             AppError::DownloadError(msg) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("download file error: {}", msg),
@@ -87,8 +106,90 @@ impl IntoResponse for AppError {
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("kill command error: {}", msg),
             ),
+            AppError::SavefileError(msg) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("savefile error: {}", msg),
+            ),
+            AppError::PresetError(msg) => (
+                StatusCode::BAD_REQUEST,
+                format!("preset error: {}", msg),
+            ),
+            AppError::InvalidParamError(msg) => (
+                StatusCode::BAD_REQUEST,
+                format!("invalid param: {}", msg),
+            ),
+            AppError::NotFoundError(msg) => (
+                StatusCode::NOT_FOUND,
+                format!("not found: {}", msg),
+            ),
+            AppError::ServerAdminError(msg) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("serveradmin.xml error: {}", msg),
+            ),
+            AppError::ConsoleCommandError(msg) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("console command error: {}", msg),
+            ),
+            AppError::ModError(msg) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("mod error: {}", msg),
+            ),
+            AppError::GameIsInstalling => (
+                StatusCode::CONFLICT,
+                "game is installing".to_string(),
+            ),
+            AppError::InstallError(msg) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("install error: {}", msg),
+            ),
+            AppError::TunnelError(msg) => (
+                StatusCode::BAD_GATEWAY,
+                format!("tunnel error: {}", msg),
+            ),
+            AppError::PortLeaseError(msg) => (
+                StatusCode::CONFLICT,
+                format!("port lease error: {}", msg),
+            ),
+            AppError::DataServerUnavailable(msg) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("data server unavailable: {}", msg),
+            ),
+            AppError::DataServerBadResponse(msg) => (
+                StatusCode::BAD_GATEWAY,
+                format!("data server error: {}", msg),
+            ),
+            AppError::Unauthorized(msg) => {
+                return (StatusCode::UNAUTHORIZED, [(axum::http::header::WWW_AUTHENTICATE, "Bearer")], format!("unauthorized: {}", msg)).into_response();
+            }
+            AppError::Forbidden(msg) => (
+                StatusCode::FORBIDDEN,
+                format!("forbidden: {}", msg),
+            ),
+            AppError::InvalidServerSettings(violations) => {
+                return (StatusCode::UNPROCESSABLE_ENTITY, Json(violations)).into_response();
+            }
         };
 
         (status, error_message).into_response()
     }
-}
\ No newline at end of file
+}
+
+impl From<DataServerError> for AppError {
+    fn from(error: DataServerError) -> Self {
+        match error {
+            DataServerError::NotConfigured => AppError::DataServerFucError(error.to_string()),
+            DataServerError::NotFound(msg) => AppError::NotFoundError(msg),
+            DataServerError::Unavailable(msg) => AppError::DataServerUnavailable(msg),
+            DataServerError::Unauthorized(_) | DataServerError::BadResponse(_) => AppError::DataServerBadResponse(error.to_string()),
+        }
+    }
+}
+
+impl From<ServerConfigXmlError> for AppError {
+    fn from(error: ServerConfigXmlError) -> Self {
+        match error {
+            ServerConfigXmlError::InvalidSettings(violations) => AppError::InvalidServerSettings(violations),
+            ServerConfigXmlError::Failed(msg) => AppError::SetServerConfigXmlErrror(msg),
+        }
+    }
+}
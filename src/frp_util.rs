@@ -1,8 +1,14 @@
-use std::process::ExitStatus;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
 use serde::{Deserialize, Serialize};
 use tokio::fs;
-use tokio::process::Command;
-use crate::const_value::{FRPC_EXE_PATH, FRPC_TOML_PATH, TCP_LOCAL_PORT, UDP_LOCAL_PORT};
+use crate::config_util::AppConfig;
+use crate::frpc_admin_util::FrpcAdminClient;
+use crate::game_config_util::{ServerSettings, Violation};
+
+const MANAGED_METADATA_KEY: &str = "game_master";
+// 每个节点占用的远端端口数，见 allocate_ports
+pub const GAME_PORT_SLOTS: u16 = 6;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct FrpcToml {
@@ -16,136 +22,391 @@ pub struct FrpcToml {
     pub bandwidthLimit: String
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct Auth {
-    token: String,
+// 顶层和每个子表中未建模的字段都原样保留在 extra 里，读改写不会丢配置
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Auth {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(default)]
+    pub token: String,
+    #[serde(flatten)]
+    pub extra: toml::Table,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct WebServer {
-    addr: String,
-    port: u16,
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WebServer {
+    #[serde(default)]
+    pub addr: String,
+    #[serde(default)]
+    pub port: u16,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub user: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub password: String,
+    #[serde(flatten)]
+    pub extra: toml::Table,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct Transport {
-    bandwidthLimit: String
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Transport {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth_limit: Option<String>,
+    // client / server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth_limit_mode: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub use_encryption: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub use_compression: Option<bool>,
+    #[serde(flatten)]
+    pub extra: toml::Table,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
-struct Proxy {
-    name: String,
+pub struct HealthCheck {
+    // tcp / http
     #[serde(rename = "type")]
-    proxy_type: String,
-    #[serde(rename = "localIP")]
-    local_ip: String,
-    local_port: u16,
-    remote_port: u16,
-    transport: Transport
+    pub check_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_seconds: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_failed: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_seconds: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(flatten)]
+    pub extra: toml::Table,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyType {
+    Tcp,
+    Udp,
+    Http,
+    Https,
+    Stcp,
+    Xtcp,
+    // tcpmux、sudp 等，只做透传
+    #[serde(untagged)]
+    Other(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Proxy {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub proxy_type: ProxyType,
+    #[serde(rename = "localIP", default, skip_serializing_if = "Option::is_none")]
+    pub local_ip: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_port: Option<u16>,
+    // tcp / udp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_port: Option<u16>,
+    // http / https
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom_domains: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subdomain: Option<String>,
+    // stcp / xtcp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transport: Option<Transport>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_check: Option<HealthCheck>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadatas: Option<BTreeMap<String, String>>,
+    #[serde(flatten)]
+    pub extra: toml::Table,
+}
+
+impl Proxy {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("proxy name is empty".to_string());
+        }
+        let ok = match &self.proxy_type {
+            ProxyType::Tcp | ProxyType::Udp => self.local_port.is_some() && self.remote_port.is_some(),
+            ProxyType::Http | ProxyType::Https => self.local_port.is_some()
+                && (self.custom_domains.as_ref().is_some_and(|d| !d.is_empty()) || self.subdomain.is_some()),
+            ProxyType::Stcp | ProxyType::Xtcp => self.local_port.is_some() && self.secret_key.is_some(),
+            ProxyType::Other(_) => true,
+        };
+        if !ok {
+            return Err(format!("proxy {} is missing required fields for type {:?}", self.name, self.proxy_type));
+        }
+        Ok(())
+    }
+
+    // game_master 生成的代理带有 game_master = <role> 元数据
+    pub fn managed_role(&self) -> Option<&str> {
+        self.metadatas.as_ref()?.get(MANAGED_METADATA_KEY).map(String::as_str)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
-    server_addr: String,
-    server_port: u16,
-    auth: Auth,
-    web_server: WebServer,
-    proxies: Vec<Proxy>,
+    #[serde(default)]
+    pub server_addr: String,
+    #[serde(default)]
+    pub server_port: u16,
+    #[serde(default)]
+    pub auth: Auth,
+    #[serde(default)]
+    pub web_server: WebServer,
+    #[serde(default)]
+    pub proxies: Vec<Proxy>,
+    #[serde(flatten)]
+    pub extra: toml::Table,
 }
 
-pub async fn frpc_config_read(path: &str) -> Result<Config, Box<dyn std::error::Error>> {
-    let contents = fs::read_to_string(path).await?;
+impl Config {
+    // 按名字替换，否则追加
+    pub fn upsert_proxy(&mut self, proxy: Proxy) {
+        match self.proxies.iter_mut().find(|p| p.name == proxy.name) {
+            Some(existing) => *existing = proxy,
+            None => self.proxies.push(proxy),
+        }
+    }
 
-    let config: Config = toml::from_str(&contents)?;
+    pub fn remove_proxy(&mut self, name: &str) -> bool {
+        let before = self.proxies.len();
+        self.proxies.retain(|p| p.name != name);
+        self.proxies.len() != before
+    }
 
-    println!("read frpc.toml:\n{:#?}", config);
-    Ok(config)
+    // 替换同一 role 或同名的受管代理，不影响手动添加的代理
+    pub fn set_managed_proxy(&mut self, proxy: Proxy) {
+        let role = proxy.managed_role().map(str::to_string);
+        self.proxies.retain(|p| p.name != proxy.name && (role.is_none() || p.managed_role() != role.as_deref()));
+        self.proxies.push(proxy);
+    }
+
+    pub fn remove_managed_proxies(&mut self) {
+        self.proxies.retain(|p| p.managed_role().is_none());
+    }
+
+    fn apply_server(&mut self, app_config: &AppConfig) {
+        self.web_server.addr = app_config.frpc_admin_addr.clone();
+        self.web_server.port = app_config.frpc_admin_port;
+        self.web_server.user = app_config.frpc_admin_user.clone();
+        self.web_server.password = app_config.frpc_admin_password.clone();
+    }
 }
 
-pub async fn frpc_config_write(config: &FrpcToml, path: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let auth = Auth{ token: config.auth_token.clone() };
-    let server_addr = config.server_addr.clone();
-    let server_port = config.server_port;
-    let transport = Transport {
-        bandwidthLimit: config.bandwidthLimit.clone()
-    };
-    let tcp = Proxy {
-        name: config.tcp_name.clone(),
-        proxy_type: "tcp".to_string(),
-        local_port: TCP_LOCAL_PORT,
-        local_ip: "127.0.0.1".to_string(),
-        remote_port: config.tcp_remote_port,
-        transport: transport.clone()
-    };
-    let ucp = Proxy {
-        name: config.udp_name.clone(),
-        proxy_type: "udp".to_string(),
-        local_port: UDP_LOCAL_PORT,
-        local_ip: "127.0.0.1".to_string(),
-        remote_port: config.udp_remote_port,
-        transport
-    };
-    let web_server = WebServer {
-        addr: "127.0.0.1".to_string(),
-        port: 7400
-    };
-    let frpc_config = Config {
-        server_port,
-        server_addr,
-        auth,
-        web_server,
-        proxies: vec![tcp, ucp]
+pub fn managed_proxy(role: &str, name: String, proxy_type: ProxyType, local_port: u16, remote_port: u16, bandwidth_limit: &str) -> Proxy {
+    Proxy {
+        name,
+        proxy_type,
+        local_ip: Some("127.0.0.1".to_string()),
+        local_port: Some(local_port),
+        remote_port: Some(remote_port),
+        custom_domains: None,
+        subdomain: None,
+        secret_key: None,
+        transport: Some(Transport { bandwidth_limit: Some(bandwidth_limit.to_string()), ..Transport::default() }),
+        health_check: None,
+        metadatas: Some(BTreeMap::from([(MANAGED_METADATA_KEY.to_string(), role.to_string())])),
+        extra: toml::Table::new(),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PortMapping {
+    pub role: String,
+    pub name: String,
+    pub proxy_type: ProxyType,
+    pub local_port: u16,
+    pub remote_port: u16,
+}
+
+pub fn remote_block_start(app_config: &AppConfig, index: u8) -> u16 {
+    app_config.remote_port_base + index as u16 * app_config.remote_port_stride
+}
+
+// 每个 index 占一段远端端口 [base + index * stride, base + index * stride + GAME_PORT_SLOTS)
+pub fn allocate_ports(app_config: &AppConfig, index: u8) -> Vec<PortMapping> {
+    allocate_ports_at(app_config, &index.to_string(), remote_block_start(app_config, index))
+}
+
+// 从 remote 开始的一段远端端口，段内偏移和本地一致：P 为 TCP+UDP，P+1..=P+3 为 UDP，之后是 web dashboard 和 telnet；
+// tunnel 用于代理名，同一个 index 上的多条隧道靠它区分
+pub fn allocate_ports_at(app_config: &AppConfig, tunnel: &str, remote: u16) -> Vec<PortMapping> {
+    let local = app_config.server_port;
+    let mapping = |role: String, name: String, proxy_type: ProxyType, local_port: u16, remote_port: u16| {
+        PortMapping { role, name, proxy_type, local_port, remote_port }
     };
+    let mut ports = vec![
+        mapping("game_tcp".to_string(), format!("7daysTodieServer-{}", tunnel), ProxyType::Tcp, local, remote),
+        mapping("game_udp".to_string(), format!("7daysTodieServerUDP-{}", tunnel), ProxyType::Udp, local, remote),
+    ];
+    for offset in 1..=3 {
+        ports.push(mapping(format!("game_udp_{}", offset), format!("7daysTodieServerUDP{}-{}", local + offset, tunnel),
+                           ProxyType::Udp, local + offset, remote + offset));
+    }
+    if app_config.frpc_expose_web_dashboard {
+        ports.push(mapping("web_dashboard".to_string(), format!("7daysTodieDashboard-{}", tunnel), ProxyType::Tcp,
+                           app_config.web_dashboard_local_port, remote + 4));
+    }
+    if app_config.frpc_expose_telnet {
+        ports.push(mapping("telnet".to_string(), format!("7daysTodieTelnet-{}", tunnel), ProxyType::Tcp,
+                           app_config.telnet_local_port, remote + 5));
+    }
+    ports
+}
+
+fn proxies_for(app_config: &AppConfig, ports: &[PortMapping]) -> Vec<Proxy> {
+    ports.iter()
+        .map(|p| managed_proxy(&p.role, p.name.clone(), p.proxy_type.clone(), p.local_port, p.remote_port, &app_config.frpc_bandwidth_limit))
+        .collect()
+}
+
+pub fn game_proxies(app_config: &AppConfig, index: u8) -> Vec<Proxy> {
+    proxies_for(app_config, &allocate_ports(app_config, index))
+}
+
+// frps 上同一协议的远端端口只能被一个代理占用
+pub fn check_port_collisions(proxies: &[Proxy]) -> Result<(), String> {
+    let mut used: HashMap<(bool, u16), &str> = HashMap::new();
+    for proxy in proxies {
+        let udp = match proxy.proxy_type {
+            ProxyType::Tcp => false,
+            ProxyType::Udp => true,
+            _ => continue,
+        };
+        let Some(remote_port) = proxy.remote_port else {
+            continue;
+        };
+        if let Some(other) = used.insert((udp, remote_port), &proxy.name) {
+            return Err(format!("proxies {} and {} both use remote {} port {}",
+                               other, proxy.name, if udp { "udp" } else { "tcp" }, remote_port));
+        }
+    }
+    Ok(())
+}
 
-    let toml_string = toml::to_string_pretty(&frpc_config)?;
-    fs::write(path, toml_string).await?;
+// 隧道按节点配置的本地端口转发，serverconfig.xml 必须用同样的端口；返回被改动的字段
+pub fn apply_local_ports(settings: &mut ServerSettings, app_config: &AppConfig) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    for (field, value, port) in [
+        ("server_port", &mut settings.server_port, app_config.server_port),
+        ("web_dashboard_port", &mut settings.web_dashboard_port, app_config.web_dashboard_local_port),
+        ("telnet_port", &mut settings.telnet_port, app_config.telnet_local_port),
+    ] {
+        if *value != port as i32 {
+            *value = port as i32;
+            changed.push(field);
+        }
+    }
+    changed
+}
 
-    println!("sucess write frpc.toml to {}", path);
+// telnet 暴露到 frps 后谁都能连，游戏没设 telnet 密码时拒绝启动
+pub fn check_exposed_settings(settings: &ServerSettings, app_config: &AppConfig) -> Result<(), Vec<Violation>> {
+    if app_config.frpc_expose_telnet && settings.telnet_enabled && settings.telnet_password.is_empty() {
+        return Err(vec![Violation {
+            field: "telnet_password".to_string(),
+            message: "must not be empty when frpc_expose_telnet is on".to_string(),
+        }]);
+    }
+    Ok(())
+}
 
+// 文件不存在时从空配置开始
+pub async fn frpc_config_load(path: &Path) -> Result<Config, Box<dyn std::error::Error>> {
+    if !fs::try_exists(path).await? {
+        return Ok(Config::default());
+    }
+    Ok(toml::from_str(&fs::read_to_string(path).await?)?)
+}
+
+pub async fn frpc_config_save(config: &Config, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    fs::write(path, toml::to_string_pretty(config)?).await?;
+    println!("sucess write frpc.toml to {:?}", path);
     Ok(())
 }
 
-pub async fn frpc_config_reset_by_index(path: &str, index: u8) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn frpc_config_read(path: &Path) -> Result<Config, Box<dyn std::error::Error>> {
     let contents = fs::read_to_string(path).await?;
 
+    let config: Config = toml::from_str(&contents)?;
+
+    println!("read frpc.toml:\n{:#?}", config);
+    Ok(config)
+}
+
+// 启动时按端口映射（节点编号计算或租约分配）生成 frpc.toml，保留文件中手动添加的代理和未知字段
+pub async fn frpc_config_init(app_config: &AppConfig, ports: &[PortMapping]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = frpc_config_load(&app_config.frpc_toml_path).await?;
+    config.server_addr = app_config.frps_addr.clone();
+    config.server_port = app_config.frps_port;
+    config.auth.token = app_config.frps_token.clone();
+    config.apply_server(app_config);
+    config.remove_managed_proxies();
+    for proxy in proxies_for(app_config, ports) {
+        config.set_managed_proxy(proxy);
+    }
+    check_port_collisions(&config.proxies)?;
+    frpc_config_save(&config, &app_config.frpc_toml_path).await
+}
+
+pub async fn frpc_config_write(config: &FrpcToml, app_config: &AppConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let mut frpc_config = frpc_config_load(&app_config.frpc_toml_path).await?;
+    frpc_config.server_addr = config.server_addr.clone();
+    frpc_config.server_port = config.server_port;
+    frpc_config.auth.token = config.auth_token.clone();
+    frpc_config.apply_server(app_config);
+    frpc_config.set_managed_proxy(managed_proxy("game_tcp", config.tcp_name.clone(), ProxyType::Tcp,
+                                                app_config.server_port, config.tcp_remote_port, &config.bandwidthLimit));
+    frpc_config.set_managed_proxy(managed_proxy("game_udp", config.udp_name.clone(), ProxyType::Udp,
+                                                app_config.server_port, config.udp_remote_port, &config.bandwidthLimit));
+    check_port_collisions(&frpc_config.proxies)?;
+
+    frpc_config_save(&frpc_config, &app_config.frpc_toml_path).await
+}
+
+pub async fn frpc_config_reset_by_index(app_config: &AppConfig, index: u8) -> Result<(), Box<dyn std::error::Error>> {
+    let contents = fs::read_to_string(&app_config.frpc_toml_path).await?;
+
     let mut config: Config = toml::from_str(&contents)?;
-    for proxy in config.proxies.iter_mut() {
-        if proxy.proxy_type == "tcp" {
-            proxy.name = format!("7daysTodieServer-{}", index);
-            proxy.remote_port = TCP_LOCAL_PORT + index as u16;
-        } else if proxy.proxy_type == "udp" {
-            proxy.name = format!("7daysTodieServerUDP-{}", index);
-            proxy.remote_port = UDP_LOCAL_PORT + index as u16;
-        }
+    config.remove_managed_proxies();
+    for proxy in game_proxies(app_config, index) {
+        config.set_managed_proxy(proxy);
     }
+    check_port_collisions(&config.proxies)?;
 
-    let toml_string = toml::to_string_pretty(&config)?;
-    fs::write(path, toml_string).await?;
+    frpc_config_save(&config, &app_config.frpc_toml_path).await?;
 
-    frpc_config_reload().await?;
+    frpc_config_reload(app_config).await?;
 
     Ok(())
 }
 
-pub async fn frpc_config_reload() -> anyhow::Result<ExitStatus> {
-    let status = Command::new(FRPC_EXE_PATH)
-        .arg("reload")
-        .arg("-c")
-        .arg(FRPC_TOML_PATH)
-        .spawn()?
-        .wait().await.unwrap();
-
-    Ok(status)
+// 通过 frpc 管理接口重新加载 frpc.toml
+pub async fn frpc_config_reload(app_config: &AppConfig) -> anyhow::Result<()> {
+    FrpcAdminClient::from_config(app_config)?.reload().await
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::frp_util::{frpc_config_read, frpc_config_write, FrpcToml};
+    use tempfile::tempdir;
+    use crate::config_util::AppConfig;
+    use crate::frp_util::{allocate_ports, allocate_ports_at, apply_local_ports, check_exposed_settings, check_port_collisions, frpc_config_init, frpc_config_load, frpc_config_read,
+                          frpc_config_write, game_proxies, managed_proxy, proxies_for, FrpcToml, Proxy, ProxyType};
+    use crate::game_config_util::ServerSettings;
 
     #[tokio::test]
     async fn frpc_config_write_test() {
+        let temp = tempdir().unwrap();
         let config = FrpcToml {
             server_addr: "124.223.27.133".to_string(),
             server_port: 7000,
@@ -156,13 +417,154 @@ mod tests {
             udp_remote_port: 26902,
             bandwidthLimit: "1KB".to_string()
         };
-        let _ = frpc_config_write(&config, "C:\\Users\\89396\\projects\\game_master\\frpc.toml").await.unwrap();
+        let app_config = AppConfig {
+            frpc_toml_path: temp.path().join("frpc.toml"),
+            ..AppConfig::default()
+        };
+        frpc_config_write(&config, &app_config).await.unwrap();
 
-        ()
+        let written = frpc_config_load(&app_config.frpc_toml_path).await.unwrap();
+        assert_eq!(written.server_addr, "124.223.27.133");
+        assert_eq!(written.proxies.len(), 2);
+        assert_eq!(written.proxies[1].remote_port, Some(26902));
+        assert_eq!(written.proxies[1].local_port, Some(26900));
+        assert_eq!(written.proxies[1].transport.as_ref().unwrap().bandwidth_limit.as_deref(), Some("1KB"));
     }
 
     #[tokio::test]
     async fn frpc_config_read_test() {
-        let _ = frpc_config_read("C:\\Users\\89396\\Downloads\\frp_0.65.0_windows_amd64\\frp_0.65.0_windows_amd64\\frpc.toml").await.unwrap();
+        let temp = tempdir().unwrap();
+        let path = temp.path().join("frpc.toml");
+        std::fs::write(&path, r#"
+serverAddr = "124.223.27.133"
+serverPort = 7000
+
+[[proxies]]
+name = "7daysTodieServer-1"
+type = "tcp"
+localIP = "127.0.0.1"
+localPort = 26900
+remotePort = 26910
+"#).unwrap();
+        let config = frpc_config_read(&path).await.unwrap();
+        assert_eq!(config.server_addr, "124.223.27.133");
+        assert_eq!(config.proxies[0].remote_port, Some(26910));
+        assert!(frpc_config_read(&temp.path().join("missing.toml")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn unknown_fields_roundtrip_test() {
+        let temp = tempdir().unwrap();
+        let path = temp.path().join("frpc.toml");
+        std::fs::write(&path, r#"
+serverAddr = "1.2.3.4"
+serverPort = 7000
+loginFailExit = false
+
+[auth]
+token = "old"
+
+[log]
+to = "console"
+level = "debug"
+
+[[proxies]]
+name = "my-ssh"
+type = "stcp"
+localPort = 22
+secretKey = "abc"
+annotations = { owner = "ops" }
+
+[proxies.transport]
+useEncryption = true
+proxyProtocolVersion = "v2"
+
+[[proxies]]
+name = "mux"
+type = "tcpmux"
+multiplexer = "httpconnect"
+customDomains = ["a.example.com"]
+localPort = 80
+"#).unwrap();
+
+        let app_config = AppConfig { frpc_toml_path: path.clone(), frps_addr: "frps.example.com".to_string(), ..AppConfig::default() };
+        frpc_config_init(&app_config, &allocate_ports(&app_config, 1)).await.unwrap();
+        frpc_config_init(&app_config, &allocate_ports(&app_config, 2)).await.unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("loginFailExit = false"));
+        assert!(contents.contains("level = \"debug\""));
+        assert!(contents.contains("proxyProtocolVersion = \"v2\""));
+        assert!(contents.contains("owner = \"ops\""));
+        assert!(contents.contains("multiplexer = \"httpconnect\""));
+
+        let config = frpc_config_load(&path).await.unwrap();
+        assert_eq!(config.server_addr, "frps.example.com");
+        assert_eq!(config.proxies[0].proxy_type, ProxyType::Stcp);
+        assert_eq!(config.proxies[1].proxy_type, ProxyType::Other("tcpmux".to_string()));
+        // 重新生成时替换受管代理，不会越积越多
+        assert_eq!(config.proxies.len(), 2 + game_proxies(&app_config, 2).len());
+        assert!(config.proxies.iter().any(|p| p.name == "7daysTodieServer-2"));
+        assert!(!config.proxies.iter().any(|p| p.name == "7daysTodieServer-1"));
+    }
+
+    #[test]
+    fn allocate_ports_test() {
+        let exposed = AppConfig { frpc_expose_web_dashboard: true, frpc_expose_telnet: true, ..AppConfig::default() };
+        let ports: Vec<(ProxyType, u16, u16)> = allocate_ports(&exposed, 2)
+            .into_iter()
+            .map(|p| (p.proxy_type, p.local_port, p.remote_port))
+            .collect();
+        assert_eq!(ports, vec![
+            (ProxyType::Tcp, 26900, 26920), (ProxyType::Udp, 26900, 26920), (ProxyType::Udp, 26901, 26921),
+            (ProxyType::Udp, 26902, 26922), (ProxyType::Udp, 26903, 26923),
+            (ProxyType::Tcp, 8080, 26924), (ProxyType::Tcp, 8081, 26925),
+        ]);
+        assert!(game_proxies(&exposed, 2).iter().all(|p| p.validate().is_ok()));
+        // 默认只暴露游戏端口
+        assert_eq!(allocate_ports(&AppConfig::default(), 2).len(), 5);
+    }
+
+    #[test]
+    fn no_collisions_across_indexes_test() {
+        let app_config = AppConfig::default();
+        let all: Vec<Proxy> = (0..=255).flat_map(|index| game_proxies(&app_config, index)).collect();
+        assert_eq!(check_port_collisions(&all), Ok(()));
+
+        // 手动添加的代理占用了 index 0 的 UDP 端口
+        let mut clash = game_proxies(&app_config, 0);
+        clash.push(managed_proxy("extra", "extra".to_string(), ProxyType::Udp, 1, 26901, "1KB"));
+        assert!(check_port_collisions(&clash).unwrap_err().contains("26901"));
+    }
+
+    #[test]
+    fn leased_tunnels_on_one_index_test() {
+        let app_config = AppConfig { frpc_expose_web_dashboard: true, frpc_expose_telnet: true, ..AppConfig::default() };
+        // 同一个 index 上两条隧道，租到相邻的两段端口
+        let mut ports = allocate_ports_at(&app_config, "3", 30000);
+        ports.extend(allocate_ports_at(&app_config, "3-b", 30006));
+        assert_eq!(ports[0].name, "7daysTodieServer-3");
+        assert_eq!(ports.iter().map(|p| p.remote_port).max(), Some(30011));
+        assert_eq!(check_port_collisions(&proxies_for(&app_config, &ports)), Ok(()));
+    }
+
+    #[test]
+    fn apply_local_ports_test() {
+        let mut settings = ServerSettings { server_port: 27015, telnet_port: 8081, ..ServerSettings::default() };
+        assert_eq!(apply_local_ports(&mut settings, &AppConfig::default()), vec!["server_port"]);
+        assert_eq!(settings.server_port, 26900);
+    }
+
+    #[test]
+    fn check_exposed_settings_test() {
+        let settings = ServerSettings::default();
+        assert_eq!(check_exposed_settings(&settings, &AppConfig::default()), Ok(()));
+        let exposed = AppConfig { frpc_expose_telnet: true, ..AppConfig::default() };
+        let violations = check_exposed_settings(&settings, &exposed).unwrap_err();
+        assert_eq!(violations[0].field, "telnet_password");
+        let with_password = ServerSettings { telnet_password: "secret".to_string(), ..ServerSettings::default() };
+        assert_eq!(check_exposed_settings(&with_password, &exposed), Ok(()));
+        let disabled = ServerSettings { telnet_enabled: false, ..ServerSettings::default() };
+        assert_eq!(check_exposed_settings(&disabled, &exposed), Ok(()));
     }
 }
@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use anyhow::bail;
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use crate::config_util::AppConfig;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// frpc /api/status 中的一项
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProxyStatus {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub proxy_type: String,
+    // new / wait start / start error / running / check failed / closed
+    pub status: String,
+    #[serde(default)]
+    pub err: String,
+    #[serde(default)]
+    pub local_addr: String,
+    #[serde(default)]
+    pub remote_addr: String,
+}
+
+impl ProxyStatus {
+    pub fn is_running(&self) -> bool {
+        self.status == "running"
+    }
+
+    // frps 拒绝时的报错，例如 "port already used" / "port unavailable"
+    pub fn remote_port_taken(&self) -> bool {
+        let err = self.err.to_lowercase();
+        err.contains("port already used") || err.contains("port unavailable") || err.contains("address already in use")
+    }
+}
+
+// frpc webServer 的管理接口
+pub struct FrpcAdminClient {
+    base_url: String,
+    user: String,
+    password: String,
+    client: Client,
+}
+
+impl FrpcAdminClient {
+    pub fn new(base_url: &str, user: &str, password: &str) -> anyhow::Result<Self> {
+        Ok(FrpcAdminClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            user: user.to_string(),
+            password: password.to_string(),
+            client: Client::builder().timeout(REQUEST_TIMEOUT).build()?,
+        })
+    }
+
+    pub fn from_config(config: &AppConfig) -> anyhow::Result<Self> {
+        FrpcAdminClient::new(&format!("http://{}:{}", config.frpc_admin_addr, config.frpc_admin_port),
+                             &config.frpc_admin_user, &config.frpc_admin_password)
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> RequestBuilder {
+        let builder = self.client.request(method, format!("{}{}", self.base_url, path));
+        if self.user.is_empty() {
+            builder
+        } else {
+            builder.basic_auth(&self.user, Some(&self.password))
+        }
+    }
+
+    async fn send(&self, builder: RequestBuilder) -> anyhow::Result<reqwest::Response> {
+        let response = builder.send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            bail!("frpc admin api returned {}: {}", status, body.trim());
+        }
+        Ok(response)
+    }
+
+    // 按代理名排序
+    pub async fn status(&self) -> anyhow::Result<Vec<ProxyStatus>> {
+        let response = self.send(self.request(reqwest::Method::GET, "/api/status")).await?;
+        let by_type = response.json::<BTreeMap<String, Vec<ProxyStatus>>>().await?;
+        let mut proxies: Vec<ProxyStatus> = by_type.into_values().flatten().collect();
+        proxies.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(proxies)
+    }
+
+    // frpc 当前实际加载的配置文本
+    pub async fn get_config(&self) -> anyhow::Result<String> {
+        let response = self.send(self.request(reqwest::Method::GET, "/api/config")).await?;
+        Ok(response.text().await?)
+    }
+
+    pub async fn reload(&self) -> anyhow::Result<()> {
+        self.send(self.request(reqwest::Method::GET, "/api/reload")).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use serde_json::json;
+    use tokio::sync::Mutex;
+    use crate::frpc_admin_util::{FrpcAdminClient, ProxyStatus};
+
+    // 模拟 frpc webServer，要求 admin:secret 的 basic auth
+    async fn stub_frpc_admin() -> (String, Arc<Mutex<u32>>) {
+        let reloads = Arc::new(Mutex::new(0));
+        let reload_count = reloads.clone();
+        let authorized = |headers: &HeaderMap| headers.get("authorization").is_some_and(|v| v == "Basic YWRtaW46c2VjcmV0");
+        let app = Router::new()
+            .route("/api/status", get(move |headers: HeaderMap| async move {
+                if !authorized(&headers) {
+                    return Err(StatusCode::UNAUTHORIZED);
+                }
+                Ok(Json(json!({
+                    "udp": [{"name": "7daysTodieServerUDP-1", "type": "udp", "status": "running", "err": "",
+                             "local_addr": "127.0.0.1:26902", "remote_addr": "1.2.3.4:26903"}],
+                    "tcp": [{"name": "7daysTodieServer-1", "type": "tcp", "status": "start error",
+                             "err": "port already used", "local_addr": "127.0.0.1:26900", "remote_addr": ""}]
+                })))
+            }))
+            .route("/api/reload", get(move || async move {
+                *reload_count.lock().await += 1;
+                StatusCode::OK
+            }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}", addr), reloads)
+    }
+
+    #[tokio::test]
+    async fn status_and_reload_test() {
+        let (base_url, reloads) = stub_frpc_admin().await;
+        let client = FrpcAdminClient::new(&base_url, "admin", "secret").unwrap();
+
+        let proxies: Vec<ProxyStatus> = client.status().await.unwrap();
+        assert_eq!(proxies.len(), 2);
+        assert_eq!(proxies[0].name, "7daysTodieServer-1");
+        assert!(!proxies[0].is_running());
+        assert!(proxies[0].remote_port_taken());
+        assert!(proxies[1].is_running());
+        assert_eq!(proxies[1].remote_addr, "1.2.3.4:26903");
+
+        client.reload().await.unwrap();
+        assert_eq!(*reloads.lock().await, 1);
+
+        let anonymous = FrpcAdminClient::new(&base_url, "", "").unwrap();
+        assert!(anonymous.status().await.unwrap_err().to_string().contains("401"));
+    }
+}
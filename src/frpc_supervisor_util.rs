@@ -0,0 +1,207 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+use tokio::sync::{broadcast, Mutex};
+use tokio::time::sleep;
+
+const RECENT_LOG_LINES: usize = 200;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// 运行超过这个时间才算启动成功，退避时间清零
+const STABLE_RUN: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FrpcState {
+    NotStarted,
+    Running,
+    Backoff,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FrpcHealth {
+    pub state: FrpcState,
+    pub pid: Option<u32>,
+    pub restarts: u32,
+    pub started_at: Option<u64>,
+    pub last_exit: Option<String>,
+}
+
+pub fn backoff_delay(attempt: u32, initial: Duration, max: Duration) -> Duration {
+    initial.saturating_mul(2u32.saturating_pow(attempt)).min(max)
+}
+
+struct Inner {
+    health: FrpcHealth,
+    recent: VecDeque<String>,
+    started: bool,
+}
+
+// 由 game_master 持有 frpc 子进程，退出后按退避时间重启
+#[derive(Clone)]
+pub struct FrpcSupervisor {
+    inner: Arc<Mutex<Inner>>,
+    logs: broadcast::Sender<String>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for FrpcSupervisor {
+    fn default() -> Self {
+        FrpcSupervisor::new(INITIAL_BACKOFF, MAX_BACKOFF)
+    }
+}
+
+impl FrpcSupervisor {
+    pub fn new(initial_backoff: Duration, max_backoff: Duration) -> Self {
+        let (logs, _) = broadcast::channel(RECENT_LOG_LINES);
+        let health = FrpcHealth { state: FrpcState::NotStarted, pid: None, restarts: 0, started_at: None, last_exit: None };
+        FrpcSupervisor {
+            inner: Arc::new(Mutex::new(Inner { health, recent: VecDeque::new(), started: false })),
+            logs,
+            initial_backoff,
+            max_backoff,
+        }
+    }
+
+    pub async fn health(&self) -> FrpcHealth {
+        self.inner.lock().await.health.clone()
+    }
+
+    pub async fn recent_logs(&self) -> Vec<String> {
+        self.inner.lock().await.recent.iter().cloned().collect()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.logs.subscribe()
+    }
+
+    async fn log(&self, line: String) {
+        println!("frpc: {}", line);
+        {
+            let mut inner = self.inner.lock().await;
+            if inner.recent.len() == RECENT_LOG_LINES {
+                inner.recent.pop_front();
+            }
+            inner.recent.push_back(line.clone());
+        }
+        let _ = self.logs.send(line);
+    }
+
+    async fn forward_logs(&self, output: impl AsyncRead + Unpin) {
+        let mut lines = BufReader::new(output).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            self.log(line).await;
+        }
+    }
+
+    // 只会启动一次，返回 false 表示已经在监管中
+    pub async fn start(&self, exe: PathBuf, config: PathBuf) -> bool {
+        {
+            let mut inner = self.inner.lock().await;
+            if inner.started {
+                return false;
+            }
+            inner.started = true;
+        }
+        let supervisor = self.clone();
+        tokio::spawn(async move { supervisor.run(exe, config).await });
+        true
+    }
+
+    async fn run(self, exe: PathBuf, config: PathBuf) {
+        let mut attempt = 0;
+        loop {
+            let started = Instant::now();
+            let exit = match Command::new(&exe)
+                .arg("-c")
+                .arg(&config)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn() {
+                Ok(mut child) => {
+                    {
+                        let mut inner = self.inner.lock().await;
+                        inner.health.state = FrpcState::Running;
+                        inner.health.pid = child.id();
+                        inner.health.started_at = SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs());
+                    }
+                    let stdout = child.stdout.take().expect("frpc stdout is piped");
+                    let stderr = child.stderr.take().expect("frpc stderr is piped");
+                    tokio::join!(self.forward_logs(stdout), self.forward_logs(stderr));
+                    match child.wait().await {
+                        Ok(status) => format!("exited with {}", status),
+                        Err(e) => format!("wait error: {}", e),
+                    }
+                }
+                Err(e) => format!("spawn {:?} error: {}", exe, e),
+            };
+
+            if started.elapsed() >= STABLE_RUN {
+                attempt = 0;
+            }
+            let delay = backoff_delay(attempt, self.initial_backoff, self.max_backoff);
+            attempt += 1;
+            self.log(format!("{}, restart in {:?}", exit, delay)).await;
+            {
+                let mut inner = self.inner.lock().await;
+                inner.health.state = FrpcState::Backoff;
+                inner.health.pid = None;
+                inner.health.last_exit = Some(exit);
+                inner.health.restarts += 1;
+            }
+            sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::Duration;
+    use tempfile::tempdir;
+    use crate::frpc_supervisor_util::{backoff_delay, FrpcState, FrpcSupervisor};
+
+    #[test]
+    fn backoff_delay_test() {
+        let initial = Duration::from_secs(1);
+        let max = Duration::from_secs(60);
+        assert_eq!(backoff_delay(0, initial, max), Duration::from_secs(1));
+        assert_eq!(backoff_delay(3, initial, max), Duration::from_secs(8));
+        assert_eq!(backoff_delay(10, initial, max), max);
+        assert_eq!(backoff_delay(100, initial, max), max);
+    }
+
+    #[tokio::test]
+    async fn restart_on_exit_test() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        let exe = root.join("frpc");
+        std::fs::write(&exe, "#!/bin/sh\necho \"start with $2\"\necho \"oops\" >&2\nexit 1\n").unwrap();
+        std::fs::set_permissions(&exe, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let supervisor = FrpcSupervisor::new(Duration::from_millis(10), Duration::from_millis(20));
+        assert_eq!(supervisor.health().await.state, FrpcState::NotStarted);
+        assert!(supervisor.start(exe.clone(), root.join("frpc.toml")).await);
+        assert!(!supervisor.start(exe, root.join("frpc.toml")).await);
+
+        for _ in 0..200 {
+            if supervisor.health().await.restarts >= 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let health = supervisor.health().await;
+        assert!(health.restarts >= 2);
+        assert!(health.last_exit.unwrap().contains("exit status: 1"));
+        let logs = supervisor.recent_logs().await;
+        assert!(logs.iter().any(|l| l.ends_with("frpc.toml")));
+        assert!(logs.iter().any(|l| l == "oops"));
+    }
+}
@@ -1,22 +1,609 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use anyhow::bail;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use tera::{Tera, Context};
 use tokio::fs;
-use crate::const_value::SERVERCONFIG_XML_PATH;
+use crate::installation_util::GameInstallation;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
 pub struct ServerSettings {
+    // Server representation
     pub server_name: String,
     pub server_description: String,
+    pub server_website_url: String,
     pub server_password: String,
+    pub server_login_confirmation_text: String,
+    pub region: String,
     pub language: String,
+
+    // Networking
+    pub server_port: i32,
+    pub server_visibility: i32,
+    pub server_disabled_network_protocols: String,
+    pub server_max_world_transfer_speed_kibs: i32,
+
+    // Slots
     pub server_max_player_count: i32,
+    pub server_reserved_slots: i32,
+    pub server_reserved_slots_permission: i32,
+    pub server_admin_slots: i32,
+    pub server_admin_slots_permission: i32,
+
+    // Admin interfaces
+    pub web_dashboard_enabled: bool,
+    pub web_dashboard_port: i32,
+    pub web_dashboard_url: String,
+    pub enable_map_rendering: bool,
+    pub telnet_enabled: bool,
+    pub telnet_port: i32,
+    pub telnet_password: String,
+    pub telnet_failed_login_limit: i32,
+    pub telnet_failed_logins_blocktime: i32,
+    pub terminal_window_enabled: bool,
+
+    // Folder and file locations
+    pub admin_file_name: String,
+
+    // Other technical settings
+    pub server_allow_crossplay: bool,
     pub eac_enabled: bool,
+    pub ignore_eos_sanctions: bool,
+    pub hide_command_execution_log: i32,
+    pub max_uncovered_map_chunks_per_player: i32,
+    pub persistent_player_profiles: bool,
+    pub max_chunk_age: i32,
+    pub save_data_limit: i32,
+
+    // World
+    pub game_world: String,
+    pub world_gen_seed: String,
+    pub world_gen_size: i32,
+    pub game_name: String,
+    pub game_mode: String,
+
+    // Difficulty
     pub game_difficulty: i32,
+    pub block_damage_player: i32,
+    pub block_damage_ai: i32,
+    pub block_damage_ai_bm: i32,
+    pub xp_multiplier: i32,
+    pub player_safe_zone_level: i32,
+    pub player_safe_zone_hours: i32,
+
+    // Game Rules
+    pub build_create: bool,
+    pub day_night_length: i32,
+    pub day_light_length: i32,
+    pub biome_progression: bool,
+    pub storm_freq: i32,
+    pub death_penalty: i32,
+    pub drop_on_death: i32,
+    pub drop_on_quit: i32,
+    pub bedroll_dead_zone_size: i32,
+    pub bedroll_expiry_time: i32,
+    pub allow_spawn_near_friend: i32,
+
+    // Performance related
+    pub max_spawned_zombies: i32,
+    pub max_spawned_animals: i32,
+    pub server_max_allowed_view_distance: i32,
+    pub max_queued_mesh_layers: i32,
+
+    // Zombie settings
+    pub enemy_spawn_mode: bool,
+    pub enemy_difficulty: i32,
+    pub zombie_feral_sense: i32,
+    pub zombie_move: i32,
+    pub zombie_move_night: i32,
+    pub zombie_feral_move: i32,
+    pub zombie_bm_move: i32,
+    pub blood_moon_frequency: i32,
+    pub blood_moon_range: i32,
+    pub blood_moon_warning: i32,
+    pub blood_moon_enemy_count: i32,
+
+    // Loot
+    pub loot_abundance: i32,
+    pub loot_respawn_days: i32,
+    pub air_drop_frequency: i32,
+    pub air_drop_marker: bool,
+
+    // Multiplayer
     pub party_shared_kill_range: i32,
-    pub player_killing_mode: i32
+    pub player_killing_mode: i32,
+
+    // Land claim options
+    pub land_claim_count: i32,
+    pub land_claim_size: i32,
+    pub land_claim_dead_zone: i32,
+    pub land_claim_expiry_time: i32,
+    pub land_claim_decay_mode: i32,
+    pub land_claim_online_durability_modifier: i32,
+    pub land_claim_offline_durability_modifier: i32,
+    pub land_claim_offline_delay: i32,
+
+    // Dynamic mesh
+    pub dynamic_mesh_enabled: bool,
+    pub dynamic_mesh_land_claim_only: bool,
+    pub dynamic_mesh_land_claim_buffer: i32,
+    pub dynamic_mesh_max_item_cache: i32,
+
+    // Twitch
+    pub twitch_server_permission: i32,
+    pub twitch_blood_moon_allowed: bool,
+
+    // Quests
+    pub quest_progression_daily_limit: i32,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            // Server representation
+            server_name: "Local Game Host".to_string(),
+            server_description: "A 7 Days to Die server".to_string(),
+            server_website_url: "".to_string(),
+            server_password: "".to_string(),
+            server_login_confirmation_text: "".to_string(),
+            region: "NorthAmericaEast".to_string(),
+            language: "English".to_string(),
+
+            // Networking
+            server_port: 26900,
+            server_visibility: 2,
+            server_disabled_network_protocols: "SteamNetworking".to_string(),
+            server_max_world_transfer_speed_kibs: 512,
+
+            // Slots
+            server_max_player_count: 8,
+            server_reserved_slots: 0,
+            server_reserved_slots_permission: 100,
+            server_admin_slots: 0,
+            server_admin_slots_permission: 0,
+
+            // Admin interfaces
+            web_dashboard_enabled: true,
+            web_dashboard_port: 8080,
+            web_dashboard_url: "/".to_string(),
+            enable_map_rendering: true,
+            telnet_enabled: true,
+            telnet_port: 8081,
+            telnet_password: "".to_string(),
+            telnet_failed_login_limit: 10,
+            telnet_failed_logins_blocktime: 10,
+            terminal_window_enabled: true,
+
+            // Folder and file locations
+            admin_file_name: "serveradmin.xml".to_string(),
+
+            // Other technical settings
+            server_allow_crossplay: false,
+            eac_enabled: false,
+            ignore_eos_sanctions: false,
+            hide_command_execution_log: 0,
+            max_uncovered_map_chunks_per_player: 131072,
+            persistent_player_profiles: false,
+            max_chunk_age: -1,
+            save_data_limit: -1,
+
+            // World
+            game_world: "Navezgane".to_string(),
+            world_gen_seed: "MyGame".to_string(),
+            world_gen_size: 6144,
+            game_name: "MyGame".to_string(),
+            game_mode: "GameModeSurvival".to_string(),
+
+            // Difficulty
+            game_difficulty: 1,
+            block_damage_player: 100,
+            block_damage_ai: 100,
+            block_damage_ai_bm: 100,
+            xp_multiplier: 100,
+            player_safe_zone_level: 5,
+            player_safe_zone_hours: 5,
+
+            // Game Rules
+            build_create: false,
+            day_night_length: 60,
+            day_light_length: 18,
+            biome_progression: true,
+            storm_freq: 100,
+            death_penalty: 1,
+            drop_on_death: 1,
+            drop_on_quit: 0,
+            bedroll_dead_zone_size: 15,
+            bedroll_expiry_time: 45,
+            allow_spawn_near_friend: 2,
+
+            // Performance related
+            max_spawned_zombies: 64,
+            max_spawned_animals: 50,
+            server_max_allowed_view_distance: 12,
+            max_queued_mesh_layers: 1000,
+
+            // Zombie settings
+            enemy_spawn_mode: true,
+            enemy_difficulty: 0,
+            zombie_feral_sense: 0,
+            zombie_move: 0,
+            zombie_move_night: 3,
+            zombie_feral_move: 3,
+            zombie_bm_move: 3,
+            blood_moon_frequency: 7,
+            blood_moon_range: 0,
+            blood_moon_warning: 8,
+            blood_moon_enemy_count: 8,
+
+            // Loot
+            loot_abundance: 100,
+            loot_respawn_days: 7,
+            air_drop_frequency: 72,
+            air_drop_marker: true,
+
+            // Multiplayer
+            party_shared_kill_range: 100,
+            player_killing_mode: 3,
+
+            // Land claim options
+            land_claim_count: 5,
+            land_claim_size: 41,
+            land_claim_dead_zone: 30,
+            land_claim_expiry_time: 7,
+            land_claim_decay_mode: 0,
+            land_claim_online_durability_modifier: 4,
+            land_claim_offline_durability_modifier: 4,
+            land_claim_offline_delay: 0,
+
+            // Dynamic mesh
+            dynamic_mesh_enabled: true,
+            dynamic_mesh_land_claim_only: true,
+            dynamic_mesh_land_claim_buffer: 3,
+            dynamic_mesh_max_item_cache: 3,
+
+            // Twitch
+            twitch_server_permission: 90,
+            twitch_blood_moon_allowed: false,
+
+            // Quests
+            quest_progression_daily_limit: 4,
+        }
+    }
+}
+
+const REGIONS: [&str; 11] = [
+    "NorthAmericaEast", "NorthAmericaWest", "CentralAmerica", "SouthAmerica", "Europe",
+    "Russia", "Asia", "MiddleEast", "Africa", "Oceania", "",
+];
+const NETWORK_PROTOCOLS: [&str; 2] = ["LiteNetLib", "SteamNetworking"];
+const GAME_MODES: [&str; 2] = ["GameModeSurvival", "GameModeCreative"];
+const RWG_WORLD_SIZES: [i32; 3] = [6144, 8192, 10240];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub field: String,
+    pub message: String,
+}
+
+// 只能是单个目录名，不能跳出所在目录
+pub fn is_path_segment(value: &str) -> bool {
+    !(value.is_empty() || value == "." || value == ".." || value.contains(['/', '\\']))
+}
+
+#[derive(Default)]
+struct Validator {
+    violations: Vec<Violation>,
+}
+
+impl Validator {
+    fn push(&mut self, field: &str, message: String) {
+        self.violations.push(Violation { field: field.to_string(), message });
+    }
+
+    fn range(&mut self, field: &str, value: i32, min: i32, max: i32) {
+        if value < min || value > max {
+            self.push(field, format!("{} is out of range {}..={}", value, min, max));
+        }
+    }
+
+    fn min(&mut self, field: &str, value: i32, min: i32) {
+        if value < min {
+            self.push(field, format!("{} is less than {}", value, min));
+        }
+    }
+
+    fn one_of(&mut self, field: &str, value: &str, allowed: &[&str]) {
+        if !allowed.contains(&value) {
+            self.push(field, format!("{:?} is not one of {:?}", value, allowed));
+        }
+    }
+
+    // XML 1.0 不允许除 \t \n \r 以外的控制字符，转义也救不回来
+    fn text(&mut self, field: &str, value: &str) {
+        if value.chars().any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r')) {
+            self.push(field, "contains control characters".to_string());
+        }
+    }
+
+    // 会被拼进文件路径的值
+    fn path_segment(&mut self, field: &str, value: &str) {
+        if !is_path_segment(value) {
+            self.push(field, format!("{:?} is not a valid file name", value));
+        }
+    }
+}
+
+impl ServerSettings {
+    pub fn validate(&self) -> Result<(), Vec<Violation>> {
+        let mut v = Validator::default();
+
+        // Server representation
+        if self.server_name.trim().is_empty() {
+            v.push("server_name", "must not be empty".to_string());
+        }
+        v.text("server_name", &self.server_name);
+        v.text("server_description", &self.server_description);
+        v.text("server_website_url", &self.server_website_url);
+        v.text("server_password", &self.server_password);
+        v.text("server_login_confirmation_text", &self.server_login_confirmation_text);
+        v.one_of("region", &self.region, &REGIONS);
+        v.text("language", &self.language);
+
+        // Networking
+        v.range("server_port", self.server_port, 1, 65535);
+        v.range("server_visibility", self.server_visibility, 0, 2);
+        for protocol in self.server_disabled_network_protocols.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            v.one_of("server_disabled_network_protocols", protocol, &NETWORK_PROTOCOLS);
+        }
+        v.min("server_max_world_transfer_speed_kibs", self.server_max_world_transfer_speed_kibs, 0);
+
+        // Slots
+        v.min("server_max_player_count", self.server_max_player_count, 1);
+        v.range("server_reserved_slots", self.server_reserved_slots, 0, self.server_max_player_count.max(0));
+        v.range("server_reserved_slots_permission", self.server_reserved_slots_permission, 0, 1000);
+        v.min("server_admin_slots", self.server_admin_slots, 0);
+        v.range("server_admin_slots_permission", self.server_admin_slots_permission, 0, 1000);
+
+        // Admin interfaces
+        v.range("web_dashboard_port", self.web_dashboard_port, 1, 65535);
+        v.text("web_dashboard_url", &self.web_dashboard_url);
+        v.range("telnet_port", self.telnet_port, 1, 65535);
+        v.text("telnet_password", &self.telnet_password);
+        v.min("telnet_failed_login_limit", self.telnet_failed_login_limit, 0);
+        v.min("telnet_failed_logins_blocktime", self.telnet_failed_logins_blocktime, 0);
+
+        // Folder and file locations
+        v.path_segment("admin_file_name", &self.admin_file_name);
+        v.text("admin_file_name", &self.admin_file_name);
+
+        // Other technical settings
+        v.range("hide_command_execution_log", self.hide_command_execution_log, 0, 3);
+        v.min("max_uncovered_map_chunks_per_player", self.max_uncovered_map_chunks_per_player, 0);
+        v.min("max_chunk_age", self.max_chunk_age, -1);
+        v.min("save_data_limit", self.save_data_limit, -1);
+
+        // World
+        v.path_segment("game_world", &self.game_world);
+        v.text("game_world", &self.game_world);
+        if self.game_world == "RWG" {
+            if self.world_gen_seed.is_empty() {
+                v.push("world_gen_seed", "must not be empty for RWG".to_string());
+            }
+            if !RWG_WORLD_SIZES.contains(&self.world_gen_size) {
+                v.push("world_gen_size", format!("{} is not one of {:?}", self.world_gen_size, RWG_WORLD_SIZES));
+            }
+        }
+        v.text("world_gen_seed", &self.world_gen_seed);
+        if !self.game_name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | ' ')) {
+            v.push("game_name", format!("{:?} may only contain [A-Za-z0-9_-. ]", self.game_name));
+        } else {
+            v.path_segment("game_name", &self.game_name);
+        }
+        v.one_of("game_mode", &self.game_mode, &GAME_MODES);
+
+        // Difficulty
+        v.range("game_difficulty", self.game_difficulty, 0, 5);
+        v.min("block_damage_player", self.block_damage_player, 0);
+        v.min("block_damage_ai", self.block_damage_ai, 0);
+        v.min("block_damage_ai_bm", self.block_damage_ai_bm, 0);
+        v.min("xp_multiplier", self.xp_multiplier, 0);
+        v.min("player_safe_zone_level", self.player_safe_zone_level, 0);
+        v.min("player_safe_zone_hours", self.player_safe_zone_hours, 0);
+
+        // Game Rules
+        v.min("day_night_length", self.day_night_length, 1);
+        v.range("day_light_length", self.day_light_length, 0, 24);
+        v.range("storm_freq", self.storm_freq, 0, 500);
+        v.range("death_penalty", self.death_penalty, 0, 3);
+        v.range("drop_on_death", self.drop_on_death, 0, 4);
+        v.range("drop_on_quit", self.drop_on_quit, 0, 3);
+        v.min("bedroll_dead_zone_size", self.bedroll_dead_zone_size, 0);
+        v.min("bedroll_expiry_time", self.bedroll_expiry_time, 0);
+        v.range("allow_spawn_near_friend", self.allow_spawn_near_friend, 0, 2);
+
+        // Performance related
+        v.min("max_spawned_zombies", self.max_spawned_zombies, 0);
+        v.min("max_spawned_animals", self.max_spawned_animals, 0);
+        v.range("server_max_allowed_view_distance", self.server_max_allowed_view_distance, 6, 12);
+        v.min("max_queued_mesh_layers", self.max_queued_mesh_layers, 0);
+
+        // Zombie settings
+        v.range("enemy_difficulty", self.enemy_difficulty, 0, 1);
+        v.range("zombie_feral_sense", self.zombie_feral_sense, 0, 3);
+        v.range("zombie_move", self.zombie_move, 0, 4);
+        v.range("zombie_move_night", self.zombie_move_night, 0, 4);
+        v.range("zombie_feral_move", self.zombie_feral_move, 0, 4);
+        v.range("zombie_bm_move", self.zombie_bm_move, 0, 4);
+        v.min("blood_moon_frequency", self.blood_moon_frequency, 0);
+        v.min("blood_moon_range", self.blood_moon_range, 0);
+        v.range("blood_moon_warning", self.blood_moon_warning, -1, 23);
+        v.min("blood_moon_enemy_count", self.blood_moon_enemy_count, 0);
+
+        // Loot
+        v.min("loot_abundance", self.loot_abundance, 0);
+        v.min("loot_respawn_days", self.loot_respawn_days, 0);
+        v.min("air_drop_frequency", self.air_drop_frequency, 0);
+
+        // Multiplayer
+        v.min("party_shared_kill_range", self.party_shared_kill_range, 0);
+        v.range("player_killing_mode", self.player_killing_mode, 0, 3);
+
+        // Land claim options
+        v.min("land_claim_count", self.land_claim_count, 0);
+        v.min("land_claim_size", self.land_claim_size, 0);
+        v.min("land_claim_dead_zone", self.land_claim_dead_zone, 0);
+        v.min("land_claim_expiry_time", self.land_claim_expiry_time, 0);
+        v.range("land_claim_decay_mode", self.land_claim_decay_mode, 0, 2);
+        v.min("land_claim_online_durability_modifier", self.land_claim_online_durability_modifier, 0);
+        v.min("land_claim_offline_durability_modifier", self.land_claim_offline_durability_modifier, 0);
+        v.min("land_claim_offline_delay", self.land_claim_offline_delay, 0);
+
+        // Dynamic mesh
+        v.min("dynamic_mesh_land_claim_buffer", self.dynamic_mesh_land_claim_buffer, 0);
+        v.min("dynamic_mesh_max_item_cache", self.dynamic_mesh_max_item_cache, 0);
+
+        // Twitch
+        v.range("twitch_server_permission", self.twitch_server_permission, 0, 1000);
+
+        // Quests
+        v.min("quest_progression_daily_limit", self.quest_progression_daily_limit, 0);
+
+        if v.violations.is_empty() {
+            Ok(())
+        } else {
+            Err(v.violations)
+        }
+    }
 }
 
+pub fn escape_xml(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&apos;"),
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+const SERVERCONFIG_TEMPLATE: &str = include_str!("../templates/serverconfig.xml");
+
+#[derive(Serialize, Debug)]
+pub struct ParsedServerConfig {
+    pub settings: ServerSettings,
+    // 模板没有管理的属性，比如手动加上去的
+    pub unmanaged_properties: BTreeMap<String, String>,
+    // 存在但无法解析成对应类型的属性
+    pub violations: Vec<Violation>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SettingChange {
+    pub field: String,
+    pub current: Value,
+    pub proposed: Value,
+}
+
+fn properties(doc: &roxmltree::Document) -> Vec<(String, String)> {
+    doc.descendants()
+        .filter(|node| node.has_tag_name("property"))
+        .filter_map(|node| Some((node.attribute("name")?.to_string(), node.attribute("value")?.to_string())))
+        .collect()
+}
+
+// 模板中 property name 与 settings 字段的对应关系，按模板顺序
+fn template_fields() -> Vec<(String, String)> {
+    let doc = roxmltree::Document::parse(SERVERCONFIG_TEMPLATE).expect("invalid serverconfig.xml template");
+    properties(&doc)
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let field = value.trim().strip_prefix("{{")?.strip_suffix("}}")?.trim().strip_prefix("settings.")?;
+            Some((name, field.to_string()))
+        })
+        .collect()
+}
+
+fn parse_value(default: &Value, raw: &str) -> Option<Value> {
+    let raw = raw.trim();
+    match default {
+        Value::Bool(_) => raw.to_ascii_lowercase().parse::<bool>().ok().map(Value::Bool),
+        Value::Number(_) => raw.parse::<i32>().ok().map(Value::from),
+        _ => Some(Value::String(raw.to_string())),
+    }
+}
+
+pub fn parse_serverconfig_xml(xml: &str) -> anyhow::Result<ParsedServerConfig> {
+    let doc = roxmltree::Document::parse(xml)?;
+    let fields: HashMap<String, String> = template_fields().into_iter().collect();
+    let defaults = match serde_json::to_value(ServerSettings::default())? {
+        Value::Object(map) => map,
+        _ => bail!("ServerSettings is not an object"),
+    };
+
+    let mut values = Map::new();
+    let mut unmanaged_properties = BTreeMap::new();
+    let mut violations = Vec::new();
+    for (name, raw) in properties(&doc) {
+        let Some(field) = fields.get(&name) else {
+            unmanaged_properties.insert(name, raw);
+            continue;
+        };
+        match parse_value(&defaults[field], &raw) {
+            Some(value) => {
+                values.insert(field.clone(), value);
+            }
+            None => violations.push(Violation { field: field.clone(), message: format!("cannot parse {} value {:?}", name, raw) }),
+        }
+    }
+
+    Ok(ParsedServerConfig {
+        settings: serde_json::from_value(Value::Object(values))?,
+        unmanaged_properties,
+        violations,
+    })
+}
+
+pub fn diff_settings(current: &ServerSettings, proposed: &ServerSettings) -> anyhow::Result<Vec<SettingChange>> {
+    let current = serde_json::to_value(current)?;
+    let proposed = serde_json::to_value(proposed)?;
+
+    Ok(template_fields()
+        .into_iter()
+        .filter(|(_, field)| current[field] != proposed[field])
+        .map(|(_, field)| SettingChange {
+            current: current[&field].clone(),
+            proposed: proposed[&field].clone(),
+            field,
+        })
+        .collect())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerConfigXmlError {
+    // 设置没通过校验，调用方可以按字段报给用户
+    InvalidSettings(Vec<Violation>),
+    // 模板渲染或写文件失败
+    Failed(String),
+}
+
+impl fmt::Display for ServerConfigXmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerConfigXmlError::InvalidSettings(violations) => write!(f, "invalid server settings: {:?}", violations),
+            ServerConfigXmlError::Failed(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ServerConfigXmlError {}
+
 pub struct GameConfigUtil {
     init: bool,
     tera: Tera,
@@ -26,41 +613,157 @@ impl GameConfigUtil {
     pub fn new() -> Self {
         let mut tera = Tera::default();
 
-        let rss_template = include_str!("../templates/serverconfig.xml");
-        tera.add_raw_template("serverconfig.xml", rss_template).unwrap();
+        tera.add_raw_template("serverconfig.xml", SERVERCONFIG_TEMPLATE).unwrap();
+        // 默认的 html 转义会把 / 变成 &#x2F;，换成 xml 转义
+        tera.set_escape_fn(escape_xml);
 
         GameConfigUtil { init: true, tera }
     }
 
-    fn render(&self, server_settings: &ServerSettings) -> anyhow::Result<String> {
+    fn render(&self, server_settings: &ServerSettings, user_data_folder: &str) -> Result<String, ServerConfigXmlError> {
+        server_settings.validate().map_err(ServerConfigXmlError::InvalidSettings)?;
+
         let mut context = Context::new();
         context.insert("settings", server_settings);
+        context.insert("user_data_folder", user_data_folder);
 
-        let xml = self.tera.render("serverconfig.xml", &context)?;
+        let xml = self.tera.render("serverconfig.xml", &context)
+            .map_err(|e| ServerConfigXmlError::Failed(e.to_string()))?;
 
         Ok(xml)
     }
 
-    pub async fn set_serverconfig_xml(&self, server_settings: &ServerSettings) -> anyhow::Result<()> {
+    pub async fn set_serverconfig_xml(&self, server_settings: &ServerSettings, installation: &GameInstallation) -> Result<(), ServerConfigXmlError> {
         if !self.init {
-            bail!("GameConfigUtil not init");
+            return Err(ServerConfigXmlError::Failed("GameConfigUtil not init".to_string()));
         }
 
-        let xml = self.render(server_settings)?;
-        fs::write(SERVERCONFIG_XML_PATH, xml).await?;
+        let xml = self.render(server_settings, installation.user_data_path().to_string_lossy().as_ref())?;
+        fs::write(installation.serverconfig_path(), xml).await
+            .map_err(|e| ServerConfigXmlError::Failed(format!("write {:?}: {}", installation.serverconfig_path(), e)))?;
 
         println!("set serverconfig xml to {:#?}", server_settings);
         Ok(())
     }
+
+    pub async fn read_serverconfig_xml(&self, installation: &GameInstallation) -> anyhow::Result<ParsedServerConfig> {
+        let xml = fs::read_to_string(installation.serverconfig_path()).await?;
+        parse_serverconfig_xml(&xml)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::game_config_util::GameConfigUtil;
+    use serde_json::json;
+    use crate::game_config_util::{diff_settings, parse_serverconfig_xml, GameConfigUtil, ServerConfigXmlError, ServerSettings, SettingChange, RWG_WORLD_SIZES};
 
     #[test]
     fn init_test() {
 
         GameConfigUtil::new();
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn partial_settings_test() {
+        let settings: ServerSettings = serde_json::from_str(r#"{"xp_multiplier": 200, "day_night_length": 90}"#).unwrap();
+        assert_eq!(settings.xp_multiplier, 200);
+        assert_eq!(settings.day_night_length, 90);
+        assert_eq!(settings.loot_abundance, 100);
+        assert_eq!(settings.server_name, "Local Game Host");
+    }
+
+    #[test]
+    fn render_settings_test() {
+        let settings = ServerSettings {
+            loot_abundance: 50,
+            land_claim_size: 71,
+            server_visibility: 0,
+            ..ServerSettings::default()
+        };
+        let xml = GameConfigUtil::new().render(&settings, "/root/7DaysToDieServer/Data").unwrap();
+        assert!(!xml.contains("{{"));
+        assert!(xml.contains(r#"<property name="LootAbundance"					value="50"/>"#));
+        assert!(xml.contains(r#"<property name="LandClaimSize"					value="71"/>"#));
+        assert!(xml.contains(r#"<property name="ServerVisibility"				value="0"/>"#));
+    }
+
+    #[test]
+    fn validate_test() {
+        assert_eq!(ServerSettings::default().validate(), Ok(()));
+
+        let settings = ServerSettings {
+            server_max_player_count: -1,
+            game_difficulty: 6,
+            player_killing_mode: 4,
+            region: "Mars".to_string(),
+            game_name: "../etc".to_string(),
+            game_world: "RWG".to_string(),
+            world_gen_size: 1000,
+            ..ServerSettings::default()
+        };
+        let fields: Vec<String> = settings.validate().unwrap_err().into_iter().map(|v| v.field).collect();
+        assert_eq!(fields, vec!["region", "server_max_player_count", "world_gen_size", "game_name",
+                                "game_difficulty", "player_killing_mode"]);
+
+        let rwg = ServerSettings { game_world: "RWG".to_string(), world_gen_size: RWG_WORLD_SIZES[1], ..ServerSettings::default() };
+        assert_eq!(rwg.validate(), Ok(()));
+    }
+
+    #[test]
+    fn render_escape_test() {
+        let settings = ServerSettings {
+            server_name: "Tom's \"<Server>\" & co".to_string(),
+            ..ServerSettings::default()
+        };
+        let xml = GameConfigUtil::new().render(&settings, "/root/7DaysToDieServer/Data").unwrap();
+        assert!(xml.contains(r#"value="Tom&apos;s &quot;&lt;Server&gt;&quot; &amp; co""#));
+        assert!(xml.contains(r#"<property name="WebDashboardUrl"				value="/"/>"#));
+
+        let bad = ServerSettings { server_password: "a\u{0}b".to_string(), ..ServerSettings::default() };
+        match GameConfigUtil::new().render(&bad, "/root/7DaysToDieServer/Data") {
+            Err(ServerConfigXmlError::InvalidSettings(violations)) => assert_eq!(violations[0].field, "server_password"),
+            other => panic!("expected invalid settings, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_rendered_test() {
+        let settings = ServerSettings {
+            server_name: "<Friends & Family>".to_string(),
+            game_world: "RWG".to_string(),
+            world_gen_size: 8192,
+            eac_enabled: true,
+            ..ServerSettings::default()
+        };
+        let xml = GameConfigUtil::new().render(&settings, "/root/7DaysToDieServer/Data").unwrap();
+        let parsed = parse_serverconfig_xml(&xml).unwrap();
+        assert_eq!(parsed.settings, settings);
+        assert!(parsed.violations.is_empty());
+        assert_eq!(parsed.unmanaged_properties.get("UserDataFolder").map(String::as_str), Some("/root/7DaysToDieServer/Data"));
+    }
+
+    #[test]
+    fn parse_hand_edited_test() {
+        let xml = r#"<?xml version="1.0"?>
+<ServerSettings>
+    <!-- <property name="XPMultiplier" value="999"/> -->
+    <property name="XPMultiplier" value=" 300 "/>
+    <property name="BuildCreate" value="True"/>
+    <property name="LootAbundance" value="lots"/>
+    <property name="SomeModSetting" value="1"/>
+</ServerSettings>"#;
+        let parsed = parse_serverconfig_xml(xml).unwrap();
+        assert_eq!(parsed.settings.xp_multiplier, 300);
+        assert!(parsed.settings.build_create);
+        assert_eq!(parsed.settings.loot_abundance, 100);
+        assert_eq!(parsed.violations.len(), 1);
+        assert_eq!(parsed.violations[0].field, "loot_abundance");
+        assert!(parsed.unmanaged_properties.contains_key("SomeModSetting"));
+
+        let diff = diff_settings(&parsed.settings, &ServerSettings::default()).unwrap();
+        assert_eq!(diff, vec![
+            SettingChange { field: "xp_multiplier".to_string(), current: json!(300), proposed: json!(100) },
+            SettingChange { field: "build_create".to_string(), current: json!(true), proposed: json!(false) },
+        ]);
+    }
+}
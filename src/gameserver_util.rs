@@ -1,24 +1,141 @@
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tokio::process::Command;
-use crate::const_value::{SEVENDAYS_EXE_PATH, SEVENDAYS_LOG_PATH, SEVENDAYS_SERVER_PATH};
+use crate::config_util::AppConfig;
+use crate::installation_util::GameInstallation;
 
-pub fn start_game_server() -> Result<tokio::process::Child, std::io::Error> {
-    let child = Command::new(SEVENDAYS_EXE_PATH)
+const LOG_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub fn start_game_server(exe: &Path, installation: &GameInstallation) -> Result<tokio::process::Child, std::io::Error> {
+    let child = Command::new(exe)
         .arg("-logfile")
-        .arg(SEVENDAYS_LOG_PATH)
+        .arg(installation.log_path())
         .arg("-quit")
         .arg("-batchmode")
         .arg("-nographics")
         .arg("-dedicated")
         .arg("-configfile=serverconfig.xml")
-        .env("LD_LIBRARY_PATH", SEVENDAYS_SERVER_PATH)
-        .current_dir(SEVENDAYS_SERVER_PATH)
+        .env("LD_LIBRARY_PATH", &installation.server_path)
+        .current_dir(&installation.server_path)
         .spawn()?;
 
     Ok(child)
 }
 
-pub fn start_folk_game_server() -> Result<tokio::process::Child, std::io::Error> {
-    let child = Command::new("C:\\Users\\89396\\projects\\game_master\\target\\debug\\folk_server.exe")
-        .arg("-configfile=serverconfig.xml").spawn()?;
-    Ok(child)
-}
\ No newline at end of file
+// game_server_exe 为空时用安装目录里的可执行文件
+pub fn game_server_exe(config: &AppConfig, installation: &GameInstallation) -> PathBuf {
+    if config.game_server_exe.as_os_str().is_empty() {
+        installation.exe_path()
+    } else {
+        config.game_server_exe.clone()
+    }
+}
+
+// 从游戏日志里识别的事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameLogEvent {
+    Ready,
+    PlayerJoined { name: String, entity_id: Option<u32>, platform_id: String },
+    PlayerLeft { name: String, entity_id: Option<u32>, platform_id: String },
+}
+
+// "key=value, key='value'" 形式的字段
+fn log_field<'a>(fields: &'a str, key: &str) -> Option<&'a str> {
+    fields.split(", ")
+        .find_map(|pair| pair.trim().strip_prefix(key)?.strip_prefix('='))
+        .map(|value| value.trim().trim_matches('\''))
+}
+
+// 例如:
+// 2024-07-25T12:00:00 10.123 INF StartGame done
+// 2024-07-25T12:00:00 10.123 INF Player connected, entityid=171, name=Alice, pltfmid=Steam_765, crossid=EOS_00, ...
+// 2024-07-25T12:00:00 10.123 INF Player disconnected: EntId=171, PlayerID='Steam_765', OwnerID='Steam_765', PlayerName='Alice', ...
+pub fn parse_log_line(line: &str) -> Option<GameLogEvent> {
+    if line.trim_end().ends_with("INF StartGame done") {
+        return Some(GameLogEvent::Ready);
+    }
+    if let Some((_, fields)) = line.split_once("INF Player connected, ") {
+        return Some(GameLogEvent::PlayerJoined {
+            name: log_field(fields, "name")?.to_string(),
+            entity_id: log_field(fields, "entityid").and_then(|id| id.parse().ok()),
+            platform_id: log_field(fields, "pltfmid").unwrap_or_default().to_string(),
+        });
+    }
+    if let Some((_, fields)) = line.split_once("INF Player disconnected: ") {
+        return Some(GameLogEvent::PlayerLeft {
+            name: log_field(fields, "PlayerName")?.to_string(),
+            entity_id: log_field(fields, "EntId").and_then(|id| id.parse().ok()),
+            platform_id: log_field(fields, "PlayerID").unwrap_or_default().to_string(),
+        });
+    }
+    None
+}
+
+// 持续读取日志新增的行，文件被截断（游戏重启）时从头读；由调用方 abort 结束
+pub async fn follow_log(path: PathBuf, mut on_line: impl FnMut(&str)) {
+    let mut offset: u64 = 0;
+    let mut pending = String::new();
+    loop {
+        if let Ok(mut file) = tokio::fs::File::open(&path).await {
+            let len = file.metadata().await.map(|m| m.len()).unwrap_or(0);
+            if len < offset {
+                offset = 0;
+                pending.clear();
+            }
+            if len > offset && file.seek(SeekFrom::Start(offset)).await.is_ok() {
+                let mut buf = Vec::new();
+                if let Ok(read) = file.read_to_end(&mut buf).await {
+                    offset += read as u64;
+                    pending.push_str(&String::from_utf8_lossy(&buf));
+                    while let Some(end) = pending.find('\n') {
+                        on_line(pending[..end].trim_end_matches('\r'));
+                        pending.drain(..=end);
+                    }
+                }
+            }
+        }
+        tokio::time::sleep(LOG_POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tempfile::tempdir;
+    use crate::gameserver_util::{follow_log, parse_log_line, GameLogEvent};
+
+    #[test]
+    fn parse_log_line_test() {
+        assert_eq!(parse_log_line("2024-07-25T12:00:00 10.123 INF StartGame done\r"), Some(GameLogEvent::Ready));
+        assert_eq!(
+            parse_log_line("2024-07-25T12:00:00 10.123 INF Player connected, entityid=171, name=Alice Smith, pltfmid=Steam_765, crossid=EOS_00, ip=1.2.3.4"),
+            Some(GameLogEvent::PlayerJoined { name: "Alice Smith".to_string(), entity_id: Some(171), platform_id: "Steam_765".to_string() })
+        );
+        assert_eq!(
+            parse_log_line("2024-07-25T12:00:00 10.123 INF Player disconnected: EntId=171, PlayerID='Steam_765', OwnerID='Steam_765', PlayerName='Alice Smith', ClientNumber='1'"),
+            Some(GameLogEvent::PlayerLeft { name: "Alice Smith".to_string(), entity_id: Some(171), platform_id: "Steam_765".to_string() })
+        );
+        assert_eq!(parse_log_line("2024-07-25T12:00:00 10.123 INF Chat: 'Alice': StartGame done?"), None);
+    }
+
+    #[tokio::test]
+    async fn follow_log_test() {
+        let temp = tempdir().unwrap();
+        let path = temp.path().join("output_log.txt");
+        std::fs::write(&path, "line 1\nline").unwrap();
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let collected = lines.clone();
+        let task = tokio::spawn(follow_log(path.clone(), move |line| collected.lock().unwrap().push(line.to_string())));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        std::fs::write(&path, "line 1\nline 2\n").unwrap();
+        tokio::time::sleep(Duration::from_millis(800)).await;
+        // 截断后从头读
+        std::fs::write(&path, "new\n").unwrap();
+        tokio::time::sleep(Duration::from_millis(800)).await;
+        task.abort();
+        assert_eq!(*lines.lock().unwrap(), vec!["line 1", "line 2", "new"]);
+    }
+}
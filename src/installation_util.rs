@@ -0,0 +1,135 @@
+use std::path::{Path, PathBuf};
+use anyhow::bail;
+use serde::Serialize;
+use tokio::fs;
+use crate::steamcmd_util::{read_install_info, InstallInfo};
+
+pub const DEFAULT_INSTALLATION: &str = "default";
+const SEVENDAYS_EXE_NAME: &str = "7DaysToDieServer.x86_64";
+const SERVERCONFIG_XML_NAME: &str = "serverconfig.xml";
+const SEVENDAYS_LOG_NAME: &str = "output_log.txt";
+
+// 一份游戏安装，按版本/分支区分，例如 "default"、"v1.4"、"alpha21"
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GameInstallation {
+    pub key: String,
+    pub server_path: PathBuf,
+}
+
+pub fn is_valid_installation_key(key: &str) -> bool {
+    !key.is_empty() && key != "." && key != ".."
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+impl GameInstallation {
+    // "default" 沿用原来的安装目录，其他版本放在 installs_root/<key>
+    pub fn new(default_path: &Path, installs_root: &Path, key: &str) -> anyhow::Result<Self> {
+        if !is_valid_installation_key(key) {
+            bail!("invalid installation {:?}", key);
+        }
+        let server_path = if key == DEFAULT_INSTALLATION {
+            default_path.to_path_buf()
+        } else {
+            installs_root.join(key)
+        };
+        Ok(GameInstallation { key: key.to_string(), server_path })
+    }
+
+    pub fn exe_path(&self) -> PathBuf {
+        self.server_path.join(SEVENDAYS_EXE_NAME)
+    }
+
+    pub fn serverconfig_path(&self) -> PathBuf {
+        self.server_path.join(SERVERCONFIG_XML_NAME)
+    }
+
+    pub fn log_path(&self) -> PathBuf {
+        self.server_path.join(SEVENDAYS_LOG_NAME)
+    }
+
+    // serverconfig.xml 里的 UserDataFolder
+    pub fn user_data_path(&self) -> PathBuf {
+        self.server_path.join("Data")
+    }
+
+    pub fn saves_path(&self) -> PathBuf {
+        self.user_data_path().join("Saves")
+    }
+
+    pub async fn install_info(&self) -> anyhow::Result<Option<InstallInfo>> {
+        read_install_info(&self.server_path).await
+    }
+
+    // 写进存档元数据的版本标识
+    pub async fn version(&self) -> Option<String> {
+        match self.install_info().await {
+            Ok(Some(info)) => Some(format!("{}/{}", info.branch, info.build_id)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct InstallationInfo {
+    pub installation: GameInstallation,
+    pub install: Option<InstallInfo>,
+}
+
+pub async fn list_installations(default_path: &Path, installs_root: &Path) -> anyhow::Result<Vec<InstallationInfo>> {
+    let mut installations = vec![GameInstallation::new(default_path, installs_root, DEFAULT_INSTALLATION)?];
+    if fs::try_exists(installs_root).await? {
+        let mut entries = fs::read_dir(installs_root).await?;
+        let mut keys = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let key = entry.file_name().to_string_lossy().to_string();
+            if entry.file_type().await?.is_dir() && is_valid_installation_key(&key) && key != DEFAULT_INSTALLATION {
+                keys.push(key);
+            }
+        }
+        keys.sort();
+        for key in keys {
+            installations.push(GameInstallation::new(default_path, installs_root, &key)?);
+        }
+    }
+
+    let mut result = Vec::new();
+    for installation in installations {
+        let install = installation.install_info().await?;
+        result.push(InstallationInfo { installation, install });
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use tempfile::tempdir;
+    use crate::installation_util::{list_installations, GameInstallation};
+
+    #[test]
+    fn installation_paths_test() {
+        let default = GameInstallation::new(Path::new("/root/7DaysToDieServer"), Path::new("/root/7DaysToDieServers"), "default").unwrap();
+        assert_eq!(default.saves_path(), Path::new("/root/7DaysToDieServer/Data/Saves"));
+
+        let alpha = GameInstallation::new(Path::new("/root/7DaysToDieServer"), Path::new("/root/7DaysToDieServers"), "alpha21").unwrap();
+        assert_eq!(alpha.exe_path(), Path::new("/root/7DaysToDieServers/alpha21/7DaysToDieServer.x86_64"));
+        assert_eq!(alpha.serverconfig_path(), Path::new("/root/7DaysToDieServers/alpha21/serverconfig.xml"));
+
+        assert!(GameInstallation::new(Path::new("/a"), Path::new("/b"), "../etc").is_err());
+    }
+
+    #[tokio::test]
+    async fn list_installations_test() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join("installs/alpha21")).unwrap();
+        std::fs::write(root.join("installs/alpha21/game_master_install.toml"),
+                       "app_id = 294420\nbuild_id = \"111\"\nbranch = \"alpha21\"\ninstalled_at = 0\n").unwrap();
+
+        let installations = list_installations(&root.join("default"), &root.join("installs")).await.unwrap();
+        let keys: Vec<&str> = installations.iter().map(|i| i.installation.key.as_str()).collect();
+        assert_eq!(keys, vec!["default", "alpha21"]);
+        assert!(installations[0].install.is_none());
+        assert_eq!(installations[1].installation.version().await, Some("alpha21/111".to_string()));
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Job {
+    pub id: u64,
+    pub kind: String,
+    pub status: JobStatus,
+    // 0 - 100
+    pub progress: f32,
+    pub message: String,
+    pub result: Option<Value>,
+}
+
+#[derive(Default)]
+struct JobsInner {
+    next_id: u64,
+    jobs: HashMap<u64, Job>,
+}
+
+// 长时间运行的后台任务，通过 /jobs/{id} 查询进度
+#[derive(Clone, Default)]
+pub struct JobRegistry {
+    inner: Arc<Mutex<JobsInner>>,
+}
+
+impl JobRegistry {
+    pub async fn create(&self, kind: &str) -> u64 {
+        let mut inner = self.inner.lock().await;
+        inner.next_id += 1;
+        let id = inner.next_id;
+        inner.jobs.insert(id, Job {
+            id,
+            kind: kind.to_string(),
+            status: JobStatus::Running,
+            progress: 0.0,
+            message: String::new(),
+            result: None,
+        });
+        id
+    }
+
+    pub async fn progress(&self, id: u64, progress: f32, message: &str) {
+        if let Some(job) = self.inner.lock().await.jobs.get_mut(&id) {
+            job.progress = progress;
+            job.message = message.to_string();
+        }
+    }
+
+    pub async fn finish(&self, id: u64, result: anyhow::Result<Value>) {
+        if let Some(job) = self.inner.lock().await.jobs.get_mut(&id) {
+            match result {
+                Ok(value) => {
+                    job.status = JobStatus::Succeeded;
+                    job.progress = 100.0;
+                    job.result = Some(value);
+                }
+                Err(e) => {
+                    job.status = JobStatus::Failed;
+                    job.message = e.to_string();
+                }
+            }
+        }
+    }
+
+    pub async fn get(&self, id: u64) -> Option<Job> {
+        self.inner.lock().await.jobs.get(&id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::job_util::{JobRegistry, JobStatus};
+
+    #[tokio::test]
+    async fn job_lifecycle_test() {
+        let jobs = JobRegistry::default();
+        let id = jobs.create("install").await;
+        jobs.progress(id, 42.0, "downloading").await;
+        assert_eq!(jobs.get(id).await.unwrap().progress, 42.0);
+
+        jobs.finish(id, Ok(json!({"build_id": "1"}))).await;
+        let job = jobs.get(id).await.unwrap();
+        assert_eq!(job.status, JobStatus::Succeeded);
+        assert_eq!(job.result, Some(json!({"build_id": "1"})));
+
+        let failed = jobs.create("install").await;
+        jobs.finish(failed, Err(anyhow::anyhow!("boom"))).await;
+        assert_eq!(jobs.get(failed).await.unwrap().message, "boom");
+        assert!(jobs.get(999).await.is_none());
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::bail;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use crate::config_util::AppConfig;
+use crate::data_server_util::{DataServerClient, DataServerError};
+use crate::frp_util::GAME_PORT_SLOTS;
+
+// 远端端口的来源
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PortLeaseMode {
+    // remote_port_base + index * remote_port_stride
+    Static,
+    // 向 port_allocator_url 申请租约
+    Remote,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LeaseRequest {
+    // 同一个 holder 重复申请会拿回原来的租约
+    pub holder: String,
+    // 连续端口数
+    pub count: u16,
+    // frps 的公网地址，和端口一起登记
+    pub public_addr: String,
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PortLease {
+    pub lease_id: String,
+    pub holder: String,
+    pub start_port: u16,
+    pub count: u16,
+    pub public_addr: String,
+    pub expires_at: u64,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct RenewRequest {
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+}
+
+// 一条隧道需要的整段端口，连同 frps 的公网地址一起登记
+pub fn lease_request(config: &AppConfig, index: u8) -> LeaseRequest {
+    LeaseRequest {
+        holder: format!("node-{}", config.tunnel_label(index)),
+        count: GAME_PORT_SLOTS,
+        public_addr: config.frps_addr.clone(),
+        ttl_secs: Some(config.port_lease_ttl),
+    }
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// 控制面模式下由本进程分配 [min, max] 内的端口段
+pub struct PortAllocator {
+    min: u16,
+    max: u16,
+    default_ttl: u64,
+    next_id: u64,
+    leases: HashMap<String, PortLease>,
+}
+
+impl PortAllocator {
+    pub fn new(min: u16, max: u16, default_ttl: u64) -> Self {
+        PortAllocator { min, max, default_ttl, next_id: 0, leases: HashMap::new() }
+    }
+
+    fn purge_expired(&mut self, now: u64) {
+        self.leases.retain(|_, lease| lease.expires_at > now);
+    }
+
+    pub fn acquire(&mut self, request: &LeaseRequest, now: u64) -> anyhow::Result<PortLease> {
+        if request.count == 0 {
+            bail!("count must be at least 1");
+        }
+        self.purge_expired(now);
+        let expires_at = now + request.ttl_secs.unwrap_or(self.default_ttl);
+
+        if let Some(lease) = self.leases.values_mut().find(|l| l.holder == request.holder) {
+            if lease.count == request.count {
+                lease.expires_at = expires_at;
+                lease.public_addr = request.public_addr.clone();
+                return Ok(lease.clone());
+            }
+            let lease_id = lease.lease_id.clone();
+            self.leases.remove(&lease_id);
+        }
+
+        // 首次适配：找最低的一段空闲端口
+        let mut taken: Vec<(u32, u32)> = self.leases.values()
+            .map(|l| (l.start_port as u32, l.start_port as u32 + l.count as u32))
+            .collect();
+        taken.sort();
+        let mut start = self.min as u32;
+        for (taken_start, taken_end) in taken {
+            if start + request.count as u32 <= taken_start {
+                break;
+            }
+            start = start.max(taken_end);
+        }
+        if start + request.count as u32 > self.max as u32 + 1 {
+            bail!("no {} free ports left in {}..={}", request.count, self.min, self.max);
+        }
+
+        self.next_id += 1;
+        let lease = PortLease {
+            lease_id: format!("{}-{}", request.holder, self.next_id),
+            holder: request.holder.clone(),
+            start_port: start as u16,
+            count: request.count,
+            public_addr: request.public_addr.clone(),
+            expires_at,
+        };
+        self.leases.insert(lease.lease_id.clone(), lease.clone());
+        Ok(lease)
+    }
+
+    pub fn renew(&mut self, lease_id: &str, ttl_secs: Option<u64>, now: u64) -> Option<PortLease> {
+        self.purge_expired(now);
+        let ttl = ttl_secs.unwrap_or(self.default_ttl);
+        let lease = self.leases.get_mut(lease_id)?;
+        lease.expires_at = now + ttl;
+        Some(lease.clone())
+    }
+
+    pub fn release(&mut self, lease_id: &str) -> bool {
+        self.leases.remove(lease_id).is_some()
+    }
+
+    pub fn list(&mut self, now: u64) -> Vec<PortLease> {
+        self.purge_expired(now);
+        let mut leases: Vec<PortLease> = self.leases.values().cloned().collect();
+        leases.sort_by_key(|l| l.start_port);
+        leases
+    }
+}
+
+// data server 或控制面 game_master 的租约接口：
+// POST {base}/port_leases, POST {base}/port_leases/{id}/renew, DELETE {base}/port_leases/{id}
+pub struct LeaseClient {
+    client: DataServerClient,
+}
+
+impl LeaseClient {
+    // 沿用 data server 的认证、超时和重试设置
+    pub fn from_config(config: &AppConfig) -> anyhow::Result<Self> {
+        Ok(LeaseClient { client: DataServerClient::with_base_url(config, &config.port_allocator_base_url()?)? })
+    }
+
+    pub async fn acquire(&self, request: &LeaseRequest) -> anyhow::Result<PortLease> {
+        let response = self.client.send(Method::POST, "/port_leases", &[], Some(&serde_json::to_value(request)?)).await?;
+        Ok(response.json::<PortLease>().await?)
+    }
+
+    // 租约已过期或不存在时返回 None，需要重新申请
+    pub async fn renew(&self, lease_id: &str, ttl_secs: Option<u64>) -> anyhow::Result<Option<PortLease>> {
+        let body = serde_json::json!({ "ttl_secs": ttl_secs });
+        match self.client.send(Method::POST, &format!("/port_leases/{}/renew", lease_id), &[], Some(&body)).await {
+            Ok(response) => Ok(Some(response.json::<PortLease>().await?)),
+            Err(DataServerError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // 续约；租约已失效时重新申请，端口可能和原来不同
+    pub async fn renew_or_acquire(&self, lease: Option<&PortLease>, request: &LeaseRequest) -> anyhow::Result<PortLease> {
+        if let Some(lease) = lease && let Some(renewed) = self.renew(&lease.lease_id, request.ttl_secs).await? {
+            return Ok(renewed);
+        }
+        self.acquire(request).await
+    }
+
+    pub async fn release(&self, lease_id: &str) -> anyhow::Result<()> {
+        match self.client.send(Method::DELETE, &format!("/port_leases/{}", lease_id), &[], None).await {
+            Ok(_) | Err(DataServerError::NotFound(_)) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use axum::extract::Path;
+    use axum::http::StatusCode;
+    use axum::routing::{delete, post};
+    use axum::{Json, Router};
+    use tokio::sync::Mutex;
+    use crate::config_util::AppConfig;
+    use crate::lease_util::{LeaseClient, LeaseRequest, PortAllocator, PortLease};
+
+    fn request(holder: &str, count: u16) -> LeaseRequest {
+        LeaseRequest { holder: holder.to_string(), count, public_addr: "1.2.3.4".to_string(), ttl_secs: None }
+    }
+
+    #[test]
+    fn acquire_first_fit_test() {
+        let mut allocator = PortAllocator::new(30000, 30019, 60);
+        let a = allocator.acquire(&request("node-0", 6), 0).unwrap();
+        let b = allocator.acquire(&request("node-1", 6), 0).unwrap();
+        assert_eq!((a.start_port, b.start_port), (30000, 30006));
+
+        // 同一个 holder 拿回原租约
+        assert_eq!(allocator.acquire(&request("node-0", 6), 10).unwrap().lease_id, a.lease_id);
+
+        assert!(allocator.release(&a.lease_id));
+        let c = allocator.acquire(&request("node-2", 6), 10).unwrap();
+        assert_eq!(c.start_port, 30000);
+
+        // 剩下 30012..=30019 只有 8 个
+        assert!(allocator.acquire(&request("node-3", 10), 10).is_err());
+        assert_eq!(allocator.acquire(&request("node-3", 8), 10).unwrap().start_port, 30012);
+    }
+
+    #[test]
+    fn expire_and_renew_test() {
+        let mut allocator = PortAllocator::new(30000, 30005, 60);
+        let a = allocator.acquire(&request("node-0", 6), 0).unwrap();
+        assert!(allocator.acquire(&request("node-1", 6), 30).is_err());
+
+        assert_eq!(allocator.renew(&a.lease_id, None, 50).unwrap().expires_at, 110);
+        assert!(allocator.acquire(&request("node-1", 6), 100).is_err());
+
+        // 过期后端口可以再分配，旧租约不能续
+        let b = allocator.acquire(&request("node-1", 6), 111).unwrap();
+        assert_eq!(b.start_port, 30000);
+        assert!(allocator.renew(&a.lease_id, None, 111).is_none());
+        assert_eq!(allocator.list(111).len(), 1);
+    }
+
+    // 模拟 data server 的租约接口；续约一律 404，模拟租约已被回收
+    async fn stub_allocator() -> (String, Arc<Mutex<PortAllocator>>) {
+        let allocator = Arc::new(Mutex::new(PortAllocator::new(31000, 31099, 60)));
+        let (acquire, release) = (allocator.clone(), allocator.clone());
+        let app = Router::new()
+            .route("/api/game_master/port_leases", post(move |Json(request): Json<LeaseRequest>| async move {
+                acquire.lock().await.acquire(&request, 0).map(Json).map_err(|_| StatusCode::CONFLICT)
+            }))
+            .route("/api/game_master/port_leases/{id}/renew", post(|| async { StatusCode::NOT_FOUND }))
+            .route("/api/game_master/port_leases/{id}", delete(move |Path(id): Path<String>| async move {
+                if release.lock().await.release(&id) { StatusCode::OK } else { StatusCode::NOT_FOUND }
+            }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}/api/game_master/", addr), allocator)
+    }
+
+    #[tokio::test]
+    async fn lease_client_test() {
+        let (base_url, allocator) = stub_allocator().await;
+        let config = AppConfig { port_allocator_url: base_url, ..AppConfig::default() };
+        let client = LeaseClient::from_config(&config).unwrap();
+
+        let lease: PortLease = client.acquire(&request("node-1", 6)).await.unwrap();
+        assert_eq!((lease.start_port, lease.public_addr.as_str()), (31000, "1.2.3.4"));
+
+        // 续约失败时重新申请，同一个 holder 拿回同一段端口
+        let renewed = client.renew_or_acquire(Some(&lease), &request("node-1", 6)).await.unwrap();
+        assert_eq!(renewed.lease_id, lease.lease_id);
+
+        client.release(&lease.lease_id).await.unwrap();
+        assert!(allocator.lock().await.list(0).is_empty());
+        // 已经释放的租约再释放不算错误
+        client.release(&lease.lease_id).await.unwrap();
+    }
+}
@@ -7,12 +7,31 @@ mod data_server_util;
 mod error;
 mod s3;
 mod archive;
+mod savefile_util;
+mod preset_util;
+mod telnet_util;
+mod serveradmin_util;
+mod mod_util;
+mod job_util;
+mod steamcmd_util;
+mod installation_util;
+mod config_util;
+mod frpc_supervisor_util;
+mod frpc_admin_util;
+mod lease_util;
+mod tunnel_util;
+mod registration_util;
+mod storage_util;
+mod webhook_util;
+mod auth_util;
+#[cfg(test)]
+mod mock_data_server_util;
 
 use axum::{extract::{
     ws::{Message, WebSocket, WebSocketUpgrade},
     State,
 }, response::IntoResponse, routing::get, Json, Router};
-use std::{sync::Arc, time::Duration};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 use tokio::sync::Mutex;
 use axum::extract::{ws, Query};
 use axum::http::StatusCode;
@@ -23,155 +42,614 @@ use tokio::fs;
 use tokio::process::{Child, Command};
 use tokio::sync::{broadcast};
 use tokio::time::sleep;
-use crate::archive::{unzip, zip};
-use crate::common::get_index;
-use crate::const_value::{FRPC_TOML_PATH, SEVENDAYS_SERVER_SAVEFILE_PARENT_PATH, SEVENDAYS_SERVER_SAVEFILE_PATH, SEVENDAYS_STOP_TIME, TCP_LOCAL_PORT, TEMP_DIR, TEMP_SEVENDAYS_SAVEFILE_ZIP, UDP_LOCAL_PORT};
-use crate::data_server_util::{get_game_config_by_serverconfig_id, get_savefile_info_by_save_file_id};
+use crate::auth_util::{authorize, Authenticator};
+use crate::common::{discover_index, sha256_file_hex, IndexStrategy};
+use crate::config_util::{load_config, AppConfig};
+use crate::frpc_supervisor_util::{FrpcHealth, FrpcSupervisor};
+use crate::frpc_admin_util::FrpcAdminClient;
+use crate::data_server_util::{DataServerClient, DataServerError, SaveUploadReport};
 use crate::error::AppError;
-use crate::frp_util::{frpc_config_read, frpc_config_reload, frpc_config_reset_by_index, frpc_config_write, Config, FrpcToml};
-use crate::game_config_util::{GameConfigUtil, ServerSettings};
-use crate::gameserver_util::{start_game_server};
-use crate::s3::{download_file, get_rustfs_client, upload_file};
+use crate::frp_util::{allocate_ports, allocate_ports_at, apply_local_ports, check_exposed_settings, check_port_collisions, frpc_config_load, frpc_config_read, frpc_config_reload, frpc_config_reset_by_index, frpc_config_save, frpc_config_write, Config, FrpcToml, PortMapping, Proxy};
+use crate::game_config_util::{diff_settings, GameConfigUtil, ParsedServerConfig, ServerSettings, SettingChange};
+use crate::gameserver_util::{follow_log, game_server_exe, parse_log_line, start_game_server};
+use crate::storage_util::ObjectStorage;
+use crate::preset_util::{load_preset, resolve_settings, Layer, ResolvedSettings, SettingsLayer};
+use crate::serveradmin_util::{admin_add_command, admin_remove_command, ban_add_command, ban_remove_command,
+                              command_permission_add_command, command_permission_remove_command, is_valid_permission_level, is_valid_token,
+                              read_serveradmin_xml, whitelist_add_command, whitelist_remove_command,
+                              write_serveradmin_xml, AdminUser, BanRequest, CommandPermission, ServerAdmin, WhitelistUser};
+use crate::telnet_util::send_console_command;
+use crate::job_util::{Job, JobRegistry};
+use crate::steamcmd_util::{install_game_server, InstallProgress};
+use crate::mod_util::{download_and_install_mod, read_manifest, remove_mod, set_mod_enabled, sync_mods, ModManifest, ModSpec, InstalledMod};
+use crate::installation_util::{list_installations, GameInstallation, InstallationInfo, DEFAULT_INSTALLATION};
+use crate::savefile_util::{backup_savefile, clear_savefile, parse_game_time, restore_savefile, GameTime};
+use crate::registration_util::{node_id, parse_player_count, resource_usage, Heartbeat, NodeClient, NodeRegistration, NodeState, GAME_MASTER_VERSION};
+use crate::frpc_supervisor_util::backoff_delay;
+use crate::tunnel_util::{tunnel_provider, PublicEndpoint, TunnelProvider, TunnelProviderKind, TunnelStatus};
+use crate::webhook_util::{game_log_event, EventKind, WebhookDispatcher};
+use crate::lease_util::{lease_request, now_secs, LeaseClient, LeaseRequest, PortAllocator, PortLease, PortLeaseMode, RenewRequest};
 
 struct MasterState {
     gamer_server_running: bool,
-    index: u8,
+    // 编号未知时（发现失败或等待 data server 分配）为 None
+    index: Option<u8>,
+    index_error: Option<String>,
     seven_days_child: Option<Child>,
     days7_pid: Option<u32>,
+    game_settings: ServerSettings,
+    installing: bool,
+    jobs: JobRegistry,
+    // 当前/最近一次启动使用的游戏安装
+    installation: GameInstallation,
+    config: Arc<AppConfig>,
+    frpc: FrpcSupervisor,
+    tunnel: Arc<dyn TunnelProvider>,
+    // port_lease_mode = remote 时当前持有的远端端口租约
+    lease: Option<PortLease>,
+    events: WebhookDispatcher,
+}
+
+fn game_installation(config: &AppConfig, key: Option<&str>) -> Result<GameInstallation, AppError> {
+    GameInstallation::new(&config.sevendays_server_path, &config.sevendays_installs_path, key.unwrap_or(DEFAULT_INSTALLATION))
+        .map_err(|e| AppError::InvalidParamError(e.to_string()))
+}
+
+#[derive(Deserialize, Debug)]
+struct InstallationParam {
+    installation: Option<String>,
 }
 
-async fn init() -> anyhow::Result<()> {
-    fs::create_dir_all(TEMP_DIR).await?;
+async fn init(app_config: &AppConfig) -> anyhow::Result<()> {
+    fs::create_dir_all(&app_config.temp_dir).await?;
     Ok(())
 }
 
+// 按节点编号（或租到的远端端口）建立隧道，返回租约
+async fn configure_tunnel(app_config: &AppConfig, tunnel: &dyn TunnelProvider, index: u8) -> anyhow::Result<Option<PortLease>> {
+    let lease = match app_config.port_lease_mode {
+        PortLeaseMode::Static => None,
+        PortLeaseMode::Remote => {
+            let client = LeaseClient::from_config(app_config)?;
+            let lease = client.acquire(&lease_request(app_config, index)).await?;
+            println!("leased remote ports {}..{} on {} as {}", lease.start_port, lease.start_port + lease.count, lease.public_addr, lease.lease_id);
+            Some(lease)
+        }
+    };
+    tunnel.configure(&tunnel_port_mappings(app_config, index, lease.as_ref())).await?;
+    Ok(lease)
+}
+
+fn tunnel_port_mappings(app_config: &AppConfig, index: u8, lease: Option<&PortLease>) -> Vec<PortMapping> {
+    match lease {
+        Some(lease) => allocate_ports_at(app_config, &app_config.tunnel_label(index), lease.start_port),
+        None => allocate_ports(app_config, index),
+    }
+}
+
+// 每 ttl/3 续约一次；租约被回收后重新申请，端口变了就重写隧道
+async fn keep_port_lease(masterstate: Arc<Mutex<MasterState>>) {
+    loop {
+        let (app_config, tunnel, index, lease) = {
+            let state = masterstate.lock().await;
+            (state.config.clone(), state.tunnel.clone(), state.index, state.lease.clone())
+        };
+        sleep(Duration::from_secs(app_config.port_lease_ttl / 3)).await;
+        let Some(index) = index else {
+            continue;
+        };
+        let client = match LeaseClient::from_config(&app_config) {
+            Ok(client) => client,
+            Err(e) => {
+                eprintln!("port allocator error: {}", e);
+                continue;
+            }
+        };
+        match client.renew_or_acquire(lease.as_ref(), &lease_request(&app_config, index)).await {
+            Ok(renewed) => {
+                if lease.as_ref().map(|l| l.start_port) != Some(renewed.start_port) {
+                    println!("remote ports moved to {}..{}", renewed.start_port, renewed.start_port + renewed.count);
+                    let ports = tunnel_port_mappings(&app_config, index, Some(&renewed));
+                    if let Err(e) = tunnel.configure(&ports).await {
+                        eprintln!("configure tunnel error: {}", e);
+                    }
+                }
+                masterstate.lock().await.lease = Some(renewed);
+            }
+            Err(e) => eprintln!("renew port lease error: {}", e),
+        }
+    }
+}
+
+async fn node_registration(masterstate: &Arc<Mutex<MasterState>>, node_id: &str) -> NodeRegistration {
+    let (config, tunnel, index, lease, installation) = {
+        let state = masterstate.lock().await;
+        (state.config.clone(), state.tunnel.clone(), state.index, state.lease.clone(), state.installation.clone())
+    };
+    let ports = index.map(|index| tunnel_port_mappings(&config, index, lease.as_ref())).unwrap_or_default();
+    NodeRegistration {
+        node_id: node_id.to_string(),
+        index,
+        version: GAME_MASTER_VERSION.to_string(),
+        listen_addr: config.listen_addr.clone(),
+        tunnel_provider: tunnel.kind(),
+        public_host: tunnel.public_host(),
+        endpoints: tunnel.public_endpoints(&ports),
+        ports,
+        game_version: installation.version().await,
+    }
+}
+
+async fn node_heartbeat(masterstate: &Arc<Mutex<MasterState>>) -> Heartbeat {
+    let (config, tunnel, index, lease, state, settings) = {
+        let state = masterstate.lock().await;
+        let node_state = if state.index.is_none() {
+            NodeState::Starting
+        } else if state.installing {
+            NodeState::Installing
+        } else if state.gamer_server_running {
+            NodeState::Running
+        } else {
+            NodeState::Idle
+        };
+        (state.config.clone(), state.tunnel.clone(), state.index, state.lease.clone(), node_state, state.game_settings.clone())
+    };
+    let player_count = if state == NodeState::Running && settings.telnet_enabled {
+        match send_console_command(settings.telnet_port as u16, &settings.telnet_password, "lp").await {
+            Ok(output) => parse_player_count(&output),
+            Err(e) => {
+                eprintln!("count players error: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let ports = index.map(|index| tunnel_port_mappings(&config, index, lease.as_ref())).unwrap_or_default();
+    Heartbeat { index, state, player_count, resources: resource_usage().await, endpoints: tunnel.public_endpoints(&ports) }
+}
+
+// index_strategy 为 data_server 时用注册返回的编号建立隧道
+async fn assign_index(masterstate: &Arc<Mutex<MasterState>>, assigned: Option<u8>) {
+    let (config, tunnel) = {
+        let mut state = masterstate.lock().await;
+        if state.config.index_strategy != IndexStrategy::DataServer || state.index.is_some() {
+            return;
+        }
+        let Some(index) = assigned else {
+            state.index_error = Some("data server did not assign an index".to_string());
+            return;
+        };
+        println!("index {} assigned by data server", index);
+        state.index = Some(index);
+        state.index_error = None;
+        (state.config.clone(), state.tunnel.clone())
+    };
+    let index = assigned.unwrap_or_default();
+    match configure_tunnel(&config, tunnel.as_ref(), index).await {
+        Ok(lease) => masterstate.lock().await.lease = lease,
+        Err(e) => {
+            eprintln!("configure tunnel error: {}", e);
+            masterstate.lock().await.index_error = Some(format!("configure tunnel: {}", e));
+        }
+    }
+}
+
+// 启动时注册节点，之后定期发心跳；data server 不可用时按退避时间重试，
+// 心跳返回 404（data server 丢了注册信息）时重新注册
+async fn keep_registration(masterstate: Arc<Mutex<MasterState>>, client: NodeClient) {
+    let config = masterstate.lock().await.config.clone();
+    let node_id = node_id(&config);
+    let interval = Duration::from_secs(config.heartbeat_interval);
+    let mut registered = false;
+    let mut failures = 0;
+    loop {
+        let outcome = if registered {
+            client.heartbeat(&node_id, &node_heartbeat(&masterstate).await).await
+        } else {
+            match client.register(&node_registration(&masterstate, &node_id).await).await {
+                Ok(response) => {
+                    println!("registered node {} with data server", node_id);
+                    registered = true;
+                    assign_index(&masterstate, response.index).await;
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            }
+        };
+        let delay = match outcome {
+            Ok(()) => {
+                failures = 0;
+                interval
+            }
+            Err(DataServerError::NotFound(_)) if registered => {
+                println!("data server forgot node {}, register again", node_id);
+                registered = false;
+                Duration::ZERO
+            }
+            Err(e) => {
+                let delay = backoff_delay(failures, Duration::from_secs(1), interval);
+                failures += 1;
+                eprintln!("{} node {} error: {}, retry in {:?}", if registered { "heartbeat" } else { "register" }, node_id, e, delay);
+                delay
+            }
+        };
+        sleep(delay).await;
+    }
+}
+
+// 隧道从正常变为异常时推送 tunnel_down
+async fn watch_tunnel(masterstate: Arc<Mutex<MasterState>>) {
+    let mut healthy = true;
+    loop {
+        let (config, tunnel, index, events) = {
+            let state = masterstate.lock().await;
+            (state.config.clone(), state.tunnel.clone(), state.index, state.events.clone())
+        };
+        sleep(Duration::from_secs(config.heartbeat_interval)).await;
+        if index.is_none() {
+            continue;
+        }
+        let down = match tunnel.status().await {
+            Ok(status) if status.healthy => None,
+            Ok(status) => Some(serde_json::json!({
+                "provider": status.provider,
+                "proxies": status.proxies.iter().filter(|p| !p.is_running()).map(|p| &p.name).collect::<Vec<_>>(),
+            })),
+            Err(e) => Some(serde_json::json!({ "provider": tunnel.kind(), "error": e.to_string() })),
+        };
+        if let Some(data) = &down && healthy {
+            events.emit(EventKind::TunnelDown, index, data.clone()).await;
+        }
+        healthy = down.is_none();
+    }
+}
+
+async fn shutdown_signal() {
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {},
+        _ = terminate => {},
+    }
+    println!("shutting down");
+}
+
 #[tokio::main]
 async fn main() {
     dotenv::dotenv().ok();
-    init().await.expect("Init failed");
-
-    let index = get_index().unwrap();
-    println!("index is {}", index);
-
-    let config = FrpcToml {
-        server_addr: "124.223.27.133".to_string(),
-        server_port: 7000,
-        auth_token: "123456".to_string(),
-        tcp_name: format!("7daysTodieServer-{}", index),
-        tcp_remote_port: TCP_LOCAL_PORT + index as u16,
-        udp_name: format!("7daysTodieServerUDP-{}", index),
-        udp_remote_port: UDP_LOCAL_PORT + index as u16,
-        bandwidthLimit: "50KB".to_string()
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let app_config = load_config(&args, &std::env::vars().collect::<HashMap<_, _>>()).unwrap_or_else(|e| {
+        eprintln!("load config error: {}", e);
+        std::process::exit(2);
+    });
+    if let Err(violations) = app_config.validate() {
+        for violation in violations {
+            eprintln!("invalid config {}: {}", violation.field, violation.message);
+        }
+        std::process::exit(2);
+    }
+    println!("config: {:#?}", app_config.redacted());
+    let app_config = Arc::new(app_config);
+    init(&app_config).await.expect("Init failed");
+
+    let (index, index_error) = match discover_index(&app_config) {
+        Ok(Some(index)) => {
+            println!("index is {}", index);
+            (Some(index), None)
+        }
+        Ok(None) => {
+            println!("index will be assigned by data server");
+            (None, None)
+        }
+        Err(e) => {
+            eprintln!("discover index by {:?} error: {}", app_config.index_strategy, e);
+            (None, Some(e.to_string()))
+        }
     };
-    let _ = frpc_config_write(&config, FRPC_TOML_PATH).await.unwrap();
-    let res = frpc_config_reload().await.unwrap();
-    if !res.success() {
-        return;
+
+    let frpc = FrpcSupervisor::default();
+    let tunnel = tunnel_provider(app_config.clone(), frpc.clone()).unwrap_or_else(|e| {
+        eprintln!("tunnel provider {:?} error: {}", app_config.tunnel_provider, e);
+        std::process::exit(2);
+    });
+    let mut lease = None;
+    if let Some(index) = index {
+        match configure_tunnel(&app_config, tunnel.as_ref(), index).await {
+            Ok(leased) => lease = leased,
+            Err(e) => {
+                eprintln!("configure tunnel error: {}", e);
+                return;
+            }
+        }
     }
 
     // 初始化GameConfigUtil
-    let settings_data = ServerSettings {
-        server_name: "Local Game Host".to_string(),
-        server_description: "A 7 Days to Die server".to_string(),
-        server_password: "".to_string(),
-        language: "English".to_string(),
-        server_max_player_count: 8,
-        eac_enabled: false,
-        game_difficulty: 1,
-        party_shared_kill_range: 100,
-        player_killing_mode: 3
-    };
+    let mut settings_data = ServerSettings::default();
+    apply_local_ports(&mut settings_data, &app_config);
+    let installation = game_installation(&app_config, None).unwrap_or_else(|_| unreachable!("default installation is valid"));
     let game_config_util = GameConfigUtil::new();
-    game_config_util.set_serverconfig_xml(&settings_data).await.expect("Set serverconfig.xml Error");
+    game_config_util.set_serverconfig_xml(&settings_data, &installation).await.expect("Set serverconfig.xml Error");
+
+    let events = WebhookDispatcher::open(&app_config, &node_id(&app_config)).await.unwrap_or_else(|e| {
+        eprintln!("open webhook outbox {:?} error: {}", app_config.webhook_outbox_path, e);
+        std::process::exit(2);
+    });
+    tokio::spawn(events.clone().run());
 
     // 初始化state
     let masterstate = Arc::new(Mutex::new(
-        MasterState { gamer_server_running: false, index: index, seven_days_child: None, days7_pid: None },
+        MasterState { gamer_server_running: false, index, index_error, seven_days_child: None, days7_pid: None, game_settings: settings_data,
+                      installing: false, jobs: JobRegistry::default(), installation, config: app_config.clone(), frpc, tunnel, lease,
+                      events },
     ));
+    if app_config.port_lease_mode == PortLeaseMode::Remote {
+        tokio::spawn(keep_port_lease(masterstate.clone()));
+    }
+    let node_client = if app_config.node_registration {
+        match NodeClient::from_config(&app_config) {
+            Ok(client) => Some(client),
+            Err(e) => {
+                println!("skip node registration: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    if let Some(client) = node_client {
+        tokio::spawn(keep_registration(masterstate.clone(), client));
+    }
+    if !app_config.webhook_urls.is_empty() {
+        tokio::spawn(watch_tunnel(masterstate.clone()));
+    }
+    // 控制面模式下给其它节点分配远端端口
+    let port_allocator = app_config.port_allocator_enabled.then(|| Arc::new(Mutex::new(
+        PortAllocator::new(app_config.port_allocator_min, app_config.port_allocator_max, app_config.port_lease_ttl))));
+
+    let auth = Authenticator::from_config(&app_config).unwrap_or_else(|e| {
+        eprintln!("auth config error: {}", e);
+        std::process::exit(2);
+    });
 
     let (tx, _rx) = broadcast::channel(100);
     let app = Router::new()
         .route("/hello", get(|| async { "Hello, World!" }))
         .route("/status", get(status))
             .with_state(masterstate.clone())
-        .route("/start_7days", get(start_7days))
+        .route("/start_7days", get(start_7days).post(start_7days_with_body))
             .with_state(masterstate.clone())
         .route("/stop_7days", get(stop_7days))
             .with_state(masterstate.clone())
+        .route("/frpclog", get(frpc_log_handler))
+            .with_state(masterstate.clone())
         .route("/7daysserverlog", get(ws_handler))
             .with_state(Arc::new(AppState { tx }))
+        .route("/serverconfig", get(get_serverconfig))
+            .with_state(masterstate.clone())
+        .route("/serverconfig/diff", post(diff_serverconfig))
+            .with_state(masterstate.clone())
+        .route("/serveradmin", get(get_serveradmin))
+            .with_state(masterstate.clone())
+        .route("/serveradmin/admins", axum::routing::put(put_serveradmin_admin))
+            .with_state(masterstate.clone())
+        .route("/serveradmin/admins/{platform}/{userid}", axum::routing::delete(delete_serveradmin_admin))
+            .with_state(masterstate.clone())
+        .route("/serveradmin/whitelist", axum::routing::put(put_serveradmin_whitelist))
+            .with_state(masterstate.clone())
+        .route("/serveradmin/whitelist/{platform}/{userid}", axum::routing::delete(delete_serveradmin_whitelist))
+            .with_state(masterstate.clone())
+        .route("/serveradmin/blacklist", axum::routing::put(put_serveradmin_blacklist))
+            .with_state(masterstate.clone())
+        .route("/serveradmin/blacklist/{platform}/{userid}", axum::routing::delete(delete_serveradmin_blacklist))
+            .with_state(masterstate.clone())
+        .route("/serveradmin/commands", axum::routing::put(put_serveradmin_command))
+            .with_state(masterstate.clone())
+        .route("/serveradmin/commands/{cmd}", axum::routing::delete(delete_serveradmin_command))
+            .with_state(masterstate.clone())
+        .route("/mods", get(get_mods).post(install_mod))
+            .with_state(masterstate.clone())
+        .route("/mods/{name}", axum::routing::delete(delete_mod))
+            .with_state(masterstate.clone())
+        .route("/mods/{name}/enable", post(enable_mod))
+            .with_state(masterstate.clone())
+        .route("/mods/{name}/disable", post(disable_mod))
+            .with_state(masterstate.clone())
+        .route("/game/installations", get(get_game_installations))
+            .with_state(masterstate.clone())
+        .route("/game/install", post(install_game))
+            .with_state(masterstate.clone())
+        .route("/jobs/{id}", get(get_job))
+            .with_state(masterstate.clone())
+        .route("/config", get(get_config))
+            .with_state(app_config.clone())
+        .route("/tunnel/status", get(tunnel_status))
+            .with_state(masterstate.clone())
+        .route("/tunnel/reload", post(tunnel_reload))
+            .with_state(masterstate.clone())
+        .route("/tunnel/ports", get(tunnel_ports))
+            .with_state(masterstate.clone())
+        .route("/tunnel/config", get(tunnel_config))
+            .with_state(app_config.clone())
+        .route("/tunnel/proxies", axum::routing::put(put_tunnel_proxy))
+            .with_state(app_config.clone())
+        .route("/tunnel/proxies/{name}", axum::routing::delete(delete_tunnel_proxy))
+            .with_state(app_config.clone())
+        .route("/port_leases", get(list_port_leases).post(acquire_port_lease))
+            .with_state(port_allocator.clone())
+        .route("/port_leases/{id}/renew", post(renew_port_lease))
+            .with_state(port_allocator.clone())
+        .route("/port_leases/{id}", axum::routing::delete(release_port_lease))
+            .with_state(port_allocator.clone())
         .route("/get_frpc_toml", get(get_frpc_toml))
+            .with_state(app_config.clone())
         .route("/reset_frpc_toml", post(reset_frpc_toml))
-        .route("/reset_frpc_toml_by_index", post(reset_frpc_toml_by_index));
+            .with_state(app_config.clone())
+        .route("/reset_frpc_toml_by_index", post(reset_frpc_toml_by_index))
+            .with_state(app_config.clone())
+        .layer(axum::middleware::from_fn_with_state(Arc::new(auth), authorize));
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3005")
+    let listener = tokio::net::TcpListener::bind(&app_config.listen_addr)
         .await
         .unwrap();
 
-    println!("start listening on port 3005");
-    axum::serve(listener, app).await.unwrap();
+    println!("start listening on {}", app_config.listen_addr);
+    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+        .with_graceful_shutdown(shutdown_signal()).await.unwrap();
+
+    // 退出前归还租约，端口立即可以分给别的节点
+    let lease = masterstate.lock().await.lease.take();
+    if let Some(lease) = lease {
+        let released = match LeaseClient::from_config(&app_config) {
+            Ok(client) => client.release(&lease.lease_id).await,
+            Err(e) => Err(e),
+        };
+        match released {
+            Ok(()) => println!("released port lease {}", lease.lease_id),
+            Err(e) => eprintln!("release port lease {} error: {}", lease.lease_id, e),
+        }
+    }
+    if app_config.node_registration && let Ok(client) = NodeClient::from_config(&app_config) {
+        let node_id = node_id(&app_config);
+        match client.deregister(&node_id).await {
+            Ok(()) => println!("deregistered node {}", node_id),
+            Err(e) => eprintln!("deregister node {} error: {}", node_id, e),
+        }
+    }
 }
 
 #[derive(Serialize, Debug)]
 struct Status {
-    index: u8,
+    index: Option<u8>,
+    index_error: Option<String>,
     days7server_running: bool,
+    // 未由 game_master 监管时为 None
+    frpc: Option<FrpcHealth>,
+    // 还没送达的 webhook 事件数
+    webhook_pending: usize,
 }
 async fn status(State(masterstate): State<Arc<Mutex<MasterState>>>) -> Result<Json<Status>, AppError> {
-    let index = get_index().unwrap_or_else(|e| 255);
     let state = masterstate.lock().await;
     let status = Status {
-        index,
+        index: state.index,
+        index_error: state.index_error.clone(),
         days7server_running: state.gamer_server_running,
+        frpc: if state.tunnel.kind() == TunnelProviderKind::Frp && state.config.frpc_supervise {
+            Some(state.frpc.health().await)
+        } else {
+            None
+        },
+        webhook_pending: state.events.pending().await.len(),
     };
     Ok(Json(status))
 }
 
 #[derive(Deserialize, Debug)]
 struct Start7DaysParam {
-    serverconfig_id: i32,
-    save_file_id: Option<i32>
+    serverconfig_id: Option<i32>,
+    save_file_id: Option<i32>,
+    preset: Option<String>,
+    #[serde(default)]
+    overrides: SettingsLayer,
+    // 为空时不改动已安装的 mod
+    mods: Option<Vec<ModSpec>>,
+    // 游戏安装版本，默认 "default"
+    installation: Option<String>,
 }
 #[axum::debug_handler]
 async fn start_7days(
     State(masterstate): State<Arc<Mutex<MasterState>>>,
-    Query(params): Query<Start7DaysParam>) -> Result<StatusCode, AppError> {
-    if params.save_file_id.is_some() {
-        println!("start 7days by serverconfig_id: {} save_file_id: {} ...",
-                 params.serverconfig_id,  params.save_file_id.unwrap());
-    } else {
-        println!("start 7days by serverconfig_id: {} ...",
-                 params.serverconfig_id);
+    Query(params): Query<Start7DaysParam>) -> Result<Json<ResolvedSettings>, AppError> {
+    start_7days_with_params(masterstate, params).await
+}
+
+async fn start_7days_with_body(
+    State(masterstate): State<Arc<Mutex<MasterState>>>,
+    Json(params): Json<Start7DaysParam>) -> Result<Json<ResolvedSettings>, AppError> {
+    start_7days_with_params(masterstate, params).await
+}
+
+// 配置叠加顺序（后者覆盖前者）：默认值 < preset < serverconfig_id 对应的 data server 配置 < overrides
+async fn resolve_start_settings(config: &AppConfig, params: &Start7DaysParam) -> Result<ResolvedSettings, AppError> {
+    let data_server = DataServerClient::from_config(config);
+    let mut layers = Vec::new();
+    if let Some(preset) = &params.preset {
+        let values = load_preset(&config.presets_dir, data_server.as_ref().ok(), preset)
+            .await
+            .map_err(|e| AppError::PresetError(e.to_string()))?;
+        layers.push(Layer { name: format!("preset:{}", preset), values, ignore_unknown: false });
     }
+    if let Some(serverconfig_id) = params.serverconfig_id {
+        let values = data_server.as_ref().map_err(|e| e.clone())?.game_config(serverconfig_id).await?;
+        layers.push(Layer { name: format!("serverconfig:{}", serverconfig_id), values, ignore_unknown: true });
+    }
+    layers.push(Layer { name: "override".to_string(), values: params.overrides.clone(), ignore_unknown: false });
+
+    resolve_settings(&layers).map_err(AppError::InvalidServerSettings)
+}
+
+async fn start_7days_with_params(
+    masterstate: Arc<Mutex<MasterState>>,
+    params: Start7DaysParam) -> Result<Json<ResolvedSettings>, AppError> {
+    println!("start 7days by serverconfig_id: {:?} save_file_id: {:?} preset: {:?} ...",
+             params.serverconfig_id, params.save_file_id, params.preset);
+
+    let config = {
+        let state = masterstate.lock().await;
+        if state.gamer_server_running {
+            return Err(AppError::GameIsRunning);
+        }
+        if state.installing {
+            return Err(AppError::GameIsInstalling);
+        }
+        state.config.clone()
+    };
 
+    let installation = game_installation(&config, params.installation.as_deref())?;
+    if !game_server_exe(&config, &installation).is_file() {
+        return Err(AppError::NotFoundError(format!("installation {} at {:?}", installation.key, installation.server_path)));
+    }
 
     // // 配置serverconfig.xml
-    let game_config = get_game_config_by_serverconfig_id(params.serverconfig_id)
-        .await
-        .map_err(|e| AppError::DataServerFucError(e.to_string()))?;
+    let mut resolved = resolve_start_settings(&config, &params).await?;
+    for field in apply_local_ports(&mut resolved.settings, &config) {
+        println!("{} is fixed by the node tunnel config", field);
+        resolved.sources.insert(field.to_string(), "node".to_string());
+    }
+    check_exposed_settings(&resolved.settings, &config).map_err(AppError::InvalidServerSettings)?;
+    let game_config = resolved.settings.clone();
+
+    if let Some(mods) = &params.mods {
+        sync_mods(&installation.server_path, &config.temp_dir, &ObjectStorage::from_config(&config), mods)
+            .await
+            .map_err(|e| AppError::ModError(e.to_string()))?;
+    }
 
     if (params.save_file_id.is_some()) {
         // 拉取存档
-        let savefile_info = get_savefile_info_by_save_file_id(params.save_file_id.unwrap())
-            .await
-            .map_err(|e| AppError::DataServerFucError(e.to_string()))?;
-        let s3client = get_rustfs_client(Some(savefile_info.host)).await.map_err(|e| AppError::GetS3ClientError(e.to_string()))?;
-        let filepath = format!("{}/{}", TEMP_DIR, savefile_info.name);
-        let _ = download_file(&s3client, filepath.as_str(), savefile_info.bucket_name.as_str(),
-                              format!("{}/{}", savefile_info.user_id, savefile_info.name).as_str())
+        let savefile_info = DataServerClient::from_config(&config)?.savefile_info(params.save_file_id.unwrap()).await?;
+        let filepath = config.temp_dir.join(&savefile_info.name);
+        ObjectStorage::from_config(&config)
+            .download(Some(savefile_info.host), &savefile_info.bucket_name, &format!("{}/{}", savefile_info.user_id, savefile_info.name), &filepath)
             .await
             .map_err(|e| AppError::DownloadError(e.to_string()))?;
 
-        let _ = fs::remove_dir_all(SEVENDAYS_SERVER_SAVEFILE_PATH).await;
-        unzip(filepath.as_str(), SEVENDAYS_SERVER_SAVEFILE_PATH)
+        restore_savefile(&filepath.to_string_lossy(), &installation.saves_path(), &game_config)
             .map_err(|e| AppError::UnzipError(e.to_string()))?;
     } else {
-        let _ = fs::remove_dir_all(SEVENDAYS_SERVER_SAVEFILE_PATH).await;
+        clear_savefile(&installation.saves_path(), &game_config)
+            .map_err(|e| AppError::SavefileError(e.to_string()))?;
     }
 
     // 磁盘io操作
     let game_config_util = GameConfigUtil::new();
-    game_config_util.set_serverconfig_xml(&game_config).await.map_err(|e| AppError::SetServerConfigXmlErrror(e.to_string()))?;
+    game_config_util.set_serverconfig_xml(&game_config, &installation).await?;
 
     // 启动7days
     // 获取state
@@ -180,27 +658,72 @@ async fn start_7days(
         if state.gamer_server_running {
             return Err(AppError::GameIsRunning);
         }
+        // 准备存档和配置期间可能有安装任务开始；在同一把锁里标记运行，之后的安装请求会被拒绝
+        if state.installing {
+            return Err(AppError::GameIsInstalling);
+        }
+        state.gamer_server_running = true;
+        state.game_settings = game_config.clone();
+        state.installation = installation.clone();
     }
 
+    let exe = game_server_exe(&config, &installation);
     let masterstate2 = masterstate.clone();
     tokio::spawn(async move {
-        let mut child = start_game_server().expect("Failed to start game server");
+        let (events, index) = {
+            let state = masterstate2.lock().await;
+            (state.events.clone(), state.index)
+        };
+        // 上次运行留下的日志里也有 StartGame done
+        let _ = fs::remove_file(installation.log_path()).await;
+        let mut child = match start_game_server(&exe, &installation) {
+            Ok(child) => child,
+            Err(e) => {
+                eprintln!("Failed to start game server: {}", e);
+                masterstate2.lock().await.gamer_server_running = false;
+                events.emit(EventKind::ServerCrashed, index, serde_json::json!({ "installation": installation.key, "error": e.to_string() })).await;
+                return;
+            }
+        };
         {
             let mut state = masterstate2.lock().await;
-            state.gamer_server_running = true;
             state.days7_pid = child.id();
             println!("Game server state set to running.");
         }
-        child.wait().await.expect("Failed to wait game server");
-        {
+
+        // 从游戏日志里识别启动完成和玩家进出
+        let (log_tx, mut log_rx) = tokio::sync::mpsc::unbounded_channel();
+        let log_follower = tokio::spawn(follow_log(installation.log_path(), move |line| {
+            if let Some(event) = parse_log_line(line) {
+                let _ = log_tx.send(event);
+            }
+        }));
+        let log_events = events.clone();
+        tokio::spawn(async move {
+            while let Some(event) = log_rx.recv().await {
+                let (kind, data) = game_log_event(&event);
+                log_events.emit(kind, index, data).await;
+            }
+        });
+
+        let exit_status = match child.wait().await {
+            Ok(status) => status.to_string(),
+            Err(e) => format!("wait error: {}", e),
+        };
+        log_follower.abort();
+        let stopped = {
             let mut state = masterstate2.lock().await;
             state.gamer_server_running = false;
             state.seven_days_child = None;
             println!("Game server shutdown");
+            // stop_7days 会先取走 pid，否则是游戏自己退出
+            state.days7_pid.take().is_none()
         };
+        let kind = if stopped { EventKind::ServerStopped } else { EventKind::ServerCrashed };
+        events.emit(kind, index, serde_json::json!({ "installation": installation.key, "exit_status": exit_status })).await;
     });
 
-    Ok(StatusCode::OK)
+    Ok(Json(resolved))
 }
 
 #[derive(Deserialize, Debug)]
@@ -213,9 +736,12 @@ async fn stop_7days(
     State(masterstate): State<Arc<Mutex<MasterState>>>) -> Result<StatusCode, AppError> {
     println!("stop 7days ...");
 
+    // 停服前读取游戏内时间，随存档信息回报给 data server
+    let game_time = if params.save_file_id.is_some() { current_game_time(&masterstate).await } else { None };
+
     // 启动7days
     // 获取state
-    {
+    let (game_settings, installation, config, events, index) = {
         let mut state = masterstate.lock().await;
         if !state.gamer_server_running {
             return Ok(StatusCode::OK);
@@ -229,29 +755,524 @@ async fn stop_7days(
             .map_err(|e| AppError::KillCommandError(e.to_string()))?;
         let _ = cmd.wait().await.map_err(|e| AppError::KillCommandError(e.to_string()))?;
         println!("Send kill command to {} prrocess", pid);
+        (state.game_settings.clone(), state.installation.clone(), state.config.clone(), state.events.clone(), state.index)
+    };
+    sleep(Duration::from_secs(config.sevendays_stop_time)).await;
+
+    if let Some(save_file_id) = params.save_file_id {
+        match upload_savefile(&config, &installation, &game_settings, save_file_id, game_time).await {
+            Ok(report) => events.emit(EventKind::BackupUploaded, index, serde_json::to_value(&report).unwrap_or_default()).await,
+            Err(e) => {
+                events.emit(EventKind::BackupFailed, index, serde_json::json!({ "save_file_id": save_file_id, "error": format!("{:?}", e) })).await;
+                return Err(e);
+            }
+        }
     }
-    sleep(Duration::from_secs(SEVENDAYS_STOP_TIME)).await;
 
-    if (params.save_file_id.is_some()) {
-        let savefile_info = get_savefile_info_by_save_file_id(params.save_file_id.unwrap())
-            .await
-            .map_err(|e| AppError::DataServerFucError(e.to_string()))?;
-        let s3client = get_rustfs_client(Some(savefile_info.host)).await.map_err(|e| AppError::GetS3ClientError(e.to_string()))?;
-
-        // IO
-        let _ = fs::remove_dir_all(TEMP_SEVENDAYS_SAVEFILE_ZIP).await;
-        zip(SEVENDAYS_SERVER_SAVEFILE_PATH, TEMP_SEVENDAYS_SAVEFILE_ZIP).map_err(|e| AppError::ZipError(e.to_string()))?;
-        let _ = upload_file(&s3client, TEMP_SEVENDAYS_SAVEFILE_ZIP, savefile_info.bucket_name.as_str(),
-                            format!("{}/{}", savefile_info.user_id, savefile_info.name).as_str())
+    Ok(StatusCode::OK)
+}
+
+// 控制台 gettime；游戏未运行或 telnet 不可用时为 None
+async fn current_game_time(masterstate: &Arc<Mutex<MasterState>>) -> Option<GameTime> {
+    let settings = {
+        let state = masterstate.lock().await;
+        if !state.gamer_server_running || !state.game_settings.telnet_enabled {
+            return None;
+        }
+        state.game_settings.clone()
+    };
+    match send_console_command(settings.telnet_port as u16, &settings.telnet_password, "gettime").await {
+        Ok(output) => parse_game_time(&output),
+        Err(e) => {
+            eprintln!("get game time error: {}", e);
+            None
+        }
+    }
+}
+
+// 打包上传存档，再把存档信息回报给 data server
+async fn upload_savefile(config: &AppConfig, installation: &GameInstallation, game_settings: &ServerSettings,
+                         save_file_id: i32, game_time: Option<GameTime>) -> Result<SaveUploadReport, AppError> {
+    let client = DataServerClient::from_config(config)?;
+    let savefile_info = client.savefile_info(save_file_id).await?;
+
+    // IO
+    let zip_path = config.savefile_zip_path();
+    let game_version = installation.version().await;
+    let location = backup_savefile(&installation.saves_path(), game_settings, game_version.clone(), &zip_path.to_string_lossy())
+        .map_err(|e| AppError::ZipError(e.to_string()))?;
+    let size_bytes = fs::metadata(&zip_path).await.map_err(AppError::IOError)?.len();
+    let sha256 = sha256_file_hex(&zip_path).map_err(AppError::IOError)?;
+    let object_key = format!("{}/{}", savefile_info.user_id, savefile_info.name);
+    ObjectStorage::from_config(config)
+        .upload(Some(savefile_info.host), &savefile_info.bucket_name, &object_key, &zip_path)
+        .await
+        .map_err(|e| AppError::UploadError(e.to_string()))?;
+    let report = SaveUploadReport {
+        save_file_id,
+        bucket_name: savefile_info.bucket_name,
+        object_key,
+        size_bytes,
+        sha256,
+        game_time,
+        game_version,
+        world: location.world_dir,
+        game_name: location.game_name,
+        uploaded_at: now_secs(),
+    };
+    // 存档已经上传，回报失败只记录日志
+    if let Err(e) = client.report_savefile_upload(&report).await {
+        eprintln!("report savefile {} upload error: {}", save_file_id, e);
+    }
+    Ok(report)
+}
+
+// 未指定 installation 时使用最近一次启动的安装
+async fn requested_installation(masterstate: &Arc<Mutex<MasterState>>, params: &InstallationParam) -> Result<GameInstallation, AppError> {
+    let state = masterstate.lock().await;
+    match &params.installation {
+        Some(key) => game_installation(&state.config, Some(key)),
+        None => Ok(state.installation.clone()),
+    }
+}
+
+async fn get_serverconfig(
+    State(masterstate): State<Arc<Mutex<MasterState>>>,
+    Query(params): Query<InstallationParam>) -> Result<Json<ParsedServerConfig>, AppError> {
+    let installation = requested_installation(&masterstate, &params).await?;
+    let parsed = GameConfigUtil::new().read_serverconfig_xml(&installation)
+        .await
+        .map_err(|e| AppError::ConfigReadError(e.to_string()))?;
+    Ok(Json(parsed))
+}
+
+#[derive(Serialize, Debug)]
+struct ServerConfigDiff {
+    current: ParsedServerConfig,
+    diff: Vec<SettingChange>,
+}
+async fn diff_serverconfig(
+    State(masterstate): State<Arc<Mutex<MasterState>>>,
+    Query(params): Query<InstallationParam>,
+    Json(proposed): Json<ServerSettings>) -> Result<Json<ServerConfigDiff>, AppError> {
+    let installation = requested_installation(&masterstate, &params).await?;
+    let current = GameConfigUtil::new().read_serverconfig_xml(&installation)
+        .await
+        .map_err(|e| AppError::ConfigReadError(e.to_string()))?;
+    let diff = diff_settings(&current.settings, &proposed)
+        .map_err(|e| AppError::ConfigReadError(e.to_string()))?;
+    Ok(Json(ServerConfigDiff { current, diff }))
+}
+
+fn serveradmin_path(state: &MasterState) -> std::path::PathBuf {
+    state.installation.saves_path().join(&state.game_settings.admin_file_name)
+}
+
+async fn get_serveradmin(State(masterstate): State<Arc<Mutex<MasterState>>>) -> Result<Json<ServerAdmin>, AppError> {
+    let path = serveradmin_path(&*masterstate.lock().await);
+    let admin = read_serveradmin_xml(&path)
+        .await
+        .map_err(|e| AppError::ServerAdminError(e.to_string()))?;
+    Ok(Json(admin))
+}
+
+fn check_tokens(tokens: &[&str]) -> Result<(), AppError> {
+    match tokens.iter().find(|t| !is_valid_token(t)) {
+        Some(t) => Err(AppError::InvalidParamError(format!("{:?} is not a valid id", t))),
+        None => Ok(()),
+    }
+}
+
+fn check_permission_level(level: i32) -> Result<(), AppError> {
+    if !is_valid_permission_level(level) {
+        return Err(AppError::InvalidParamError(format!("permission_level {} is not in 0..=1000", level)));
+    }
+    Ok(())
+}
+
+// 运行中通过控制台命令生效，停止时直接改文件
+async fn apply_serveradmin_change(
+    masterstate: Arc<Mutex<MasterState>>,
+    command: String,
+    change: impl FnOnce(&mut ServerAdmin) -> bool) -> Result<StatusCode, AppError> {
+    let state = masterstate.lock().await;
+    if state.gamer_server_running {
+        let settings = state.game_settings.clone();
+        drop(state);
+        if !settings.telnet_enabled {
+            return Err(AppError::ConsoleCommandError("telnet is disabled".to_string()));
+        }
+        let output = send_console_command(settings.telnet_port as u16, &settings.telnet_password, &command)
             .await
-            .map_err(|e| AppError::DownloadError(e.to_string()))?;
+            .map_err(|e| AppError::ConsoleCommandError(e.to_string()))?;
+        println!("console output: {}", output);
+        return Ok(StatusCode::OK);
+    }
+
+    let path = serveradmin_path(&state);
+    let mut admin = read_serveradmin_xml(&path)
+        .await
+        .map_err(|e| AppError::ServerAdminError(e.to_string()))?;
+    if !change(&mut admin) {
+        return Err(AppError::NotFoundError(command));
+    }
+    write_serveradmin_xml(&path, &admin)
+        .await
+        .map_err(|e| AppError::ServerAdminError(e.to_string()))?;
+    Ok(StatusCode::OK)
+}
+
+async fn put_serveradmin_admin(
+    State(masterstate): State<Arc<Mutex<MasterState>>>,
+    Json(admin): Json<AdminUser>) -> Result<StatusCode, AppError> {
+    check_tokens(&[&admin.platform, &admin.userid])?;
+    check_permission_level(admin.permission_level)?;
+    let command = admin_add_command(&admin);
+    apply_serveradmin_change(masterstate, command, |a| { a.upsert_admin(admin); true }).await
+}
+
+async fn delete_serveradmin_admin(
+    State(masterstate): State<Arc<Mutex<MasterState>>>,
+    axum::extract::Path((platform, userid)): axum::extract::Path<(String, String)>) -> Result<StatusCode, AppError> {
+    check_tokens(&[&platform, &userid])?;
+    let command = admin_remove_command(&platform, &userid);
+    apply_serveradmin_change(masterstate, command, |a| a.remove_admin(&platform, &userid)).await
+}
+
+async fn put_serveradmin_whitelist(
+    State(masterstate): State<Arc<Mutex<MasterState>>>,
+    Json(user): Json<WhitelistUser>) -> Result<StatusCode, AppError> {
+    check_tokens(&[&user.platform, &user.userid])?;
+    let command = whitelist_add_command(&user);
+    apply_serveradmin_change(masterstate, command, |a| { a.upsert_whitelist(user); true }).await
+}
+
+async fn delete_serveradmin_whitelist(
+    State(masterstate): State<Arc<Mutex<MasterState>>>,
+    axum::extract::Path((platform, userid)): axum::extract::Path<(String, String)>) -> Result<StatusCode, AppError> {
+    check_tokens(&[&platform, &userid])?;
+    let command = whitelist_remove_command(&platform, &userid);
+    apply_serveradmin_change(masterstate, command, |a| a.remove_whitelist(&platform, &userid)).await
+}
+
+async fn put_serveradmin_blacklist(
+    State(masterstate): State<Arc<Mutex<MasterState>>>,
+    Json(ban): Json<BanRequest>) -> Result<StatusCode, AppError> {
+    check_tokens(&[&ban.platform, &ban.userid])?;
+    let command = ban_add_command(&ban);
+    apply_serveradmin_change(masterstate, command, |a| { a.upsert_blacklist(ban.to_entry()); true }).await
+}
+
+async fn delete_serveradmin_blacklist(
+    State(masterstate): State<Arc<Mutex<MasterState>>>,
+    axum::extract::Path((platform, userid)): axum::extract::Path<(String, String)>) -> Result<StatusCode, AppError> {
+    check_tokens(&[&platform, &userid])?;
+    let command = ban_remove_command(&platform, &userid);
+    apply_serveradmin_change(masterstate, command, |a| a.remove_blacklist(&platform, &userid)).await
+}
+
+async fn put_serveradmin_command(
+    State(masterstate): State<Arc<Mutex<MasterState>>>,
+    Json(permission): Json<CommandPermission>) -> Result<StatusCode, AppError> {
+    check_tokens(&[&permission.cmd])?;
+    check_permission_level(permission.permission_level)?;
+    let command = command_permission_add_command(&permission);
+    apply_serveradmin_change(masterstate, command, |a| { a.upsert_command(permission); true }).await
+}
+
+async fn delete_serveradmin_command(
+    State(masterstate): State<Arc<Mutex<MasterState>>>,
+    axum::extract::Path(cmd): axum::extract::Path<String>) -> Result<StatusCode, AppError> {
+    check_tokens(&[&cmd])?;
+    let command = command_permission_remove_command(&cmd);
+    apply_serveradmin_change(masterstate, command, |a| a.remove_command(&cmd)).await
+}
+
+async fn get_mods(
+    State(masterstate): State<Arc<Mutex<MasterState>>>,
+    Query(params): Query<InstallationParam>) -> Result<Json<ModManifest>, AppError> {
+    let installation = requested_installation(&masterstate, &params).await?;
+    let manifest = read_manifest(&installation.server_path).map_err(|e| AppError::ModError(e.to_string()))?;
+    Ok(Json(manifest))
+}
+
+// mod 只在启动时加载，运行中不允许改动
+async fn ensure_game_stopped(masterstate: &Arc<Mutex<MasterState>>) -> Result<(), AppError> {
+    if masterstate.lock().await.gamer_server_running {
+        return Err(AppError::GameIsRunning);
+    }
+    Ok(())
+}
+
+async fn install_mod(
+    State(masterstate): State<Arc<Mutex<MasterState>>>,
+    Query(params): Query<InstallationParam>,
+    Json(spec): Json<ModSpec>) -> Result<Json<InstalledMod>, AppError> {
+    ensure_game_stopped(&masterstate).await?;
+    let installation = requested_installation(&masterstate, &params).await?;
+    let config = masterstate.lock().await.config.clone();
+    let installed = download_and_install_mod(&installation.server_path, &config.temp_dir, &ObjectStorage::from_config(&config), &spec)
+        .await
+        .map_err(|e| AppError::ModError(e.to_string()))?;
+    Ok(Json(installed))
+}
+
+async fn toggle_mod(masterstate: Arc<Mutex<MasterState>>, params: InstallationParam, name: String, enabled: bool) -> Result<StatusCode, AppError> {
+    ensure_game_stopped(&masterstate).await?;
+    let installation = requested_installation(&masterstate, &params).await?;
+    let found = set_mod_enabled(&installation.server_path, &name, enabled).map_err(|e| AppError::ModError(e.to_string()))?;
+    if !found {
+        return Err(AppError::NotFoundError(format!("mod {}", name)));
+    }
+    Ok(StatusCode::OK)
+}
+
+async fn enable_mod(
+    State(masterstate): State<Arc<Mutex<MasterState>>>,
+    Query(params): Query<InstallationParam>,
+    axum::extract::Path(name): axum::extract::Path<String>) -> Result<StatusCode, AppError> {
+    toggle_mod(masterstate, params, name, true).await
+}
+
+async fn disable_mod(
+    State(masterstate): State<Arc<Mutex<MasterState>>>,
+    Query(params): Query<InstallationParam>,
+    axum::extract::Path(name): axum::extract::Path<String>) -> Result<StatusCode, AppError> {
+    toggle_mod(masterstate, params, name, false).await
+}
+
+async fn delete_mod(
+    State(masterstate): State<Arc<Mutex<MasterState>>>,
+    Query(params): Query<InstallationParam>,
+    axum::extract::Path(name): axum::extract::Path<String>) -> Result<StatusCode, AppError> {
+    ensure_game_stopped(&masterstate).await?;
+    let installation = requested_installation(&masterstate, &params).await?;
+    let found = remove_mod(&installation.server_path, &name).map_err(|e| AppError::ModError(e.to_string()))?;
+    if !found {
+        return Err(AppError::NotFoundError(format!("mod {}", name)));
+    }
+    Ok(StatusCode::OK)
+}
+
+async fn get_game_installations(State(masterstate): State<Arc<Mutex<MasterState>>>) -> Result<Json<Vec<InstallationInfo>>, AppError> {
+    let config = masterstate.lock().await.config.clone();
+    let installations = list_installations(&config.sevendays_server_path, &config.sevendays_installs_path)
+        .await
+        .map_err(|e| AppError::InstallError(e.to_string()))?;
+    Ok(Json(installations))
+}
+
+#[derive(Deserialize, Debug)]
+struct InstallGameParam {
+    branch: Option<String>,
+    installation: Option<String>,
+}
+#[derive(Serialize, Debug)]
+struct JobCreated {
+    job_id: u64,
+}
+async fn install_game(
+    State(masterstate): State<Arc<Mutex<MasterState>>>,
+    Json(params): Json<InstallGameParam>) -> Result<Json<JobCreated>, AppError> {
+    let (jobs, installation, config) = {
+        let mut state = masterstate.lock().await;
+        let installation = game_installation(&state.config, params.installation.as_deref())?;
+        if state.gamer_server_running {
+            return Err(AppError::GameIsRunning);
+        }
+        if state.installing {
+            return Err(AppError::GameIsInstalling);
+        }
+        state.installing = true;
+        (state.jobs.clone(), installation, state.config.clone())
+    };
+    let job_id = jobs.create("install_game").await;
+    println!("install 7days {} branch: {:?}, job: {}", installation.key, params.branch, job_id);
+
+    tokio::spawn(async move {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<InstallProgress>();
+        let progress_jobs = jobs.clone();
+        let forward = tokio::spawn(async move {
+            while let Some(progress) = rx.recv().await {
+                progress_jobs.progress(job_id, progress.percent, &progress.state).await;
+            }
+        });
+
+        let result = install_game_server(&config.steamcmd_path, &installation.server_path,
+                                         params.branch.as_deref(), |p| { let _ = tx.send(p); })
+            .await;
+        drop(tx);
+        let _ = forward.await;
+
+        jobs.finish(job_id, result.and_then(|info| Ok(serde_json::to_value(info)?))).await;
+        masterstate.lock().await.installing = false;
+    });
+
+    Ok(Json(JobCreated { job_id }))
+}
+
+async fn get_job(
+    State(masterstate): State<Arc<Mutex<MasterState>>>,
+    axum::extract::Path(id): axum::extract::Path<u64>) -> Result<Json<Job>, AppError> {
+    let jobs = masterstate.lock().await.jobs.clone();
+    match jobs.get(id).await {
+        Some(job) => Ok(Json(job)),
+        None => Err(AppError::NotFoundError(format!("job {}", id))),
+    }
+}
+
+// 生效的配置，密钥已隐去
+async fn get_config(State(app_config): State<Arc<AppConfig>>) -> Json<AppConfig> {
+    Json(app_config.redacted())
+}
+
+async fn tunnel_status(State(masterstate): State<Arc<Mutex<MasterState>>>) -> Result<Json<TunnelStatus>, AppError> {
+    let tunnel = masterstate.lock().await.tunnel.clone();
+    let status = tunnel.status().await.map_err(|e| AppError::TunnelError(e.to_string()))?;
+    Ok(Json(status))
+}
+
+async fn tunnel_reload(State(masterstate): State<Arc<Mutex<MasterState>>>) -> Result<StatusCode, AppError> {
+    let tunnel = masterstate.lock().await.tunnel.clone();
+    tunnel.reload().await.map_err(|e| AppError::ConfigReloadError(e.to_string()))?;
+    Ok(StatusCode::OK)
+}
+
+// frpc.toml 相关的接口只在 frp 隧道下可用
+fn require_frp(app_config: &AppConfig) -> Result<(), AppError> {
+    if app_config.tunnel_provider != TunnelProviderKind::Frp {
+        return Err(AppError::InvalidParamError(format!("tunnel provider is {:?}, not frp", app_config.tunnel_provider)));
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Debug)]
+struct TunnelPorts {
+    provider: TunnelProviderKind,
+    // 玩家连接用的公网地址：frp 为 frps 地址，direct 为主机地址
+    public_addr: String,
+    lease: Option<PortLease>,
+    ports: Vec<PortMapping>,
+    endpoints: Vec<PublicEndpoint>,
+}
+// 当前隧道的端口映射：静态模式按节点编号计算，租约模式按租到的端口
+async fn tunnel_ports(State(masterstate): State<Arc<Mutex<MasterState>>>) -> Result<Json<TunnelPorts>, AppError> {
+    let state = masterstate.lock().await;
+    let Some(index) = state.index else {
+        return Err(AppError::NotFoundError("node index is not known yet".to_string()));
+    };
+    if state.config.port_lease_mode == PortLeaseMode::Remote && state.lease.is_none() {
+        return Err(AppError::NotFoundError("remote ports are not leased yet".to_string()));
+    }
+    let ports = tunnel_port_mappings(&state.config, index, state.lease.as_ref());
+    Ok(Json(TunnelPorts {
+        provider: state.tunnel.kind(),
+        public_addr: state.tunnel.public_host(),
+        lease: state.lease.clone(),
+        endpoints: state.tunnel.public_endpoints(&ports),
+        ports,
+    }))
+}
+
+async fn tunnel_config(State(app_config): State<Arc<AppConfig>>) -> Result<String, AppError> {
+    require_frp(&app_config)?;
+    let client = FrpcAdminClient::from_config(&app_config).map_err(|e| AppError::TunnelError(e.to_string()))?;
+    client.get_config().await.map_err(|e| AppError::TunnelError(e.to_string()))
+}
+
+// 手动添加的代理；game_master 生成的代理由节点编号决定，不允许在这里改
+async fn put_tunnel_proxy(
+    State(app_config): State<Arc<AppConfig>>,
+    Json(proxy): Json<Proxy>) -> Result<StatusCode, AppError> {
+    require_frp(&app_config)?;
+    proxy.validate().map_err(AppError::InvalidParamError)?;
+    if proxy.managed_role().is_some() {
+        return Err(AppError::InvalidParamError(format!("proxy {} must not set game_master metadata", proxy.name)));
+    }
+    let mut config = frpc_config_load(&app_config.frpc_toml_path)
+        .await
+        .map_err(|e| AppError::ConfigReadError(e.to_string()))?;
+    if config.proxies.iter().any(|p| p.name == proxy.name && p.managed_role().is_some()) {
+        return Err(AppError::InvalidParamError(format!("proxy {} is managed by game_master", proxy.name)));
     }
+    config.upsert_proxy(proxy);
+    check_port_collisions(&config.proxies).map_err(AppError::InvalidParamError)?;
+    frpc_config_save(&config, &app_config.frpc_toml_path)
+        .await
+        .map_err(|e| AppError::ConfigWriteError(e.to_string()))?;
+    frpc_config_reload(&app_config)
+        .await
+        .map_err(|e| AppError::ConfigReloadError(e.to_string()))?;
+    Ok(StatusCode::OK)
+}
 
+async fn delete_tunnel_proxy(
+    State(app_config): State<Arc<AppConfig>>,
+    axum::extract::Path(name): axum::extract::Path<String>) -> Result<StatusCode, AppError> {
+    require_frp(&app_config)?;
+    let mut config = frpc_config_load(&app_config.frpc_toml_path)
+        .await
+        .map_err(|e| AppError::ConfigReadError(e.to_string()))?;
+    if config.proxies.iter().any(|p| p.name == name && p.managed_role().is_some()) {
+        return Err(AppError::InvalidParamError(format!("proxy {} is managed by game_master", name)));
+    }
+    if !config.remove_proxy(&name) {
+        return Err(AppError::NotFoundError(format!("proxy {}", name)));
+    }
+    frpc_config_save(&config, &app_config.frpc_toml_path)
+        .await
+        .map_err(|e| AppError::ConfigWriteError(e.to_string()))?;
+    frpc_config_reload(&app_config)
+        .await
+        .map_err(|e| AppError::ConfigReloadError(e.to_string()))?;
     Ok(StatusCode::OK)
 }
 
-async fn get_frpc_toml() -> Result<Json<Config>, AppError> {
-    match frpc_config_read(FRPC_TOML_PATH).await {
+type SharedPortAllocator = Option<Arc<Mutex<PortAllocator>>>;
+
+fn enabled_allocator(allocator: &SharedPortAllocator) -> Result<&Arc<Mutex<PortAllocator>>, AppError> {
+    allocator.as_ref().ok_or_else(|| AppError::NotFoundError("port allocator is not enabled on this node".to_string()))
+}
+
+async fn list_port_leases(State(allocator): State<SharedPortAllocator>) -> Result<Json<Vec<PortLease>>, AppError> {
+    Ok(Json(enabled_allocator(&allocator)?.lock().await.list(now_secs())))
+}
+
+async fn acquire_port_lease(
+    State(allocator): State<SharedPortAllocator>,
+    Json(request): Json<LeaseRequest>) -> Result<Json<PortLease>, AppError> {
+    let lease = enabled_allocator(&allocator)?
+        .lock()
+        .await
+        .acquire(&request, now_secs())
+        .map_err(|e| AppError::PortLeaseError(e.to_string()))?;
+    println!("lease {} ports {}..{} to {} ({})", lease.lease_id, lease.start_port, lease.start_port + lease.count, lease.holder, lease.public_addr);
+    Ok(Json(lease))
+}
+
+async fn renew_port_lease(
+    State(allocator): State<SharedPortAllocator>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    body: Option<Json<RenewRequest>>) -> Result<Json<PortLease>, AppError> {
+    let ttl_secs = body.and_then(|Json(request)| request.ttl_secs);
+    match enabled_allocator(&allocator)?.lock().await.renew(&id, ttl_secs, now_secs()) {
+        Some(lease) => Ok(Json(lease)),
+        None => Err(AppError::NotFoundError(format!("port lease {}", id))),
+    }
+}
+
+async fn release_port_lease(
+    State(allocator): State<SharedPortAllocator>,
+    axum::extract::Path(id): axum::extract::Path<String>) -> Result<StatusCode, AppError> {
+    if !enabled_allocator(&allocator)?.lock().await.release(&id) {
+        return Err(AppError::NotFoundError(format!("port lease {}", id)));
+    }
+    println!("released port lease {}", id);
+    Ok(StatusCode::OK)
+}
+
+async fn get_frpc_toml(State(app_config): State<Arc<AppConfig>>) -> Result<Json<Config>, AppError> {
+    match frpc_config_read(&app_config.frpc_toml_path).await {
         Ok(config) => Ok(Json(config)),
         Err(e) => {
             println!("{:#?}", e);
@@ -260,22 +1281,24 @@ async fn get_frpc_toml() -> Result<Json<Config>, AppError> {
     }
 }
 
-async fn reset_frpc_toml(Json(config): Json<FrpcToml>) -> Result<StatusCode, AppError> {
-    frpc_config_write(&config, FRPC_TOML_PATH)
+async fn reset_frpc_toml(
+    State(app_config): State<Arc<AppConfig>>,
+    Json(config): Json<FrpcToml>) -> Result<StatusCode, AppError> {
+    frpc_config_write(&config, &app_config)
         .await
         .map_err(|e| AppError::ConfigWriteError(e.to_string()))?;
 
-    frpc_config_reload()
+    frpc_config_reload(&app_config)
         .await
         .map_err(|e| AppError::ConfigReloadError(e.to_string()))?;
 
     Ok(StatusCode::OK)
 }
 
-async fn reset_frpc_toml_by_index(body: String) -> Result<StatusCode, AppError> {
+async fn reset_frpc_toml_by_index(State(app_config): State<Arc<AppConfig>>, body: String) -> Result<StatusCode, AppError> {
     let index = body.parse::<u8>().map_err(|e| AppError::BadBodyError(e.to_string()))?;
 
-    frpc_config_reset_by_index(FRPC_TOML_PATH, index)
+    frpc_config_reset_by_index(&app_config, index)
         .await
         .map_err(|e| AppError::ConfigResetByIndexError(e.to_string()))?;
 
@@ -287,6 +1310,34 @@ struct AppState {
     tx: broadcast::Sender<String>,
 }
 
+async fn frpc_log_handler(
+    ws: WebSocketUpgrade,
+    State(masterstate): State<Arc<Mutex<MasterState>>>,
+) -> impl IntoResponse {
+    let frpc = masterstate.lock().await.frpc.clone();
+    ws.on_upgrade(|socket| handle_frpc_log_socket(socket, frpc))
+}
+
+// 先发最近的日志，再持续推送新日志
+async fn handle_frpc_log_socket(mut socket: WebSocket, frpc: FrpcSupervisor) {
+    let mut rx = frpc.subscribe();
+    for line in frpc.recent_logs().await {
+        if socket.send(Message::Text(ws::Utf8Bytes::from(line))).await.is_err() {
+            return;
+        }
+    }
+    loop {
+        let line = match rx.recv().await {
+            Ok(line) => line,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => format!("... {} lines skipped", skipped),
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        if socket.send(Message::Text(ws::Utf8Bytes::from(line))).await.is_err() {
+            return;
+        }
+    }
+}
+
 async fn ws_handler(
     ws: WebSocketUpgrade,
     State(state): State<Arc<AppState>>,
@@ -341,24 +1392,57 @@ async fn handle_socket(mut socket: WebSocket, state: Arc<AppState>) {
 mod tests {
     use axum::Router;
     use axum::routing::get;
+    use std::sync::Arc;
+    use tempfile::tempdir;
     use tokio::fs;
-    use crate::{get_frpc_toml};
+    use crate::config_util::AppConfig;
+    use crate::mock_data_server_util::{spawn, FIXTURES_DIR};
+    use crate::{get_frpc_toml, resolve_start_settings, Start7DaysParam};
 
     #[tokio::test]
     async fn get_fpc_toml_test() {
+        let temp = tempdir().unwrap();
+        let frpc_toml_path = temp.path().join("frpc.toml");
+        fs::write(&frpc_toml_path, "serverAddr = \"frps.example.com\"\nserverPort = 7000\n").await.unwrap();
         let app = Router::new()
             .route("/hello", get(|| async { "Hello, World!" }))
-            .route("/get_fpc_toml", get(get_frpc_toml));
+            .route("/get_fpc_toml", get(get_frpc_toml))
+            .with_state(Arc::new(AppConfig { frpc_toml_path, ..AppConfig::default() }));
 
-        let listener = tokio::net::TcpListener::bind("127.0.0.1:3105")
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
             .await
             .unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let config: serde_json::Value = reqwest::get(format!("http://{}/get_fpc_toml", addr)).await.unwrap().json().await.unwrap();
+        assert_eq!(config["serverAddr"], "frps.example.com");
+    }
+
+    #[tokio::test]
+    async fn resolve_start_settings_offline_test() {
+        let mock = spawn(FIXTURES_DIR).await.unwrap();
+        let config = AppConfig { data_server_url: mock.base_url.clone(), ..AppConfig::default() };
+        let params: Start7DaysParam = serde_json::from_value(serde_json::json!({
+            "serverconfig_id": 1, "preset": "friends_weekend", "overrides": {"server_max_player_count": 6}
+        })).unwrap();
+        let resolved = resolve_start_settings(&config, &params).await.unwrap();
+        assert_eq!(resolved.settings.server_name, "Mock Server");
+        assert_eq!(resolved.settings.game_difficulty, 2);
+        assert_eq!(resolved.settings.drop_on_death, 3);
+        assert_eq!(resolved.settings.server_max_player_count, 6);
 
-        axum::serve(listener, app).await.unwrap();
+        let missing: Start7DaysParam = serde_json::from_value(serde_json::json!({ "serverconfig_id": 404 })).unwrap();
+        assert!(resolve_start_settings(&config, &missing).await.is_err());
     }
 
     #[tokio::test]
     async fn create_dir_test() {
-        fs::create_dir_all("C:\\Users\\89396\\projects\\game_master\\script").await.unwrap();
+        let temp = tempdir().unwrap();
+        let script = temp.path().join("projects").join("game_master").join("script");
+        fs::create_dir_all(&script).await.unwrap();
+        // 已存在时不报错
+        fs::create_dir_all(&script).await.unwrap();
+        assert!(script.is_dir());
     }
 }
\ No newline at end of file
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+// 独立的 bin/mock_data_server.rs 也会 include 这个文件，只能依赖外部 crate
+
+// 仓库自带的夹具：<dir>/<接口名>/<id 或 name>.json
+pub const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/data_server");
+
+#[derive(Clone)]
+struct MockState {
+    fixtures: PathBuf,
+    // 收到的 savefile_uploaded 回报
+    uploads: Arc<Mutex<Vec<Value>>>,
+}
+
+pub struct MockDataServer {
+    pub base_url: String,
+    pub uploads: Arc<Mutex<Vec<Value>>>,
+}
+
+// 只允许字母、数字、'-' 和 '_'，避免读到夹具目录以外的文件
+fn fixture_path(fixtures: &Path, endpoint: &str, key: Option<&String>) -> Option<PathBuf> {
+    let key = key.filter(|k| !k.is_empty() && k.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'))?;
+    Some(fixtures.join(endpoint).join(format!("{}.json", key)))
+}
+
+async fn read_fixture(fixtures: &Path, endpoint: &str, key: Option<&String>) -> Result<Json<Value>, StatusCode> {
+    let path = fixture_path(fixtures, endpoint, key).ok_or(StatusCode::BAD_REQUEST)?;
+    let contents = tokio::fs::read_to_string(&path).await.map_err(|_| StatusCode::NOT_FOUND)?;
+    serde_json::from_str(&contents).map(Json).map_err(|e| {
+        eprintln!("bad fixture {:?}: {}", path, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+pub fn router(fixtures: PathBuf, uploads: Arc<Mutex<Vec<Value>>>) -> Router {
+    Router::new()
+        .route("/api/game_master/game_config", get(|State(state): State<MockState>, Query(params): Query<HashMap<String, String>>| async move {
+            read_fixture(&state.fixtures, "game_config", params.get("serverconfig_id")).await
+        }))
+        .route("/api/game_master/download_savefile", get(|State(state): State<MockState>, Query(params): Query<HashMap<String, String>>| async move {
+            read_fixture(&state.fixtures, "download_savefile", params.get("save_file_id")).await
+        }))
+        .route("/api/game_master/preset", get(|State(state): State<MockState>, Query(params): Query<HashMap<String, String>>| async move {
+            read_fixture(&state.fixtures, "preset", params.get("name")).await
+        }))
+        .route("/api/game_master/savefile_uploaded", post(|State(state): State<MockState>, Json(report): Json<Value>| async move {
+            println!("savefile uploaded: {}", report);
+            state.uploads.lock().await.push(report);
+            StatusCode::NO_CONTENT
+        }))
+        .with_state(MockState { fixtures, uploads })
+}
+
+// 监听随机端口，测试里用
+pub async fn spawn(fixtures: impl Into<PathBuf>) -> anyhow::Result<MockDataServer> {
+    let uploads = Arc::new(Mutex::new(Vec::new()));
+    let app = router(fixtures.into(), uploads.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let base_url = format!("http://{}", listener.local_addr()?);
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            eprintln!("mock data server error: {}", e);
+        }
+    });
+    Ok(MockDataServer { base_url, uploads })
+}
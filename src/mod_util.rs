@@ -0,0 +1,350 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use crate::archive::unzip;
+use crate::common::sha256_file_hex;
+use crate::storage_util::ObjectStorage;
+
+pub const MODS_DIR_NAME: &str = "Mods";
+// 7dtd 没有禁用开关，禁用的 mod 移到 Mods 之外
+pub const DISABLED_MODS_DIR_NAME: &str = "DisabledMods";
+pub const MODS_MANIFEST_FILE_NAME: &str = "game_master_mods.toml";
+const MOD_INFO_FILE_NAME: &str = "ModInfo.xml";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModSpec {
+    pub name: String,
+    pub version: String,
+    pub bucket: String,
+    pub key: String,
+    pub sha256: String,
+    #[serde(default)]
+    pub host: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InstalledMod {
+    pub name: String,
+    pub version: String,
+    pub sha256: String,
+    pub enabled: bool,
+    // 一个压缩包里可能有多个 mod 目录，比如大型整合包
+    pub folders: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ModManifest {
+    #[serde(default)]
+    pub mods: Vec<InstalledMod>,
+}
+
+impl ModManifest {
+    pub fn get(&self, name: &str) -> Option<&InstalledMod> {
+        self.mods.iter().find(|m| m.name == name)
+    }
+}
+
+fn mods_dir(server_path: &Path) -> PathBuf {
+    server_path.join(MODS_DIR_NAME)
+}
+
+fn disabled_mods_dir(server_path: &Path) -> PathBuf {
+    server_path.join(DISABLED_MODS_DIR_NAME)
+}
+
+pub fn is_valid_mod_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".."
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | ' '))
+}
+
+pub fn read_manifest(server_path: &Path) -> anyhow::Result<ModManifest> {
+    let path = mods_dir(server_path).join(MODS_MANIFEST_FILE_NAME);
+    if !path.is_file() {
+        return Ok(ModManifest::default());
+    }
+    Ok(toml::from_str(&fs::read_to_string(path)?)?)
+}
+
+fn write_manifest(server_path: &Path, manifest: &ModManifest) -> anyhow::Result<()> {
+    fs::create_dir_all(mods_dir(server_path))?;
+    fs::write(mods_dir(server_path).join(MODS_MANIFEST_FILE_NAME), toml::to_string_pretty(manifest)?)?;
+    Ok(())
+}
+
+fn remove_folders(server_path: &Path, folders: &[String]) -> anyhow::Result<()> {
+    for folder in folders {
+        for dir in [mods_dir(server_path), disabled_mods_dir(server_path)] {
+            let path = dir.join(folder);
+            if path.exists() {
+                fs::remove_dir_all(path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// 找出压缩包里真正的 mod 目录：跳过外层的单个包装目录
+fn mod_folders(staging: &Path, name: &str) -> anyhow::Result<Vec<(PathBuf, String)>> {
+    let mut root = staging.to_path_buf();
+    loop {
+        if root.join(MOD_INFO_FILE_NAME).is_file() {
+            return Ok(vec![(root, name.to_string())]);
+        }
+        let entries: Vec<PathBuf> = fs::read_dir(&root)?.map(|e| e.map(|e| e.path())).collect::<Result<_, _>>()?;
+        if entries.len() == 1 && entries[0].is_dir() && !entries[0].join(MOD_INFO_FILE_NAME).is_file() {
+            root = entries[0].clone();
+            continue;
+        }
+
+        let folders: Vec<(PathBuf, String)> = entries
+            .into_iter()
+            .filter(|p| p.join(MOD_INFO_FILE_NAME).is_file())
+            .map(|p| {
+                let folder = p.file_name().unwrap_or_default().to_string_lossy().to_string();
+                (p, folder)
+            })
+            .collect();
+        if folders.is_empty() {
+            bail!("mod archive {} contains no {}", name, MOD_INFO_FILE_NAME);
+        }
+        return Ok(folders);
+    }
+}
+
+pub fn install_mod_archive(server_path: &Path, spec: &ModSpec, archive_path: &Path) -> anyhow::Result<InstalledMod> {
+    if !is_valid_mod_name(&spec.name) {
+        bail!("invalid mod name {:?}", spec.name);
+    }
+    let sha256 = sha256_file_hex(archive_path)?;
+    if !sha256.eq_ignore_ascii_case(&spec.sha256) {
+        bail!("mod {} checksum mismatch: expected {}, got {}", spec.name, spec.sha256, sha256);
+    }
+
+    let staging = mods_dir(server_path).join(format!(".staging_{}", spec.name));
+    let _ = fs::remove_dir_all(&staging);
+    fs::create_dir_all(&staging)?;
+    let result = (|| {
+        unzip(archive_path.to_string_lossy().as_ref(), staging.to_string_lossy().as_ref())?;
+        let folders = mod_folders(&staging, &spec.name)?;
+
+        // 解压和冲突检查都通过后才删除旧版本，失败时已安装的 mod 不受影响
+        let mut manifest = read_manifest(server_path)?;
+        let names: Vec<String> = folders.iter().map(|(_, folder)| folder.clone()).collect();
+        if let Some(other) = manifest.mods.iter().find(|m| m.name != spec.name && m.folders.iter().any(|f| names.contains(f))) {
+            bail!("mod {} conflicts with installed mod {}", spec.name, other.name);
+        }
+        if let Some(old) = manifest.get(&spec.name) {
+            remove_folders(server_path, &old.folders)?;
+        }
+        remove_folders(server_path, &names)?;
+        for (path, folder) in &folders {
+            fs::rename(path, mods_dir(server_path).join(folder))?;
+        }
+
+        let installed = InstalledMod {
+            name: spec.name.clone(),
+            version: spec.version.clone(),
+            sha256,
+            enabled: true,
+            folders: names,
+        };
+        manifest.mods.retain(|m| m.name != spec.name);
+        manifest.mods.push(installed.clone());
+        write_manifest(server_path, &manifest)?;
+        Ok(installed)
+    })();
+    let _ = fs::remove_dir_all(&staging);
+
+    if let Ok(installed) = &result {
+        println!("install mod {} {} to {:?}", installed.name, installed.version, installed.folders);
+    }
+    result
+}
+
+pub async fn download_and_install_mod(server_path: &Path, temp_dir: &Path, storage: &ObjectStorage, spec: &ModSpec) -> anyhow::Result<InstalledMod> {
+    if !is_valid_mod_name(&spec.name) {
+        bail!("invalid mod name {:?}", spec.name);
+    }
+    let archive_path = temp_dir.join(format!("mod_{}.zip", spec.name));
+    storage.download(spec.host.clone(), &spec.bucket, &spec.key, &archive_path).await?;
+
+    let installed = install_mod_archive(server_path, spec, &archive_path);
+    let _ = fs::remove_file(&archive_path);
+    installed
+}
+
+pub fn set_mod_enabled(server_path: &Path, name: &str, enabled: bool) -> anyhow::Result<bool> {
+    let mut manifest = read_manifest(server_path)?;
+    let Some(installed) = manifest.mods.iter_mut().find(|m| m.name == name) else {
+        return Ok(false);
+    };
+    if installed.enabled == enabled {
+        return Ok(true);
+    }
+
+    let (from, to) = if enabled {
+        (disabled_mods_dir(server_path), mods_dir(server_path))
+    } else {
+        (mods_dir(server_path), disabled_mods_dir(server_path))
+    };
+    fs::create_dir_all(&to)?;
+    for folder in &installed.folders {
+        if from.join(folder).exists() {
+            fs::rename(from.join(folder), to.join(folder))?;
+        }
+    }
+    installed.enabled = enabled;
+    write_manifest(server_path, &manifest)?;
+
+    println!("set mod {} enabled: {}", name, enabled);
+    Ok(true)
+}
+
+pub fn remove_mod(server_path: &Path, name: &str) -> anyhow::Result<bool> {
+    let mut manifest = read_manifest(server_path)?;
+    let Some(installed) = manifest.get(name).cloned() else {
+        return Ok(false);
+    };
+    remove_folders(server_path, &installed.folders)?;
+    manifest.mods.retain(|m| m.name != name);
+    write_manifest(server_path, &manifest)?;
+
+    println!("remove mod {}", name);
+    Ok(true)
+}
+
+// 让已安装的 mod 与期望列表一致：缺的下载，校验和不同的重装，多余的禁用
+pub async fn sync_mods(server_path: &Path, temp_dir: &Path, storage: &ObjectStorage, desired: &[ModSpec]) -> anyhow::Result<ModManifest> {
+    for spec in desired {
+        let manifest = read_manifest(server_path)?;
+        match manifest.get(&spec.name) {
+            Some(installed) if installed.sha256.eq_ignore_ascii_case(&spec.sha256) => {
+                set_mod_enabled(server_path, &spec.name, true)?;
+            }
+            _ => {
+                download_and_install_mod(server_path, temp_dir, storage, spec).await?;
+            }
+        }
+    }
+
+    let manifest = read_manifest(server_path)?;
+    for installed in &manifest.mods {
+        if !desired.iter().any(|spec| spec.name == installed.name) {
+            set_mod_enabled(server_path, &installed.name, false)?;
+        }
+    }
+
+    read_manifest(server_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+    use std::path::Path;
+    use zip::write::SimpleFileOptions;
+    use tempfile::tempdir;
+    use crate::common::sha256_file_hex;
+    use crate::mod_util::{install_mod_archive, read_manifest, remove_mod, set_mod_enabled, ModSpec};
+
+    fn make_archive(path: &Path, files: &[&str]) {
+        let mut writer = zip::ZipWriter::new(fs::File::create(path).unwrap());
+        for file in files {
+            writer.start_file(*file, SimpleFileOptions::default()).unwrap();
+            writer.write_all(b"<xml/>").unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    fn spec(name: &str, archive: &Path) -> ModSpec {
+        ModSpec {
+            name: name.to_string(),
+            version: "1.0".to_string(),
+            bucket: "mods".to_string(),
+            key: format!("{}.zip", name),
+            sha256: sha256_file_hex(archive).unwrap(),
+            host: None,
+        }
+    }
+
+    #[test]
+    fn install_mod_folder_test() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        let archive = root.join("qol.zip");
+        make_archive(&archive, &["QoL-Main/ModInfo.xml", "QoL-Main/Config/items.xml"]);
+
+        let server = root.join("server");
+        let installed = install_mod_archive(&server, &spec("qol", &archive), &archive).unwrap();
+        assert_eq!(installed.folders, vec!["QoL-Main"]);
+        assert!(server.join("Mods/QoL-Main/Config/items.xml").is_file());
+        assert_eq!(read_manifest(&server).unwrap().mods, vec![installed]);
+    }
+
+    #[test]
+    fn install_multi_folder_and_toggle_test() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        let archive = root.join("df.zip");
+        make_archive(&archive, &["0-SCore/ModInfo.xml", "0-DarknessFallsCore/ModInfo.xml"]);
+
+        let server = root.join("server");
+        let mut installed = install_mod_archive(&server, &spec("darkness_falls", &archive), &archive).unwrap();
+        installed.folders.sort();
+        assert_eq!(installed.folders, vec!["0-DarknessFallsCore", "0-SCore"]);
+
+        assert!(set_mod_enabled(&server, "darkness_falls", false).unwrap());
+        assert!(!server.join("Mods/0-SCore").exists());
+        assert!(server.join("DisabledMods/0-SCore/ModInfo.xml").is_file());
+
+        assert!(set_mod_enabled(&server, "darkness_falls", true).unwrap());
+        assert!(server.join("Mods/0-SCore/ModInfo.xml").is_file());
+
+        assert!(remove_mod(&server, "darkness_falls").unwrap());
+        assert!(!server.join("Mods/0-SCore").exists());
+        assert!(read_manifest(&server).unwrap().mods.is_empty());
+    }
+
+    #[test]
+    fn checksum_mismatch_test() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        let archive = root.join("qol.zip");
+        make_archive(&archive, &["ModInfo.xml"]);
+        let installed = install_mod_archive(&root.join("server"), &spec("qol", &archive), &archive).unwrap();
+        assert_eq!(installed.folders, vec!["qol"]);
+
+        let mut bad = spec("qol", &archive);
+        bad.sha256 = "00".repeat(32);
+        assert!(install_mod_archive(&root.join("server"), &bad, &archive).is_err());
+        assert!(root.join("server/Mods/qol/ModInfo.xml").is_file());
+    }
+
+    #[test]
+    fn failed_upgrade_keeps_old_version_test() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        let server = root.join("server");
+        let qol = root.join("qol.zip");
+        make_archive(&qol, &["QoL-Main/ModInfo.xml"]);
+        install_mod_archive(&server, &spec("qol", &qol), &qol).unwrap();
+        let score = root.join("score.zip");
+        make_archive(&score, &["0-SCore/ModInfo.xml"]);
+        install_mod_archive(&server, &spec("score", &score), &score).unwrap();
+
+        // 新版本里带了别的 mod 的目录
+        let conflicting = root.join("qol_2.zip");
+        make_archive(&conflicting, &["QoL-Main/ModInfo.xml", "0-SCore/ModInfo.xml"]);
+        let error = install_mod_archive(&server, &spec("qol", &conflicting), &conflicting).unwrap_err();
+        assert!(error.to_string().contains("conflicts with installed mod score"));
+        // 解压失败同样不动旧版本
+        let broken = root.join("qol_3.zip");
+        fs::write(&broken, "not a zip").unwrap();
+        assert!(install_mod_archive(&server, &spec("qol", &broken), &broken).is_err());
+
+        assert!(server.join("Mods/QoL-Main/ModInfo.xml").is_file());
+        assert!(server.join("Mods/0-SCore/ModInfo.xml").is_file());
+        assert_eq!(read_manifest(&server).unwrap().mods.len(), 2);
+    }
+}
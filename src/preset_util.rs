@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use tokio::fs;
+use crate::data_server_util::DataServerClient;
+use crate::game_config_util::{ServerSettings, Violation};
+
+// 一层部分配置：只包含要覆盖的字段
+pub type SettingsLayer = Map<String, Value>;
+
+pub fn builtin_preset(name: &str) -> Option<SettingsLayer> {
+    let preset = match name {
+        "vanilla" => json!({}),
+        "hardcore" => json!({
+            "game_difficulty": 5,
+            "death_penalty": 3,
+            "drop_on_death": 1,
+            "loot_abundance": 50,
+            "xp_multiplier": 75,
+            "enemy_difficulty": 1,
+            "zombie_feral_sense": 3,
+            "zombie_move": 2,
+            "zombie_move_night": 4,
+            "blood_moon_enemy_count": 16,
+            "air_drop_frequency": 120,
+        }),
+        "creative" => json!({
+            "game_mode": "GameModeCreative",
+            "build_create": true,
+            "enemy_spawn_mode": false,
+            "player_killing_mode": 0,
+            "day_night_length": 120,
+            "land_claim_count": 20,
+        }),
+        "pvp_arena" => json!({
+            "player_killing_mode": 3,
+            "drop_on_death": 0,
+            "drop_on_quit": 0,
+            "enemy_spawn_mode": false,
+            "land_claim_count": 0,
+            "bedroll_dead_zone_size": 0,
+            "player_safe_zone_level": 0,
+            "allow_spawn_near_friend": 0,
+        }),
+        _ => return None,
+    };
+    preset.as_object().cloned()
+}
+
+// 查找顺序：节点上的 <name>.toml，内置预设，data server（未配置时跳过）
+pub async fn load_preset(presets_dir: &Path, data_server: Option<&DataServerClient>, name: &str) -> anyhow::Result<SettingsLayer> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        bail!("invalid preset name {:?}", name);
+    }
+
+    let path = presets_dir.join(format!("{}.toml", name));
+    if fs::try_exists(&path).await? {
+        let contents = fs::read_to_string(&path).await?;
+        let table: toml::Table = toml::from_str(&contents)?;
+        return match serde_json::to_value(table)? {
+            Value::Object(map) => Ok(map),
+            _ => bail!("preset {:?} is not a table", path),
+        };
+    }
+
+    if let Some(preset) = builtin_preset(name) {
+        return Ok(preset);
+    }
+
+    let preset = match data_server {
+        Some(client) => client.preset(name).await?,
+        None => None,
+    };
+    match preset {
+        Some(preset) => Ok(preset),
+        None => bail!("preset {} not found", name),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResolvedSettings {
+    pub settings: ServerSettings,
+    // 每个被覆盖的字段最终来自哪一层
+    pub sources: BTreeMap<String, String>,
+}
+
+pub struct Layer {
+    pub name: String,
+    pub values: SettingsLayer,
+    // data server 会带上 id 之类的额外字段，这一层忽略未知字段
+    pub ignore_unknown: bool,
+}
+
+// 按顺序叠加，后面的层覆盖前面的层
+pub fn resolve_settings(layers: &[Layer]) -> Result<ResolvedSettings, Vec<Violation>> {
+    let mut merged = match serde_json::to_value(ServerSettings::default()) {
+        Ok(Value::Object(map)) => map,
+        _ => unreachable!("ServerSettings always serializes to an object"),
+    };
+
+    let mut sources = BTreeMap::new();
+    let mut violations = Vec::new();
+    for layer in layers {
+        for (field, value) in &layer.values {
+            if !merged.contains_key(field) {
+                if !layer.ignore_unknown {
+                    violations.push(Violation { field: field.clone(), message: format!("unknown setting in {}", layer.name) });
+                }
+                continue;
+            }
+            merged.insert(field.clone(), value.clone());
+            sources.insert(field.clone(), layer.name.clone());
+        }
+    }
+    if !violations.is_empty() {
+        return Err(violations);
+    }
+
+    let settings: ServerSettings = serde_json::from_value(Value::Object(merged))
+        .map_err(|e| vec![Violation { field: "settings".to_string(), message: e.to_string() }])?;
+    settings.validate()?;
+
+    Ok(ResolvedSettings { settings, sources })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tempfile::tempdir;
+    use crate::preset_util::{builtin_preset, load_preset, resolve_settings, Layer};
+
+    fn layer(name: &str, values: serde_json::Value, ignore_unknown: bool) -> Layer {
+        Layer { name: name.to_string(), values: values.as_object().unwrap().clone(), ignore_unknown }
+    }
+
+    #[test]
+    fn builtin_presets_valid_test() {
+        for name in ["vanilla", "hardcore", "creative", "pvp_arena"] {
+            let preset = builtin_preset(name).unwrap();
+            resolve_settings(&[layer(name, serde_json::Value::Object(preset), false)]).unwrap();
+        }
+    }
+
+    #[test]
+    fn resolve_precedence_test() {
+        let resolved = resolve_settings(&[
+            layer("preset:hardcore", serde_json::Value::Object(builtin_preset("hardcore").unwrap()), false),
+            layer("serverconfig:1", json!({"id": 1, "server_name": "Friends", "loot_abundance": 80}), true),
+            layer("override", json!({"loot_abundance": 120}), false),
+        ]).unwrap();
+
+        assert_eq!(resolved.settings.game_difficulty, 5);
+        assert_eq!(resolved.settings.server_name, "Friends");
+        assert_eq!(resolved.settings.loot_abundance, 120);
+        assert_eq!(resolved.sources["game_difficulty"], "preset:hardcore");
+        assert_eq!(resolved.sources["server_name"], "serverconfig:1");
+        assert_eq!(resolved.sources["loot_abundance"], "override");
+        assert!(!resolved.sources.contains_key("id"));
+    }
+
+    #[test]
+    fn resolve_rejects_unknown_and_invalid_test() {
+        let violations = resolve_settings(&[layer("override", json!({"loot_abundace": 120}), false)]).unwrap_err();
+        assert_eq!(violations[0].field, "loot_abundace");
+
+        let violations = resolve_settings(&[layer("override", json!({"game_difficulty": 9}), false)]).unwrap_err();
+        assert_eq!(violations[0].field, "game_difficulty");
+    }
+
+    #[tokio::test]
+    async fn load_node_preset_test() {
+        let temp = tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(dir.join("hardcore.toml"), "game_difficulty = 4\nbuild_create = false\n").unwrap();
+
+        let preset = load_preset(dir, None, "hardcore").await.unwrap();
+        assert_eq!(preset.get("game_difficulty"), Some(&json!(4)));
+        assert_eq!(preset.len(), 2);
+        assert!(load_preset(dir, None, "../hardcore").await.is_err());
+    }
+}
@@ -0,0 +1,212 @@
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use crate::common::local_hostname;
+use crate::config_util::AppConfig;
+use crate::data_server_util::{DataServerClient, DataServerError};
+use crate::frp_util::PortMapping;
+use crate::tunnel_util::{PublicEndpoint, TunnelProviderKind};
+
+pub const GAME_MASTER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeState {
+    // 节点编号未知，隧道还没建立
+    Starting,
+    Idle,
+    Installing,
+    Running,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ResourceUsage {
+    pub load_1m: Option<f64>,
+    pub memory_total_kb: Option<u64>,
+    pub memory_available_kb: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NodeRegistration {
+    pub node_id: String,
+    pub index: Option<u8>,
+    pub version: String,
+    // game_master 控制接口监听地址
+    pub listen_addr: String,
+    pub tunnel_provider: TunnelProviderKind,
+    pub public_host: String,
+    pub endpoints: Vec<PublicEndpoint>,
+    pub ports: Vec<PortMapping>,
+    pub game_version: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct RegistrationResponse {
+    // index_strategy 为 data_server 时由 data server 分配
+    #[serde(default)]
+    pub index: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Heartbeat {
+    pub index: Option<u8>,
+    pub state: NodeState,
+    // 游戏未运行或 telnet 不可用时为 None
+    pub player_count: Option<u32>,
+    pub resources: ResourceUsage,
+    // 编号由 data server 分配或端口租约变化后，端口在心跳里更新
+    pub endpoints: Vec<PublicEndpoint>,
+}
+
+// node_id 为空时用主机名
+pub fn node_id(config: &AppConfig) -> String {
+    if !config.node_id.is_empty() {
+        return config.node_id.clone();
+    }
+    local_hostname().unwrap_or_else(|_| "game_master".to_string())
+}
+
+// /proc/loadavg: "0.52 0.58 0.59 1/389 12345"
+pub fn parse_loadavg(contents: &str) -> Option<f64> {
+    contents.split_whitespace().next()?.parse().ok()
+}
+
+// /proc/meminfo 中的 MemTotal 和 MemAvailable（kB）
+pub fn parse_meminfo(contents: &str) -> (Option<u64>, Option<u64>) {
+    let field = |name: &str| contents.lines()
+        .find_map(|line| line.strip_prefix(name))
+        .and_then(|rest| rest.trim_start_matches(':').split_whitespace().next())
+        .and_then(|value| value.parse().ok());
+    (field("MemTotal"), field("MemAvailable"))
+}
+
+// 控制台 lp 命令的输出最后一行是 "Total of 3 in the game"
+pub fn parse_player_count(output: &str) -> Option<u32> {
+    output.lines()
+        .rev()
+        .find_map(|line| line.trim().strip_prefix("Total of "))
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|count| count.parse().ok())
+}
+
+pub async fn resource_usage() -> ResourceUsage {
+    let load_1m = tokio::fs::read_to_string("/proc/loadavg").await.ok().and_then(|c| parse_loadavg(&c));
+    let (memory_total_kb, memory_available_kb) = match tokio::fs::read_to_string("/proc/meminfo").await {
+        Ok(contents) => parse_meminfo(&contents),
+        Err(_) => (None, None),
+    };
+    ResourceUsage { load_1m, memory_total_kb, memory_available_kb }
+}
+
+// data server 的节点接口：
+// POST /api/game_master/nodes, POST /api/game_master/nodes/{id}/heartbeat, DELETE /api/game_master/nodes/{id}
+pub struct NodeClient {
+    client: DataServerClient,
+}
+
+impl NodeClient {
+    pub fn from_config(config: &AppConfig) -> Result<Self, DataServerError> {
+        Ok(NodeClient { client: DataServerClient::from_config(config)? })
+    }
+
+    pub async fn register(&self, registration: &NodeRegistration) -> Result<RegistrationResponse, DataServerError> {
+        let body = serde_json::to_value(registration).map_err(|e| DataServerError::BadResponse(e.to_string()))?;
+        let response = self.client.send(Method::POST, "/api/game_master/nodes", &[], Some(&body)).await?;
+        // 允许 data server 返回空 body
+        let text = response.text().await.map_err(|e| DataServerError::BadResponse(e.to_string()))?;
+        if text.trim().is_empty() {
+            return Ok(RegistrationResponse::default());
+        }
+        serde_json::from_str(&text).map_err(|e| DataServerError::BadResponse(format!("register node: {}", e)))
+    }
+
+    // 返回 NotFound 表示 data server 不认识这个节点（例如重启后丢了注册信息），需要重新注册
+    pub async fn heartbeat(&self, node_id: &str, heartbeat: &Heartbeat) -> Result<(), DataServerError> {
+        let body = serde_json::to_value(heartbeat).map_err(|e| DataServerError::BadResponse(e.to_string()))?;
+        self.client.send(Method::POST, &format!("/api/game_master/nodes/{}/heartbeat", node_id), &[], Some(&body)).await?;
+        Ok(())
+    }
+
+    pub async fn deregister(&self, node_id: &str) -> Result<(), DataServerError> {
+        match self.client.send(Method::DELETE, &format!("/api/game_master/nodes/{}", node_id), &[], None).await {
+            Ok(_) | Err(DataServerError::NotFound(_)) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use axum::extract::Path;
+    use axum::http::StatusCode;
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use serde_json::{json, Value};
+    use tokio::sync::Mutex;
+    use crate::config_util::AppConfig;
+    use crate::data_server_util::DataServerError;
+    use crate::registration_util::{parse_loadavg, parse_meminfo, parse_player_count, Heartbeat, NodeClient, NodeRegistration, NodeState,
+                                   ResourceUsage};
+    use crate::tunnel_util::TunnelProviderKind;
+
+    #[test]
+    fn parse_proc_test() {
+        assert_eq!(parse_loadavg("0.52 0.58 0.59 1/389 12345\n"), Some(0.52));
+        assert_eq!(parse_loadavg(""), None);
+        let meminfo = "MemTotal:       16314748 kB\nMemFree:         1021504 kB\nMemAvailable:    9876543 kB\n";
+        assert_eq!(parse_meminfo(meminfo), (Some(16314748), Some(9876543)));
+        assert_eq!(parse_meminfo("MemTotal: 1 kB\n"), (Some(1), None));
+    }
+
+    #[test]
+    fn parse_player_count_test() {
+        let output = "1. id=171, Alice, pos=(1.0, 2.0, 3.0)\n2. id=172, Bob, pos=(4.0, 5.0, 6.0)\nTotal of 2 in the game\r\n";
+        assert_eq!(parse_player_count(output), Some(2));
+        assert_eq!(parse_player_count("Total of 0 in the game"), Some(0));
+        assert_eq!(parse_player_count("unknown command"), None);
+    }
+
+    // 模拟 data server 的节点接口，注册时分配编号 7
+    async fn stub_node_api() -> (String, Arc<Mutex<HashSet<String>>>) {
+        let nodes = Arc::new(Mutex::new(HashSet::new()));
+        let (registered, alive, removed) = (nodes.clone(), nodes.clone(), nodes.clone());
+        let app = Router::new()
+            .route("/api/game_master/nodes", post(move |Json(body): Json<Value>| async move {
+                registered.lock().await.insert(body["node_id"].as_str().unwrap_or_default().to_string());
+                Json(json!({"index": 7}))
+            }))
+            .route("/api/game_master/nodes/{id}/heartbeat", post(move |Path(id): Path<String>| async move {
+                if alive.lock().await.contains(&id) { StatusCode::OK } else { StatusCode::NOT_FOUND }
+            }))
+            .route("/api/game_master/nodes/{id}", axum::routing::delete(move |Path(id): Path<String>| async move {
+                removed.lock().await.remove(&id);
+                StatusCode::OK
+            }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}", addr), nodes)
+    }
+
+    #[tokio::test]
+    async fn node_client_test() {
+        let (base_url, nodes) = stub_node_api().await;
+        let client = NodeClient::from_config(&AppConfig { data_server_url: base_url, ..AppConfig::default() }).unwrap();
+        let heartbeat = Heartbeat { index: Some(7), state: NodeState::Idle, player_count: None, resources: ResourceUsage::default(), endpoints: Vec::new() };
+
+        // 没注册过的节点心跳返回 NotFound，由调用方重新注册
+        assert!(matches!(client.heartbeat("node-a", &heartbeat).await, Err(DataServerError::NotFound(_))));
+
+        let registration = NodeRegistration {
+            node_id: "node-a".to_string(), index: None, version: "0.1.0".to_string(), listen_addr: "0.0.0.0:3005".to_string(),
+            tunnel_provider: TunnelProviderKind::Frp, public_host: "1.2.3.4".to_string(), endpoints: Vec::new(), ports: Vec::new(),
+            game_version: None,
+        };
+        assert_eq!(client.register(&registration).await.unwrap().index, Some(7));
+        client.heartbeat("node-a", &heartbeat).await.unwrap();
+
+        client.deregister("node-a").await.unwrap();
+        assert!(nodes.lock().await.is_empty());
+    }
+}
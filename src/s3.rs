@@ -100,6 +100,7 @@ mod tests {
     use crate::s3::{download_file, get_rustfs_client};
 
     #[tokio::test]
+    #[ignore = "needs live service"]
     async fn test_rustfs_client() {
         dotenv::dotenv().ok();
         let rustfs_client = get_rustfs_client(None).await.unwrap();
@@ -113,6 +114,7 @@ mod tests {
     }
 
     #[tokio::test]
+    #[ignore = "needs live service"]
     async fn test_download_file() {
         dotenv::dotenv().ok();
         let rustfs_client = get_rustfs_client(None).await.unwrap();
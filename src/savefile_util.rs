@@ -0,0 +1,353 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use crate::archive::{unzip, zip};
+use crate::game_config_util::{is_path_segment, ServerSettings};
+
+pub const RWG_WORLD: &str = "RWG";
+pub const SAVE_META_FILE_NAME: &str = "game_master_save.toml";
+const RESTORE_STAGING_DIR_NAME: &str = ".game_master_restore";
+
+// 随存档一起打包的元数据，RWG 世界目录名只能从这里得知
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SaveMeta {
+    pub world: String,
+    pub game_name: String,
+    // 最后写入这个存档的游戏版本，"<branch>/<build id>"
+    #[serde(default)]
+    pub game_version: Option<String>,
+    // RWG 存档对应的 WorldGenSeed / WorldGenSize，用来在同名存档里找到正确的世界
+    #[serde(default)]
+    pub world_gen_seed: Option<String>,
+    #[serde(default)]
+    pub world_gen_size: Option<i32>,
+}
+
+// 游戏内时间，第 day 天 hour:minute
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct GameTime {
+    pub day: u32,
+    pub hour: u8,
+    pub minute: u8,
+}
+
+// 控制台 gettime 命令的输出: "Day 12, 13:45"
+pub fn parse_game_time(output: &str) -> Option<GameTime> {
+    output.lines().find_map(|line| {
+        let (day, time) = line.trim().strip_prefix("Day ")?.split_once(", ")?;
+        let (hour, minute) = time.trim().split_once(':')?;
+        Some(GameTime { day: day.trim().parse().ok()?, hour: hour.parse().ok()?, minute: minute.parse().ok()? })
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveLocation {
+    pub world_dir: String,
+    pub game_name: String,
+}
+
+impl SaveLocation {
+    pub fn parent_path(&self, saves_root: &Path) -> PathBuf {
+        saves_root.join(&self.world_dir)
+    }
+
+    pub fn path(&self, saves_root: &Path) -> PathBuf {
+        saves_root.join(&self.world_dir).join(&self.game_name)
+    }
+}
+
+pub fn is_rwg(settings: &ServerSettings) -> bool {
+    settings.game_world == RWG_WORLD
+}
+
+// RWG 世界目录名由游戏根据 seed 生成，只能从已有存档中找：
+// 元数据里 seed 和大小与设置一致的优先，其次是没有元数据的存档；候选不止一个时报错，不猜
+fn find_rwg_world_dir(saves_root: &Path, settings: &ServerSettings) -> anyhow::Result<Option<String>> {
+    if !saves_root.is_dir() {
+        return Ok(None);
+    }
+
+    let (mut matched, mut unknown) = (Vec::new(), Vec::new());
+    for entry in fs::read_dir(saves_root)? {
+        let entry = entry?;
+        let world_dir = entry.file_name().to_string_lossy().to_string();
+        if world_dir.starts_with('.') {
+            continue;
+        }
+        let save_path = entry.path().join(&settings.game_name);
+        if !save_path.is_dir() {
+            continue;
+        }
+        match read_save_meta(&save_path)? {
+            Some(SaveMeta { world_gen_seed: Some(seed), world_gen_size: Some(size), .. }) => {
+                if seed == settings.world_gen_seed && size == settings.world_gen_size {
+                    matched.push(world_dir);
+                }
+            }
+            _ => unknown.push(world_dir),
+        }
+    }
+
+    let mut candidates = if matched.is_empty() { unknown } else { matched };
+    if candidates.len() > 1 {
+        candidates.sort();
+        bail!("RWG game {} exists in several worlds {:?}, cannot tell which one has seed {:?} size {}",
+            settings.game_name, candidates, settings.world_gen_seed, settings.world_gen_size);
+    }
+    Ok(candidates.pop())
+}
+
+pub fn resolve_save_location(saves_root: &Path, settings: &ServerSettings) -> anyhow::Result<Option<SaveLocation>> {
+    let world_dir = if is_rwg(settings) {
+        match find_rwg_world_dir(saves_root, settings)? {
+            Some(world_dir) => world_dir,
+            None => return Ok(None),
+        }
+    } else {
+        settings.game_world.clone()
+    };
+
+    Ok(Some(SaveLocation { world_dir, game_name: settings.game_name.clone() }))
+}
+
+pub fn read_save_meta(save_path: &Path) -> anyhow::Result<Option<SaveMeta>> {
+    let meta_path = save_path.join(SAVE_META_FILE_NAME);
+    if !meta_path.is_file() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(meta_path)?;
+    Ok(Some(toml::from_str(&contents)?))
+}
+
+pub fn write_save_meta(save_path: &Path, meta: &SaveMeta) -> anyhow::Result<()> {
+    fs::write(save_path.join(SAVE_META_FILE_NAME), toml::to_string_pretty(meta)?)?;
+    Ok(())
+}
+
+pub fn clear_savefile(saves_root: &Path, settings: &ServerSettings) -> anyhow::Result<()> {
+    if let Some(location) = resolve_save_location(saves_root, settings)? {
+        let save_path = location.path(saves_root);
+        if save_path.exists() {
+            fs::remove_dir_all(&save_path)?;
+            println!("remove savefile {:?}", save_path);
+        }
+    }
+    Ok(())
+}
+
+pub fn restore_savefile(archive_path: &str, saves_root: &Path, settings: &ServerSettings) -> anyhow::Result<SaveLocation> {
+    if !is_path_segment(&settings.game_name) {
+        bail!("{:?} is not a valid game name", settings.game_name);
+    }
+    let staging = saves_root.join(RESTORE_STAGING_DIR_NAME);
+    let _ = fs::remove_dir_all(&staging);
+    fs::create_dir_all(&staging)?;
+    unzip(archive_path, staging.to_string_lossy().as_ref())?;
+
+    // 元数据不对时只清理 staging，不动已有的存档
+    let world_dir = match restore_world_dir(&staging, saves_root, settings) {
+        Ok(world_dir) => world_dir,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+    };
+
+    let location = SaveLocation { world_dir, game_name: settings.game_name.clone() };
+    let save_path = location.path(saves_root);
+    let _ = fs::remove_dir_all(&save_path);
+    fs::create_dir_all(location.parent_path(saves_root))?;
+    fs::rename(&staging, &save_path)?;
+
+    println!("restore savefile {} to {:?}", archive_path, save_path);
+    Ok(location)
+}
+
+// 存档包里的元数据来自外部，世界目录名会被拼进路径，RWG 的 seed 和大小要和这次启动的设置一致
+fn restore_world_dir(staging: &Path, saves_root: &Path, settings: &ServerSettings) -> anyhow::Result<String> {
+    let meta = read_save_meta(staging)?;
+    if let Some(meta) = meta.as_ref() {
+        if !is_path_segment(&meta.world) {
+            bail!("savefile {} has invalid world {:?}", SAVE_META_FILE_NAME, meta.world);
+        }
+        if is_rwg(settings)
+            && let (Some(seed), Some(size)) = (&meta.world_gen_seed, meta.world_gen_size)
+            && (*seed != settings.world_gen_seed || size != settings.world_gen_size) {
+            bail!("savefile was made with seed {:?} size {}, but settings have seed {:?} size {}",
+                seed, size, settings.world_gen_seed, settings.world_gen_size);
+        }
+    }
+
+    if !is_rwg(settings) {
+        Ok(settings.game_world.clone())
+    } else if let Some(meta) = meta {
+        Ok(meta.world)
+    } else if let Some(world_dir) = find_rwg_world_dir(saves_root, settings)? {
+        Ok(world_dir)
+    } else {
+        bail!("cannot determine RWG world directory for game {}: savefile has no {}",
+            settings.game_name, SAVE_META_FILE_NAME);
+    }
+}
+
+pub fn backup_savefile(saves_root: &Path, settings: &ServerSettings, game_version: Option<String>, dst_file: &str) -> anyhow::Result<SaveLocation> {
+    let location = match resolve_save_location(saves_root, settings)? {
+        Some(location) => location,
+        None => bail!("no savefile found for game {} in {:?}", settings.game_name, saves_root),
+    };
+    let save_path = location.path(saves_root);
+    if !save_path.is_dir() {
+        bail!("savefile {:?} not exists", save_path);
+    }
+
+    let rwg = is_rwg(settings);
+    let meta = SaveMeta {
+        world: location.world_dir.clone(),
+        game_name: location.game_name.clone(),
+        game_version,
+        world_gen_seed: rwg.then(|| settings.world_gen_seed.clone()),
+        world_gen_size: rwg.then_some(settings.world_gen_size),
+    };
+    write_save_meta(&save_path, &meta)?;
+
+    let _ = fs::remove_file(dst_file);
+    zip(save_path.to_string_lossy().as_ref(), dst_file)?;
+    Ok(location)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use tempfile::tempdir;
+    use crate::archive::zip;
+    use crate::game_config_util::ServerSettings;
+    use crate::savefile_util::{backup_savefile, parse_game_time, read_save_meta, resolve_save_location, restore_savefile, write_save_meta, SaveLocation, SaveMeta,
+                               RWG_WORLD};
+
+    fn settings(world: &str, game_name: &str) -> ServerSettings {
+        ServerSettings {
+            game_world: world.to_string(),
+            game_name: game_name.to_string(),
+            ..ServerSettings::default()
+        }
+    }
+
+    #[test]
+    fn parse_game_time_test() {
+        let time = parse_game_time("*** Connected with 7DTD server.\r\nDay 12, 13:45\r\n").unwrap();
+        assert_eq!((time.day, time.hour, time.minute), (12, 13, 45));
+        assert_eq!(parse_game_time("Day 1, 07:00").map(|t| t.hour), Some(7));
+        assert_eq!(parse_game_time("unknown command"), None);
+    }
+
+    #[test]
+    fn resolve_pregen_test() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        let location = resolve_save_location(root, &settings("Pregen08k01", "Friends")).unwrap().unwrap();
+        assert_eq!(location.path(root), root.join("Pregen08k01").join("Friends"));
+    }
+
+    #[test]
+    fn resolve_rwg_test() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        assert_eq!(resolve_save_location(root, &settings(RWG_WORLD, "Friends")).unwrap(), None);
+
+        fs::create_dir_all(root.join("East Nuzoto Mountains").join("Friends")).unwrap();
+        let location = resolve_save_location(root, &settings(RWG_WORLD, "Friends")).unwrap().unwrap();
+        assert_eq!(location.world_dir, "East Nuzoto Mountains");
+    }
+
+    #[test]
+    fn resolve_rwg_by_seed_test() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        let rwg = ServerSettings { world_gen_seed: "friends".to_string(), world_gen_size: 8192, ..settings(RWG_WORLD, "Friends") };
+        let meta = |world: &str, seed: &str| SaveMeta {
+            world: world.to_string(),
+            game_name: "Friends".to_string(),
+            game_version: None,
+            world_gen_seed: Some(seed.to_string()),
+            world_gen_size: Some(8192),
+        };
+        for world in ["East Nuzoto Mountains", "West Pafoxi Territory"] {
+            fs::create_dir_all(root.join(world).join("Friends")).unwrap();
+        }
+        // 两个同名存档都没有元数据，不能靠修改时间猜
+        let error = resolve_save_location(root, &rwg).unwrap_err().to_string();
+        assert!(error.contains("East Nuzoto Mountains") && error.contains("West Pafoxi Territory"));
+
+        // 另一个世界的 seed 不同，排除之后只剩一个
+        write_save_meta(&root.join("East Nuzoto Mountains").join("Friends"), &meta("East Nuzoto Mountains", "other")).unwrap();
+        assert_eq!(resolve_save_location(root, &rwg).unwrap().unwrap().world_dir, "West Pafoxi Territory");
+
+        // seed 一致的优先于没有元数据的
+        write_save_meta(&root.join("East Nuzoto Mountains").join("Friends"), &meta("East Nuzoto Mountains", "friends")).unwrap();
+        assert_eq!(resolve_save_location(root, &rwg).unwrap().unwrap().world_dir, "East Nuzoto Mountains");
+    }
+
+    #[test]
+    fn backup_and_restore_rwg_test() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        let save_path = root.join("Saves").join("East Nuzoto Mountains").join("Friends");
+        fs::create_dir_all(&save_path).unwrap();
+        fs::write(save_path.join("main.ttw"), "world").unwrap();
+
+        let archive = root.join("Friends.zip");
+        let rwg = settings(RWG_WORLD, "Friends");
+        backup_savefile(&root.join("Saves"), &rwg, Some("public/20001234".to_string()), archive.to_str().unwrap()).unwrap();
+
+        // 新节点上没有任何存档，只能依靠元数据找到世界目录
+        let fresh_saves = root.join("FreshSaves");
+        let location = restore_savefile(archive.to_str().unwrap(), &fresh_saves, &rwg).unwrap();
+        assert_eq!(location, SaveLocation { world_dir: "East Nuzoto Mountains".to_string(), game_name: "Friends".to_string() });
+        assert_eq!(fs::read_to_string(location.path(&fresh_saves).join("main.ttw")).unwrap(), "world");
+        let meta = read_save_meta(&location.path(&fresh_saves)).unwrap().unwrap();
+        assert_eq!(meta.game_version, Some("public/20001234".to_string()));
+        assert_eq!((meta.world_gen_seed, meta.world_gen_size), (Some(rwg.world_gen_seed.clone()), Some(rwg.world_gen_size)));
+    }
+
+    #[test]
+    fn restore_rejects_bad_meta_test() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        let saves = root.join("Saves");
+        let existing = saves.join("East Nuzoto Mountains").join("Friends");
+        fs::create_dir_all(&existing).unwrap();
+        fs::write(existing.join("main.ttw"), "existing").unwrap();
+        let rwg = settings(RWG_WORLD, "Friends");
+        let archive = |name: &str, meta: SaveMeta| {
+            let dir = root.join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("main.ttw"), "restored").unwrap();
+            write_save_meta(&dir, &meta).unwrap();
+            let path = root.join(format!("{}.zip", name));
+            zip(dir.to_str().unwrap(), path.to_str().unwrap()).unwrap();
+            path
+        };
+        let meta = |world: &str, seed: &str| SaveMeta {
+            world: world.to_string(),
+            game_name: "Friends".to_string(),
+            game_version: None,
+            world_gen_seed: Some(seed.to_string()),
+            world_gen_size: Some(rwg.world_gen_size),
+        };
+
+        // 元数据里的世界名会被拼进路径
+        let escape = archive("escape", meta("..", &rwg.world_gen_seed));
+        assert!(restore_savefile(escape.to_str().unwrap(), &saves, &rwg).unwrap_err().to_string().contains("invalid world"));
+        // 另一个 seed 的存档不能恢复到这次的设置上
+        let other_seed = archive("other_seed", meta("East Nuzoto Mountains", "other"));
+        assert!(restore_savefile(other_seed.to_str().unwrap(), &saves, &rwg).unwrap_err().to_string().contains("seed"));
+        // 游戏名同样会被拼进路径
+        let ok = archive("ok", meta("East Nuzoto Mountains", &rwg.world_gen_seed));
+        assert!(restore_savefile(ok.to_str().unwrap(), &saves, &settings(RWG_WORLD, "../Friends")).is_err());
+
+        // 失败时已有的存档不动，也不留下 staging
+        assert_eq!(fs::read_to_string(existing.join("main.ttw")).unwrap(), "existing");
+        assert_eq!(fs::read_dir(&saves).unwrap().count(), 1);
+    }
+}
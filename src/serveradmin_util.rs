@@ -0,0 +1,414 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use crate::game_config_util::escape_xml;
+use crate::telnet_util::quote_arg;
+
+// 游戏里 "永久" 封禁就是封很多年
+const PERMANENT_BAN_MINUTES: u64 = 10 * 365 * 24 * 60;
+// 0 权限最高，1000 是普通玩家
+const MAX_PERMISSION_LEVEL: i32 = 1000;
+// 由这里解析和生成的顶层元素，其他的（apitokens、webusers、webmodules 等）原样保留
+const KNOWN_SECTIONS: [&str; 4] = ["users", "whitelist", "blacklist", "commands"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AdminUser {
+    pub platform: String,
+    pub userid: String,
+    #[serde(default)]
+    pub name: String,
+    pub permission_level: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WhitelistUser {
+    pub platform: String,
+    pub userid: String,
+    #[serde(default)]
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlacklistEntry {
+    pub platform: String,
+    pub userid: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub reason: String,
+    // 解封时间，UTC "yyyy-MM-dd HH:mm:ss"
+    pub unbandate: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CommandPermission {
+    pub cmd: String,
+    pub permission_level: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ServerAdmin {
+    pub admins: Vec<AdminUser>,
+    pub whitelist: Vec<WhitelistUser>,
+    pub blacklist: Vec<BlacklistEntry>,
+    pub commands: Vec<CommandPermission>,
+    // 不认识的顶层元素的原始 xml，写回时放在最后
+    #[serde(skip)]
+    pub unknown_sections: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BanRequest {
+    pub platform: String,
+    pub userid: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub reason: String,
+    // 为空表示永久封禁
+    pub duration_minutes: Option<u64>,
+}
+
+// 会被拼进控制台命令，只允许不含空白和引号的简单标识
+pub fn is_valid_token(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+pub fn is_valid_permission_level(level: i32) -> bool {
+    (0..=MAX_PERMISSION_LEVEL).contains(&level)
+}
+
+pub fn platform_id(platform: &str, userid: &str) -> String {
+    format!("{}_{}", platform, userid)
+}
+
+// 不引入时间库，按 civil-from-days 算法把 unix 时间转成 UTC 日期
+pub fn format_utc(unix_secs: u64) -> String {
+    let days = (unix_secs / 86400) as i64;
+    let secs_of_day = unix_secs % 86400;
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day,
+            secs_of_day / 3600, secs_of_day % 3600 / 60, secs_of_day % 60)
+}
+
+impl BanRequest {
+    pub fn duration_minutes(&self) -> u64 {
+        self.duration_minutes.unwrap_or(PERMANENT_BAN_MINUTES)
+    }
+
+    pub fn to_entry(&self) -> BlacklistEntry {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        BlacklistEntry {
+            platform: self.platform.clone(),
+            userid: self.userid.clone(),
+            name: self.name.clone(),
+            reason: self.reason.clone(),
+            unbandate: format_utc(now + self.duration_minutes() * 60),
+        }
+    }
+}
+
+impl ServerAdmin {
+    pub fn upsert_admin(&mut self, admin: AdminUser) {
+        self.admins.retain(|a| !(a.platform == admin.platform && a.userid == admin.userid));
+        self.admins.push(admin);
+    }
+
+    pub fn remove_admin(&mut self, platform: &str, userid: &str) -> bool {
+        let len = self.admins.len();
+        self.admins.retain(|a| !(a.platform == platform && a.userid == userid));
+        len != self.admins.len()
+    }
+
+    pub fn upsert_whitelist(&mut self, user: WhitelistUser) {
+        self.whitelist.retain(|u| !(u.platform == user.platform && u.userid == user.userid));
+        self.whitelist.push(user);
+    }
+
+    pub fn remove_whitelist(&mut self, platform: &str, userid: &str) -> bool {
+        let len = self.whitelist.len();
+        self.whitelist.retain(|u| !(u.platform == platform && u.userid == userid));
+        len != self.whitelist.len()
+    }
+
+    pub fn upsert_blacklist(&mut self, entry: BlacklistEntry) {
+        self.blacklist.retain(|b| !(b.platform == entry.platform && b.userid == entry.userid));
+        self.blacklist.push(entry);
+    }
+
+    pub fn remove_blacklist(&mut self, platform: &str, userid: &str) -> bool {
+        let len = self.blacklist.len();
+        self.blacklist.retain(|b| !(b.platform == platform && b.userid == userid));
+        len != self.blacklist.len()
+    }
+
+    pub fn upsert_command(&mut self, permission: CommandPermission) {
+        self.commands.retain(|c| c.cmd != permission.cmd);
+        self.commands.push(permission);
+    }
+
+    pub fn remove_command(&mut self, cmd: &str) -> bool {
+        let len = self.commands.len();
+        self.commands.retain(|c| c.cmd != cmd);
+        len != self.commands.len()
+    }
+}
+
+pub fn render_serveradmin_xml(admin: &ServerAdmin) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<adminTools>\n");
+
+    xml.push_str("  <users>\n");
+    for a in &admin.admins {
+        xml.push_str(&format!("    <user platform=\"{}\" userid=\"{}\" name=\"{}\" permission_level=\"{}\" />\n",
+            escape_xml(&a.platform), escape_xml(&a.userid), escape_xml(&a.name), a.permission_level));
+    }
+    xml.push_str("  </users>\n");
+
+    xml.push_str("  <whitelist>\n");
+    for u in &admin.whitelist {
+        xml.push_str(&format!("    <user platform=\"{}\" userid=\"{}\" name=\"{}\" />\n",
+            escape_xml(&u.platform), escape_xml(&u.userid), escape_xml(&u.name)));
+    }
+    xml.push_str("  </whitelist>\n");
+
+    xml.push_str("  <blacklist>\n");
+    for b in &admin.blacklist {
+        xml.push_str(&format!("    <blacklisted platform=\"{}\" userid=\"{}\" name=\"{}\" unbandate=\"{}\" reason=\"{}\" />\n",
+            escape_xml(&b.platform), escape_xml(&b.userid), escape_xml(&b.name), escape_xml(&b.unbandate), escape_xml(&b.reason)));
+    }
+    xml.push_str("  </blacklist>\n");
+
+    xml.push_str("  <commands>\n");
+    for c in &admin.commands {
+        xml.push_str(&format!("    <permission cmd=\"{}\" permission_level=\"{}\" />\n",
+            escape_xml(&c.cmd), c.permission_level));
+    }
+    xml.push_str("  </commands>\n");
+
+    for raw in &admin.unknown_sections {
+        xml.push_str(&format!("  {}\n", raw));
+    }
+
+    xml.push_str("</adminTools>\n");
+    xml
+}
+
+fn section<'a, 'input>(doc: &'a roxmltree::Document<'input>, name: &str, child: &'a str) -> impl Iterator<Item = roxmltree::Node<'a, 'input>> {
+    doc.root_element()
+        .children()
+        .filter(move |n| n.has_tag_name(name))
+        .flat_map(move |n| n.children().filter(move |c| c.has_tag_name(child)))
+}
+
+fn attr(node: &roxmltree::Node, name: &str) -> String {
+    node.attribute(name).unwrap_or_default().to_string()
+}
+
+fn level(node: &roxmltree::Node) -> anyhow::Result<i32> {
+    let raw = attr(node, "permission_level");
+    let level = raw.trim().parse::<i32>().map_err(|e| anyhow::anyhow!("bad permission_level {:?}: {}", raw, e))?;
+    if !is_valid_permission_level(level) {
+        anyhow::bail!("permission_level {} is not in 0..={}", level, MAX_PERMISSION_LEVEL);
+    }
+    Ok(level)
+}
+
+pub fn parse_serveradmin_xml(xml: &str) -> anyhow::Result<ServerAdmin> {
+    let doc = roxmltree::Document::parse(xml)?;
+
+    let mut admin = ServerAdmin::default();
+    for n in section(&doc, "users", "user") {
+        admin.admins.push(AdminUser {
+            platform: attr(&n, "platform"),
+            userid: attr(&n, "userid"),
+            name: attr(&n, "name"),
+            permission_level: level(&n)?,
+        });
+    }
+    for n in section(&doc, "whitelist", "user") {
+        admin.whitelist.push(WhitelistUser { platform: attr(&n, "platform"), userid: attr(&n, "userid"), name: attr(&n, "name") });
+    }
+    for n in section(&doc, "blacklist", "blacklisted") {
+        admin.blacklist.push(BlacklistEntry {
+            platform: attr(&n, "platform"),
+            userid: attr(&n, "userid"),
+            name: attr(&n, "name"),
+            reason: attr(&n, "reason"),
+            unbandate: attr(&n, "unbandate"),
+        });
+    }
+    for n in section(&doc, "commands", "permission") {
+        admin.commands.push(CommandPermission { cmd: attr(&n, "cmd"), permission_level: level(&n)? });
+    }
+    admin.unknown_sections = doc.root_element()
+        .children()
+        .filter(|n| n.is_element() && !KNOWN_SECTIONS.contains(&n.tag_name().name()))
+        .map(|n| xml[n.range()].to_string())
+        .collect();
+
+    Ok(admin)
+}
+
+pub async fn read_serveradmin_xml(path: &Path) -> anyhow::Result<ServerAdmin> {
+    if !fs::try_exists(path).await? {
+        return Ok(ServerAdmin::default());
+    }
+    let xml = fs::read_to_string(path).await?;
+    parse_serveradmin_xml(&xml)
+}
+
+pub async fn write_serveradmin_xml(path: &Path, admin: &ServerAdmin) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(path, render_serveradmin_xml(admin)).await?;
+    println!("write serveradmin xml to {:?}", path);
+    Ok(())
+}
+
+// 服务器运行时通过控制台命令修改，由游戏自己写回 serveradmin.xml
+pub fn admin_add_command(admin: &AdminUser) -> String {
+    format!("admin add {} {} {}", platform_id(&admin.platform, &admin.userid), admin.permission_level, quote_arg(&admin.name))
+}
+
+pub fn admin_remove_command(platform: &str, userid: &str) -> String {
+    format!("admin remove {}", platform_id(platform, userid))
+}
+
+pub fn whitelist_add_command(user: &WhitelistUser) -> String {
+    format!("whitelist add {} {}", platform_id(&user.platform, &user.userid), quote_arg(&user.name))
+}
+
+pub fn whitelist_remove_command(platform: &str, userid: &str) -> String {
+    format!("whitelist remove {}", platform_id(platform, userid))
+}
+
+pub fn ban_add_command(ban: &BanRequest) -> String {
+    format!("ban add {} {} minutes {} {}", platform_id(&ban.platform, &ban.userid), ban.duration_minutes(),
+            quote_arg(&ban.reason), quote_arg(&ban.name))
+}
+
+pub fn ban_remove_command(platform: &str, userid: &str) -> String {
+    format!("ban remove {}", platform_id(platform, userid))
+}
+
+pub fn command_permission_add_command(permission: &CommandPermission) -> String {
+    format!("cp add {} {}", permission.cmd, permission.permission_level)
+}
+
+pub fn command_permission_remove_command(cmd: &str) -> String {
+    format!("cp remove {}", cmd)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::serveradmin_util::{admin_add_command, ban_add_command, format_utc, is_valid_permission_level, is_valid_token, parse_serveradmin_xml,
+                                  render_serveradmin_xml, AdminUser, BanRequest, BlacklistEntry, CommandPermission,
+                                  ServerAdmin, WhitelistUser};
+
+    #[test]
+    fn format_utc_test() {
+        assert_eq!(format_utc(0), "1970-01-01 00:00:00");
+        assert_eq!(format_utc(951782400 + 3661), "2000-02-29 01:01:01");
+        assert_eq!(format_utc(1767225599), "2025-12-31 23:59:59");
+    }
+
+    #[test]
+    fn render_parse_roundtrip_test() {
+        let mut admin = ServerAdmin::default();
+        admin.upsert_admin(AdminUser { platform: "Steam".to_string(), userid: "76561198000000001".to_string(), name: "Bob & \"Friends\"".to_string(), permission_level: 0 });
+        admin.upsert_admin(AdminUser { platform: "Steam".to_string(), userid: "76561198000000001".to_string(), name: "Bob".to_string(), permission_level: 1 });
+        admin.upsert_whitelist(WhitelistUser { platform: "EOS".to_string(), userid: "0002abcd".to_string(), name: "Alice".to_string() });
+        admin.upsert_blacklist(BlacklistEntry { platform: "Steam".to_string(), userid: "76561198000000002".to_string(), name: "Griefer".to_string(), reason: "<griefing>".to_string(), unbandate: "2030-01-01 00:00:00".to_string() });
+        admin.upsert_command(CommandPermission { cmd: "kill".to_string(), permission_level: 0 });
+
+        assert_eq!(admin.admins.len(), 1);
+        let parsed = parse_serveradmin_xml(&render_serveradmin_xml(&admin)).unwrap();
+        assert_eq!(parsed, admin);
+        assert!(admin.clone().remove_whitelist("EOS", "0002abcd"));
+    }
+
+    #[test]
+    fn parse_game_written_test() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<adminTools>
+  <users>
+    <user platform="Steam" userid="76561198000000001" name="Hint on who this user is" permission_level="0" />
+  </users>
+  <whitelist />
+  <blacklist />
+  <commands>
+    <permission cmd="dm" permission_level="0" />
+  </commands>
+  <apitokens />
+</adminTools>"#;
+        let parsed = parse_serveradmin_xml(xml).unwrap();
+        assert_eq!(parsed.admins[0].permission_level, 0);
+        assert_eq!(parsed.commands[0].cmd, "dm");
+        assert!(parsed.whitelist.is_empty());
+    }
+
+    #[test]
+    fn keep_unknown_sections_test() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<adminTools>
+  <users>
+    <user platform="Steam" userid="76561198000000001" name="Bob" permission_level="0" />
+  </users>
+  <whitelist />
+  <blacklist />
+  <commands />
+  <apitokens>
+    <token name="panel" token="abc" permission_level="0" />
+  </apitokens>
+  <webusers>
+    <user name="admin" pass="hash" platform="Steam" userid="76561198000000001" crossplatform="" crossuserid="" />
+  </webusers>
+  <webmodules>
+    <module name="web.map" permission_level="1000" />
+  </webmodules>
+</adminTools>"#;
+        let mut parsed = parse_serveradmin_xml(xml).unwrap();
+        assert_eq!(parsed.unknown_sections.len(), 3);
+        parsed.upsert_command(CommandPermission { cmd: "kill".to_string(), permission_level: 0 });
+
+        let rendered = render_serveradmin_xml(&parsed);
+        assert!(rendered.contains(r#"<token name="panel" token="abc" permission_level="0" />"#));
+        assert!(rendered.contains(r#"<module name="web.map" permission_level="1000" />"#));
+        assert_eq!(parse_serveradmin_xml(&rendered).unwrap(), parsed);
+    }
+
+    #[test]
+    fn permission_level_range_test() {
+        assert!(is_valid_permission_level(0) && is_valid_permission_level(1000));
+        assert!(!is_valid_permission_level(-1) && !is_valid_permission_level(1001));
+        let xml = r#"<adminTools><users><user platform="Steam" userid="1" permission_level="5000" /></users></adminTools>"#;
+        assert!(parse_serveradmin_xml(xml).unwrap_err().to_string().contains("5000"));
+    }
+
+    #[test]
+    fn is_valid_token_test() {
+        assert!(is_valid_token("76561198000000001"));
+        assert!(is_valid_token("Steam"));
+        assert!(!is_valid_token("kill; shutdown"));
+        assert!(!is_valid_token(""));
+    }
+
+    #[test]
+    fn console_command_test() {
+        let admin = AdminUser { platform: "Steam".to_string(), userid: "1".to_string(), name: "Big Bob".to_string(), permission_level: 0 };
+        assert_eq!(admin_add_command(&admin), "admin add Steam_1 0 \"Big Bob\"");
+
+        let ban = BanRequest { platform: "Steam".to_string(), userid: "2".to_string(), name: "".to_string(), reason: "cheating".to_string(), duration_minutes: Some(60) };
+        assert_eq!(ban_add_command(&ban), "ban add Steam_2 60 minutes \"cheating\" \"\"");
+    }
+}
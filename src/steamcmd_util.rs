@@ -0,0 +1,197 @@
+use std::path::Path;
+use std::process::Stdio;
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+
+pub const SEVENDAYS_APP_ID: u32 = 294420;
+pub const INSTALL_INFO_FILE_NAME: &str = "game_master_install.toml";
+pub const DEFAULT_BRANCH: &str = "public";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InstallProgress {
+    pub state: String,
+    pub percent: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InstallInfo {
+    pub app_id: u32,
+    pub build_id: String,
+    pub branch: String,
+    pub installed_at: u64,
+}
+
+// " Update state (0x61) downloading, progress: 45.12 (1234 / 5678)"
+pub fn parse_progress_line(line: &str) -> Option<InstallProgress> {
+    let rest = line.trim().strip_prefix("Update state (")?;
+    let (_, rest) = rest.split_once(')')?;
+    let (state, rest) = rest.split_once(", progress:")?;
+    let percent = rest.split_whitespace().next()?.parse::<f32>().ok()?;
+    Some(InstallProgress { state: state.trim().to_string(), percent })
+}
+
+// appmanifest_294420.acf 里的 "buildid"		"12345"
+pub fn parse_build_id(acf: &str) -> Option<String> {
+    acf.lines().find_map(|line| {
+        let mut parts = line.split('"').filter(|p| !p.trim().is_empty());
+        match (parts.next(), parts.next()) {
+            (Some(key), Some(value)) if key.eq_ignore_ascii_case("buildid") => Some(value.to_string()),
+            _ => None,
+        }
+    })
+}
+
+pub fn is_valid_branch(branch: &str) -> bool {
+    !branch.is_empty() && branch.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+pub async fn read_install_info(install_dir: &Path) -> anyhow::Result<Option<InstallInfo>> {
+    let path = install_dir.join(INSTALL_INFO_FILE_NAME);
+    if !fs::try_exists(&path).await? {
+        return Ok(None);
+    }
+    Ok(Some(toml::from_str(&fs::read_to_string(path).await?)?))
+}
+
+pub async fn install_game_server(
+    steamcmd: &Path,
+    install_dir: &Path,
+    branch: Option<&str>,
+    mut on_progress: impl FnMut(InstallProgress)) -> anyhow::Result<InstallInfo> {
+    if let Some(branch) = branch && !is_valid_branch(branch) {
+        bail!("invalid branch {:?}", branch);
+    }
+    fs::create_dir_all(install_dir).await?;
+
+    let mut cmd = Command::new(steamcmd);
+    cmd.arg("+force_install_dir")
+        .arg(install_dir)
+        .arg("+login")
+        .arg("anonymous")
+        .arg("+app_update")
+        .arg(SEVENDAYS_APP_ID.to_string());
+    if let Some(branch) = branch {
+        cmd.arg("-beta").arg(branch);
+    }
+    cmd.arg("validate").arg("+quit");
+
+    println!("run steamcmd: {:?}", cmd);
+    let mut child = cmd.stdout(Stdio::piped()).spawn()?;
+    let stdout = child.stdout.take().expect("steamcmd stdout is piped");
+
+    let mut reader = BufReader::new(stdout);
+    let mut buf = Vec::new();
+    let mut error_line = None;
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf).await? == 0 {
+            break;
+        }
+        // steamcmd 有时用 \r 刷新同一行
+        for line in String::from_utf8_lossy(&buf).split('\r') {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            println!("steamcmd: {}", line);
+            if let Some(progress) = parse_progress_line(line) {
+                on_progress(progress);
+            } else if line.starts_with("Error!") {
+                error_line = Some(line.to_string());
+            }
+        }
+    }
+
+    let status = child.wait().await?;
+    if let Some(line) = error_line {
+        bail!("steamcmd failed: {}", line);
+    }
+    if !status.success() {
+        bail!("steamcmd exited with {}", status);
+    }
+
+    let acf_path = install_dir.join("steamapps").join(format!("appmanifest_{}.acf", SEVENDAYS_APP_ID));
+    let acf = fs::read_to_string(&acf_path).await?;
+    let build_id = match parse_build_id(&acf) {
+        Some(build_id) => build_id,
+        None => bail!("no buildid in {:?}", acf_path),
+    };
+
+    let info = InstallInfo {
+        app_id: SEVENDAYS_APP_ID,
+        build_id,
+        branch: branch.unwrap_or(DEFAULT_BRANCH).to_string(),
+        installed_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    };
+    fs::write(install_dir.join(INSTALL_INFO_FILE_NAME), toml::to_string_pretty(&info)?).await?;
+
+    println!("installed 7days build {} ({})", info.build_id, info.branch);
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+    use tempfile::tempdir;
+    use crate::steamcmd_util::{install_game_server, parse_build_id, parse_progress_line, read_install_info, InstallProgress};
+
+    // 模拟 steamcmd：打印进度并写 appmanifest
+    fn stub_steamcmd(root: &Path, body: &str) -> PathBuf {
+        let path = root.join("steamcmd.sh");
+        std::fs::write(&path, format!("#!/bin/sh\nINSTALL_DIR=\"$2\"\necho \"args: $*\"\n{}\n", body)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn parse_progress_line_test() {
+        assert_eq!(parse_progress_line(" Update state (0x61) downloading, progress: 45.12 (1234 / 5678)"),
+                   Some(InstallProgress { state: "downloading".to_string(), percent: 45.12 }));
+        assert_eq!(parse_progress_line("Success! App '294420' fully installed."), None);
+    }
+
+    #[test]
+    fn parse_build_id_test() {
+        let acf = "\"AppState\"\n{\n\t\"appid\"\t\t\"294420\"\n\t\"buildid\"\t\t\"19876543\"\n}";
+        assert_eq!(parse_build_id(acf), Some("19876543".to_string()));
+    }
+
+    #[tokio::test]
+    async fn install_with_stub_test() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        let steamcmd = stub_steamcmd(root, r#"
+echo " Update state (0x61) downloading, progress: 10.00 (1 / 10)"
+printf " Update state (0x61) downloading, progress: 50.00 (5 / 10)\r Update state (0x81) verifying update, progress: 90.00 (9 / 10)\n"
+mkdir -p "$INSTALL_DIR/steamapps"
+printf '"AppState"\n{\n\t"buildid"\t\t"20001234"\n}\n' > "$INSTALL_DIR/steamapps/appmanifest_294420.acf"
+echo "Success! App '294420' fully installed."
+"#);
+        let install_dir = root.join("7DaysToDieServer");
+
+        let mut progress = Vec::new();
+        let info = install_game_server(&steamcmd, &install_dir, Some("latest_experimental"), |p| progress.push(p.percent))
+            .await
+            .unwrap();
+        assert_eq!(progress, vec![10.0, 50.0, 90.0]);
+        assert_eq!(info.build_id, "20001234");
+        assert_eq!(info.branch, "latest_experimental");
+        assert_eq!(read_install_info(&install_dir).await.unwrap(), Some(info));
+    }
+
+    #[tokio::test]
+    async fn install_error_test() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        let steamcmd = stub_steamcmd(root, r#"echo "Error! App '294420' state is 0x202 after update job.""#);
+        let err = install_game_server(&steamcmd, &root.join("server"), None, |_| {}).await.unwrap_err();
+        assert!(err.to_string().contains("0x202"));
+
+        assert!(install_game_server(&steamcmd, &root.join("server"), Some("bad branch"), |_| {}).await.is_err());
+    }
+}
@@ -0,0 +1,102 @@
+use std::path::{Component, Path, PathBuf};
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use crate::config_util::AppConfig;
+use crate::s3::{download_file, get_rustfs_client, upload_file};
+
+// 存档和 mod 包的存放位置
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    // data server 返回的 host 上的 S3 兼容存储（rustfs）
+    S3,
+    // 本地目录 <local_storage_dir>/<bucket>/<key>，开发和测试用
+    Local,
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectStorage {
+    backend: StorageBackend,
+    local_dir: PathBuf,
+}
+
+impl ObjectStorage {
+    pub fn from_config(config: &AppConfig) -> Self {
+        ObjectStorage { backend: config.storage_backend, local_dir: config.local_storage_dir.clone() }
+    }
+
+    // 不允许 key 跳出 bucket 目录
+    fn local_path(&self, bucket: &str, key: &str) -> anyhow::Result<PathBuf> {
+        let relative = Path::new(bucket).join(key);
+        if bucket.is_empty() || key.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            bail!("invalid object {}/{}", bucket, key);
+        }
+        Ok(self.local_dir.join(relative))
+    }
+
+    pub async fn download(&self, host: Option<String>, bucket: &str, key: &str, dst: &Path) -> anyhow::Result<()> {
+        match self.backend {
+            StorageBackend::S3 => {
+                let client = get_rustfs_client(host).await?;
+                download_file(&client, &dst.to_string_lossy(), bucket, key).await
+            }
+            StorageBackend::Local => {
+                let src = self.local_path(bucket, key)?;
+                fs::copy(&src, dst).await.map_err(|e| anyhow::anyhow!("copy {:?}: {}", src, e))?;
+                println!("Object {}/{} copied to {:?}", bucket, key, dst);
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn upload(&self, host: Option<String>, bucket: &str, key: &str, src: &Path) -> anyhow::Result<()> {
+        match self.backend {
+            StorageBackend::S3 => {
+                let client = get_rustfs_client(host).await?;
+                upload_file(&client, &src.to_string_lossy(), bucket, key).await
+            }
+            StorageBackend::Local => {
+                let dst = self.local_path(bucket, key)?;
+                if let Some(parent) = dst.parent() {
+                    fs::create_dir_all(parent).await?;
+                }
+                fs::copy(src, &dst).await.map_err(|e| anyhow::anyhow!("copy to {:?}: {}", dst, e))?;
+                println!("Object {}/{} stored at {:?}", bucket, key, dst);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+    use crate::config_util::AppConfig;
+    use crate::storage_util::{ObjectStorage, StorageBackend};
+
+    #[tokio::test]
+    async fn local_storage_test() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        let storage = ObjectStorage::from_config(&AppConfig {
+            storage_backend: StorageBackend::Local,
+            local_storage_dir: root.join("objects"),
+            ..AppConfig::default()
+        });
+
+        let src = root.join("MyGame.zip");
+        std::fs::write(&src, "zip").unwrap();
+        storage.upload(None, "days7server", "000001/MyGame.zip", &src).await.unwrap();
+        assert!(root.join("objects/days7server/000001/MyGame.zip").is_file());
+
+        let dst = root.join("downloaded.zip");
+        storage.download(Some("http://ignored".to_string()), "days7server", "000001/MyGame.zip", &dst).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&dst).unwrap(), "zip");
+
+        assert!(storage.download(None, "days7server", "../../etc/passwd", &dst).await.is_err());
+        assert!(storage.upload(None, "", "a.zip", &src).await.is_err());
+        assert!(storage.download(None, "days7server", "missing.zip", &PathBuf::from(&dst)).await.is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use std::time::Duration;
+use anyhow::bail;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+const TELNET_TIMEOUT: Duration = Duration::from_secs(5);
+// 命令执行后等待输出的时间，服务器不会告诉我们输出何时结束
+const TELNET_OUTPUT_WAIT: Duration = Duration::from_millis(500);
+
+async fn read_available(stream: &mut TcpStream, wait: Duration) -> anyhow::Result<String> {
+    let mut output = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        match timeout(wait, stream.read(&mut buf)).await {
+            Ok(Ok(0)) => break,
+            Ok(Ok(n)) => output.extend_from_slice(&buf[..n]),
+            Ok(Err(e)) => return Err(e.into()),
+            Err(_) => break,
+        }
+    }
+    Ok(String::from_utf8_lossy(&output).to_string())
+}
+
+async fn read_until(stream: &mut TcpStream, pattern: &str) -> anyhow::Result<String> {
+    let mut output = String::new();
+    let mut buf = [0u8; 4096];
+    while !output.contains(pattern) {
+        let n = timeout(TELNET_TIMEOUT, stream.read(&mut buf)).await??;
+        if n == 0 {
+            bail!("telnet connection closed before {:?}", pattern);
+        }
+        output.push_str(&String::from_utf8_lossy(&buf[..n]));
+    }
+    Ok(output)
+}
+
+// 给参数加引号，7dtd 控制台支持 "带 空格" 的参数
+pub fn quote_arg(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('"', "'"))
+}
+
+pub async fn send_console_command(port: u16, password: &str, command: &str) -> anyhow::Result<String> {
+    if command.contains(['\r', '\n']) {
+        bail!("console command must be a single line");
+    }
+
+    let mut stream = timeout(TELNET_TIMEOUT, TcpStream::connect(("127.0.0.1", port))).await??;
+    if !password.is_empty() {
+        read_until(&mut stream, "password").await?;
+        stream.write_all(format!("{}\r\n", password).as_bytes()).await?;
+        let reply = read_until(&mut stream, "\n").await?;
+        if reply.contains("Password incorrect") {
+            bail!("telnet password incorrect");
+        }
+    }
+    // 丢弃欢迎信息
+    read_available(&mut stream, TELNET_OUTPUT_WAIT).await?;
+
+    println!("send console command: {}", command);
+    stream.write_all(format!("{}\r\n", command).as_bytes()).await?;
+    let output = read_available(&mut stream, TELNET_OUTPUT_WAIT).await?;
+    let _ = stream.write_all(b"exit\r\n").await;
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use crate::telnet_util::{quote_arg, send_console_command};
+
+    #[test]
+    fn quote_arg_test() {
+        assert_eq!(quote_arg("Big \"Bob\" Smith"), "\"Big 'Bob' Smith\"");
+    }
+
+    #[tokio::test]
+    async fn send_console_command_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            socket.write_all(b"Please enter password:\r\n").await.unwrap();
+            let mut buf = [0u8; 256];
+            let n = socket.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"secret\r\n");
+            socket.write_all(b"Logon successful.\r\n*** Connected with 7DTD server.\r\n").await.unwrap();
+            let n = socket.read(&mut buf).await.unwrap();
+            let command = String::from_utf8_lossy(&buf[..n]).trim().to_string();
+            socket.write_all(format!("Executing command '{}'\r\n", command).as_bytes()).await.unwrap();
+        });
+
+        let output = send_console_command(port, "secret", "admin add Steam_1 0").await.unwrap();
+        assert!(output.contains("Executing command 'admin add Steam_1 0'"));
+    }
+}
@@ -0,0 +1,199 @@
+use std::sync::Arc;
+use anyhow::anyhow;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use crate::common::get_local_ip;
+use crate::config_util::AppConfig;
+use crate::frp_util::{frpc_config_init, frpc_config_reload, PortMapping, ProxyType};
+use crate::frpc_admin_util::{FrpcAdminClient, ProxyStatus};
+use crate::frpc_supervisor_util::FrpcSupervisor;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TunnelProviderKind {
+    // 经 frps 转发
+    Frp,
+    // 主机有公网 IP 或端口转发，玩家直连本地端口
+    Direct,
+}
+
+// 玩家实际连接的地址
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PublicEndpoint {
+    pub role: String,
+    pub proxy_type: ProxyType,
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TunnelStatus {
+    pub provider: TunnelProviderKind,
+    // 所有代理都在运行
+    pub healthy: bool,
+    pub proxies: Vec<ProxyStatus>,
+}
+
+pub trait TunnelProvider: Send + Sync {
+    fn kind(&self) -> TunnelProviderKind;
+
+    // 按端口映射建立隧道，已在运行时重新加载
+    fn configure<'a>(&'a self, ports: &'a [PortMapping]) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    fn reload(&self) -> BoxFuture<'_, anyhow::Result<()>>;
+
+    fn status(&self) -> BoxFuture<'_, anyhow::Result<TunnelStatus>>;
+
+    fn public_host(&self) -> String;
+
+    fn public_endpoints(&self, ports: &[PortMapping]) -> Vec<PublicEndpoint>;
+}
+
+pub struct FrpTunnel {
+    config: Arc<AppConfig>,
+    frpc: FrpcSupervisor,
+}
+
+impl FrpTunnel {
+    pub fn new(config: Arc<AppConfig>, frpc: FrpcSupervisor) -> Self {
+        FrpTunnel { config, frpc }
+    }
+}
+
+impl TunnelProvider for FrpTunnel {
+    fn kind(&self) -> TunnelProviderKind {
+        TunnelProviderKind::Frp
+    }
+
+    // 写 frpc.toml，frpc 未启动时启动，否则重新加载
+    fn configure<'a>(&'a self, ports: &'a [PortMapping]) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            frpc_config_init(&self.config, ports).await.map_err(|e| anyhow!("write frpc.toml: {}", e))?;
+            if self.config.frpc_supervise
+                && self.frpc.start(self.config.frpc_exe_path.clone(), self.config.frpc_toml_path.clone()).await {
+                return Ok(());
+            }
+            frpc_config_reload(&self.config).await
+        })
+    }
+
+    fn reload(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(frpc_config_reload(&self.config))
+    }
+
+    fn status(&self) -> BoxFuture<'_, anyhow::Result<TunnelStatus>> {
+        Box::pin(async move {
+            let proxies = FrpcAdminClient::from_config(&self.config)?.status().await?;
+            for proxy in proxies.iter().filter(|p| p.remote_port_taken()) {
+                println!("proxy {} remote port is taken: {}", proxy.name, proxy.err);
+            }
+            Ok(TunnelStatus { provider: self.kind(), healthy: proxies.iter().all(|p| p.is_running()), proxies })
+        })
+    }
+
+    fn public_host(&self) -> String {
+        self.config.frps_addr.clone()
+    }
+
+    fn public_endpoints(&self, ports: &[PortMapping]) -> Vec<PublicEndpoint> {
+        ports.iter()
+            .map(|p| PublicEndpoint { role: p.role.clone(), proxy_type: p.proxy_type.clone(), host: self.public_host(), port: p.remote_port })
+            .collect()
+    }
+}
+
+// 不启动 frpc，只报告主机地址和本地端口
+pub struct DirectTunnel {
+    host: String,
+}
+
+impl DirectTunnel {
+    // public_host 为空时用 net_interface_name 网卡的地址
+    pub fn new(config: &AppConfig) -> anyhow::Result<Self> {
+        let host = if config.public_host.is_empty() {
+            get_local_ip(&config.net_interface_name)
+                .ok_or_else(|| anyhow!("public_host is not set and interface {} has no ipv4 address", config.net_interface_name))?
+        } else {
+            config.public_host.clone()
+        };
+        Ok(DirectTunnel { host })
+    }
+}
+
+impl TunnelProvider for DirectTunnel {
+    fn kind(&self) -> TunnelProviderKind {
+        TunnelProviderKind::Direct
+    }
+
+    fn configure<'a>(&'a self, ports: &'a [PortMapping]) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            println!("direct tunnel: {} ports reachable on {}", ports.len(), self.host);
+            Ok(())
+        })
+    }
+
+    fn reload(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn status(&self) -> BoxFuture<'_, anyhow::Result<TunnelStatus>> {
+        Box::pin(async move { Ok(TunnelStatus { provider: self.kind(), healthy: true, proxies: Vec::new() }) })
+    }
+
+    fn public_host(&self) -> String {
+        self.host.clone()
+    }
+
+    fn public_endpoints(&self, ports: &[PortMapping]) -> Vec<PublicEndpoint> {
+        ports.iter()
+            .map(|p| PublicEndpoint { role: p.role.clone(), proxy_type: p.proxy_type.clone(), host: self.host.clone(), port: p.local_port })
+            .collect()
+    }
+}
+
+pub fn tunnel_provider(config: Arc<AppConfig>, frpc: FrpcSupervisor) -> anyhow::Result<Arc<dyn TunnelProvider>> {
+    Ok(match config.tunnel_provider {
+        TunnelProviderKind::Frp => Arc::new(FrpTunnel::new(config, frpc)),
+        TunnelProviderKind::Direct => Arc::new(DirectTunnel::new(&config)?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::config_util::AppConfig;
+    use crate::frp_util::allocate_ports;
+    use crate::frpc_supervisor_util::FrpcSupervisor;
+    use crate::tunnel_util::{tunnel_provider, TunnelProviderKind};
+
+    #[tokio::test]
+    async fn direct_tunnel_test() {
+        let config = Arc::new(AppConfig {
+            tunnel_provider: TunnelProviderKind::Direct,
+            public_host: "203.0.113.7".to_string(),
+            frpc_expose_telnet: true,
+            ..AppConfig::default()
+        });
+        let tunnel = tunnel_provider(config.clone(), FrpcSupervisor::default()).unwrap();
+        let ports = allocate_ports(&config, 3);
+        tunnel.configure(&ports).await.unwrap();
+
+        let status = tunnel.status().await.unwrap();
+        assert!(status.healthy);
+        assert_eq!(status.provider, TunnelProviderKind::Direct);
+        // 直连时公网端口就是本地端口
+        let endpoints: Vec<(String, u16)> = tunnel.public_endpoints(&ports).into_iter().map(|e| (e.host, e.port)).collect();
+        assert_eq!(endpoints[0], ("203.0.113.7".to_string(), 26900));
+        assert_eq!(endpoints.last(), Some(&("203.0.113.7".to_string(), 8081)));
+    }
+
+    #[test]
+    fn frp_endpoints_test() {
+        let config = Arc::new(AppConfig { frps_addr: "frps.example.com".to_string(), ..AppConfig::default() });
+        let tunnel = tunnel_provider(config.clone(), FrpcSupervisor::default()).unwrap();
+        assert_eq!(tunnel.kind(), TunnelProviderKind::Frp);
+        let endpoints = tunnel.public_endpoints(&allocate_ports(&config, 1));
+        assert!(endpoints.iter().all(|e| e.host == "frps.example.com"));
+        assert_eq!(endpoints[0].port, 26910);
+    }
+}
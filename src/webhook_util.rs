@@ -0,0 +1,316 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::fs;
+use tokio::sync::{Mutex, Notify};
+use crate::common::hmac_sha256_hex;
+use crate::config_util::AppConfig;
+use crate::data_server_util::{SIGNATURE_HEADER, TIMESTAMP_HEADER};
+use crate::frpc_supervisor_util::backoff_delay;
+use crate::gameserver_util::GameLogEvent;
+use crate::lease_util::now_secs;
+
+pub const EVENT_HEADER: &str = "x-game-master-event";
+pub const DELIVERY_HEADER: &str = "x-game-master-delivery";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(600);
+// 积压超过这个数时丢掉最旧的事件
+const MAX_OUTBOX_ENTRIES: usize = 500;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    ServerReady,
+    ServerCrashed,
+    ServerStopped,
+    BackupUploaded,
+    BackupFailed,
+    PlayerJoined,
+    PlayerLeft,
+    TunnelDown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Event {
+    // 接收方按 id 去重
+    pub id: String,
+    pub kind: EventKind,
+    pub node_id: String,
+    pub index: Option<u8>,
+    pub occurred_at: u64,
+    pub data: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OutboxEntry {
+    pub url: String,
+    pub event: Event,
+    pub attempts: u32,
+    pub next_attempt_at: u64,
+}
+
+// 签名内容为 "<timestamp>.<body>"，放在 x-game-master-signature: sha256=<hex>
+pub fn sign_payload(secret: &str, timestamp: u64, body: &[u8]) -> String {
+    let mut message = format!("{}.", timestamp).into_bytes();
+    message.extend_from_slice(body);
+    format!("sha256={}", hmac_sha256_hex(secret.as_bytes(), &message))
+}
+
+pub fn game_log_event(event: &GameLogEvent) -> (EventKind, Value) {
+    match event {
+        GameLogEvent::Ready => (EventKind::ServerReady, json!({})),
+        GameLogEvent::PlayerJoined { name, entity_id, platform_id } =>
+            (EventKind::PlayerJoined, json!({"name": name, "entity_id": entity_id, "platform_id": platform_id})),
+        GameLogEvent::PlayerLeft { name, entity_id, platform_id } =>
+            (EventKind::PlayerLeft, json!({"name": name, "entity_id": entity_id, "platform_id": platform_id})),
+    }
+}
+
+// 待发送的事件写在 outbox 文件里，重启后继续投递
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    outbox: Arc<Mutex<VecDeque<OutboxEntry>>>,
+    notify: Arc<Notify>,
+    sequence: Arc<AtomicU64>,
+    urls: Vec<String>,
+    secret: String,
+    node_id: String,
+    outbox_path: PathBuf,
+    max_attempts: u32,
+    client: Client,
+}
+
+impl WebhookDispatcher {
+    pub async fn open(config: &AppConfig, node_id: &str) -> anyhow::Result<Self> {
+        let outbox: VecDeque<OutboxEntry> = match fs::read_to_string(&config.webhook_outbox_path).await {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => VecDeque::new(),
+            Err(e) => return Err(e.into()),
+        };
+        if !outbox.is_empty() {
+            println!("{} webhook events left in {:?}", outbox.len(), config.webhook_outbox_path);
+        }
+        Ok(WebhookDispatcher {
+            outbox: Arc::new(Mutex::new(outbox)),
+            notify: Arc::new(Notify::new()),
+            sequence: Arc::new(AtomicU64::new(0)),
+            urls: config.webhook_urls.clone(),
+            secret: config.webhook_secret.clone(),
+            node_id: node_id.to_string(),
+            outbox_path: config.webhook_outbox_path.clone(),
+            max_attempts: config.webhook_max_attempts,
+            client: Client::builder().timeout(REQUEST_TIMEOUT).build()?,
+        })
+    }
+
+    async fn persist(&self, outbox: &VecDeque<OutboxEntry>) {
+        let result = async {
+            if let Some(parent) = self.outbox_path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            let temp = self.outbox_path.with_extension("tmp");
+            fs::write(&temp, serde_json::to_vec(outbox)?).await?;
+            fs::rename(&temp, &self.outbox_path).await?;
+            anyhow::Ok(())
+        }.await;
+        if let Err(e) = result {
+            eprintln!("write webhook outbox {:?} error: {}", self.outbox_path, e);
+        }
+    }
+
+    pub async fn pending(&self) -> Vec<OutboxEntry> {
+        self.outbox.lock().await.iter().cloned().collect()
+    }
+
+    // 没有配置 webhook_urls 时什么都不做
+    pub async fn emit(&self, kind: EventKind, index: Option<u8>, data: Value) {
+        if self.urls.is_empty() {
+            return;
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let event = Event {
+            id: format!("{}-{}-{}", self.node_id, now.as_millis(), self.sequence.fetch_add(1, Ordering::Relaxed)),
+            kind,
+            node_id: self.node_id.clone(),
+            index,
+            occurred_at: now.as_secs(),
+            data,
+        };
+        println!("event {:?} {}", kind, event.data);
+        {
+            let mut outbox = self.outbox.lock().await;
+            for url in &self.urls {
+                outbox.push_back(OutboxEntry { url: url.clone(), event: event.clone(), attempts: 0, next_attempt_at: 0 });
+            }
+            while outbox.len() > MAX_OUTBOX_ENTRIES {
+                if let Some(dropped) = outbox.pop_front() {
+                    eprintln!("webhook outbox is full, drop event {}", dropped.event.id);
+                }
+            }
+            self.persist(&outbox).await;
+        }
+        self.notify.notify_one();
+    }
+
+    async fn deliver(&self, entry: &OutboxEntry) -> anyhow::Result<()> {
+        let body = serde_json::to_vec(&entry.event)?;
+        let timestamp = now_secs();
+        let kind = serde_json::to_value(entry.event.kind)?;
+        let response = self.client.post(&entry.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, kind.as_str().unwrap_or_default())
+            .header(DELIVERY_HEADER, &entry.event.id)
+            .header(TIMESTAMP_HEADER, timestamp)
+            .header(SIGNATURE_HEADER, sign_payload(&self.secret, timestamp, &body))
+            .body(body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            anyhow::bail!("{} returned {}", entry.url, response.status());
+        }
+        Ok(())
+    }
+
+    // 投递到期的事件，返回成功的条数；失败的按退避时间推迟，超过 max_attempts 后丢弃
+    pub async fn deliver_due(&self, now: u64) -> usize {
+        let due: Vec<OutboxEntry> = self.outbox.lock().await.iter().filter(|e| e.next_attempt_at <= now).cloned().collect();
+        let mut delivered = 0;
+        for entry in due {
+            let result = self.deliver(&entry).await;
+            let mut outbox = self.outbox.lock().await;
+            let Some(position) = outbox.iter().position(|e| e.url == entry.url && e.event.id == entry.event.id) else {
+                continue;
+            };
+            match result {
+                Ok(()) => {
+                    outbox.remove(position);
+                    delivered += 1;
+                }
+                Err(e) => {
+                    let attempts = entry.attempts + 1;
+                    if attempts >= self.max_attempts {
+                        eprintln!("give up webhook event {} to {} after {} attempts: {}", entry.event.id, entry.url, attempts, e);
+                        outbox.remove(position);
+                    } else {
+                        let delay = backoff_delay(entry.attempts, INITIAL_BACKOFF, MAX_BACKOFF);
+                        eprintln!("webhook event {} to {} failed: {}, retry in {:?}", entry.event.id, entry.url, e, delay);
+                        outbox[position].attempts = attempts;
+                        outbox[position].next_attempt_at = now + delay.as_secs();
+                    }
+                }
+            }
+            self.persist(&outbox).await;
+        }
+        delivered
+    }
+
+    pub async fn run(self) {
+        loop {
+            self.deliver_due(now_secs()).await;
+            let _ = tokio::time::timeout(Duration::from_secs(1), self.notify.notified()).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use axum::body::Bytes;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::Router;
+    use serde_json::json;
+    use tokio::sync::Mutex;
+    use tempfile::tempdir;
+    use crate::config_util::AppConfig;
+    use crate::data_server_util::{SIGNATURE_HEADER, TIMESTAMP_HEADER};
+    use crate::webhook_util::{sign_payload, Event, EventKind, WebhookDispatcher, EVENT_HEADER};
+
+    // 模拟接收方：校验签名，前 fail_first 次返回 500
+    async fn stub_receiver(fail_first: u32) -> (String, Arc<Mutex<Vec<Event>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let events = received.clone();
+        let attempts = Arc::new(Mutex::new(0));
+        let app = Router::new().route("/hook", post(move |headers: HeaderMap, body: Bytes| async move {
+            let mut attempts = attempts.lock().await;
+            *attempts += 1;
+            if *attempts <= fail_first {
+                return StatusCode::INTERNAL_SERVER_ERROR;
+            }
+            let timestamp: u64 = headers[TIMESTAMP_HEADER].to_str().unwrap().parse().unwrap();
+            if headers[SIGNATURE_HEADER] != sign_payload("secret", timestamp, &body).as_str() {
+                return StatusCode::UNAUTHORIZED;
+            }
+            let event: Event = serde_json::from_slice(&body).unwrap();
+            assert_eq!(headers[EVENT_HEADER], "server_crashed");
+            events.lock().await.push(event);
+            StatusCode::OK
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}/hook", addr), received)
+    }
+
+    #[tokio::test]
+    async fn retry_and_persist_test() {
+        let temp = tempdir().unwrap();
+        let (url, received) = stub_receiver(1).await;
+        let config = AppConfig {
+            webhook_urls: vec![url],
+            webhook_secret: "secret".to_string(),
+            webhook_outbox_path: temp.path().join("webhook/outbox.json"),
+            ..AppConfig::default()
+        };
+        let dispatcher = WebhookDispatcher::open(&config, "node-a").await.unwrap();
+        dispatcher.emit(EventKind::ServerCrashed, Some(3), json!({"exit": "signal: 9"})).await;
+
+        // 第一次失败，推迟重试
+        assert_eq!(dispatcher.deliver_due(100).await, 0);
+        let pending = dispatcher.pending().await;
+        assert_eq!((pending[0].attempts, pending[0].next_attempt_at), (1, 102));
+
+        // 重启后从 outbox 文件恢复
+        let reopened = WebhookDispatcher::open(&config, "node-a").await.unwrap();
+        assert_eq!(reopened.deliver_due(101).await, 0);
+        assert_eq!(reopened.deliver_due(102).await, 1);
+        assert!(reopened.pending().await.is_empty());
+        assert!(WebhookDispatcher::open(&config, "node-a").await.unwrap().pending().await.is_empty());
+
+        let events = received.lock().await;
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].kind, events[0].index, events[0].node_id.as_str()), (EventKind::ServerCrashed, Some(3), "node-a"));
+    }
+
+    #[tokio::test]
+    async fn give_up_test() {
+        let temp = tempdir().unwrap();
+        let (url, _) = stub_receiver(u32::MAX).await;
+        let config = AppConfig {
+            webhook_urls: vec![url],
+            webhook_secret: "secret".to_string(),
+            webhook_outbox_path: temp.path().join("webhook/outbox.json"),
+            webhook_max_attempts: 2,
+            ..AppConfig::default()
+        };
+        let dispatcher = WebhookDispatcher::open(&config, "node-a").await.unwrap();
+        dispatcher.emit(EventKind::ServerCrashed, None, json!({})).await;
+        assert_eq!(dispatcher.deliver_due(0).await, 0);
+        assert_eq!(dispatcher.pending().await.len(), 1);
+        assert_eq!(dispatcher.deliver_due(1000).await, 0);
+        assert!(dispatcher.pending().await.is_empty());
+
+        // 没有配置 webhook 时不记录事件
+        let disabled = WebhookDispatcher::open(&AppConfig { webhook_outbox_path: temp.path().join("disabled/outbox.json"), ..AppConfig::default() }, "node-a")
+            .await
+            .unwrap();
+        disabled.emit(EventKind::TunnelDown, None, json!({})).await;
+        assert!(disabled.pending().await.is_empty());
+    }
+}
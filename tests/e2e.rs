@@ -0,0 +1,243 @@
+#[path = "../src/mock_data_server_util.rs"]
+mod mock_data_server_util;
+
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use axum::routing::post;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use axum::{Json, Router};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tempfile::TempDir;
+use tokio::sync::Mutex;
+use crate::mock_data_server_util::{spawn, MockDataServer, FIXTURES_DIR};
+
+// 启动/停服全流程：真实的 game_master 进程 + folk_server 代替游戏 + mock data server + 本地存储 + 假 frpc，
+// 所有路径都在一个临时目录里
+
+struct Harness {
+    temp: TempDir,
+    root: PathBuf,
+    base_url: String,
+    server_port: u16,
+    game_master: Child,
+    mock: MockDataServer,
+    events: Arc<Mutex<Vec<Value>>>,
+    client: reqwest::Client,
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = self.game_master.kill();
+        let _ = self.game_master.wait();
+        // 失败时保留临时目录方便查看日志
+        if std::thread::panicking() {
+            self.temp.disable_cleanup(true);
+            eprintln!("kept {:?}", self.root);
+        }
+    }
+}
+
+const API_TOKEN: &str = "e2e-token";
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+// 接收 webhook 事件
+async fn webhook_receiver() -> (String, Arc<Mutex<Vec<Value>>>) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let received = events.clone();
+    let app = Router::new().route("/hook", post(move |Json(event): Json<Value>| async move {
+        received.lock().await.push(event);
+    }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (url, events)
+}
+
+impl Harness {
+    async fn start() -> Harness {
+        let temp = tempfile::Builder::new().prefix("game_master_e2e").tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        for dir in ["7DaysToDieServer", "installs", "temp", "presets", "frpc", "storage"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        // 假 frpc：被 game_master 监管，只是挂着不退出
+        let frpc = root.join("frpc/frpc");
+        std::fs::write(&frpc, "#!/bin/sh\nexec sleep 60\n").unwrap();
+        Command::new("chmod").arg("+x").arg(&frpc).status().unwrap();
+
+        let mock = spawn(FIXTURES_DIR).await.unwrap();
+        let (webhook_url, events) = webhook_receiver().await;
+        let (listen_port, server_port) = (free_port(), free_port());
+        let config = format!(r#"
+listen_addr = "127.0.0.1:{listen_port}"
+index_strategy = "explicit"
+node_index = 1
+sevendays_server_path = "{root}/7DaysToDieServer"
+sevendays_installs_path = "{root}/installs"
+temp_dir = "{root}/temp"
+presets_dir = "{root}/presets"
+steamcmd_path = "{root}/steamcmd/steamcmd.sh"
+sevendays_stop_time = 2
+game_server_exe = "{folk_server}"
+storage_backend = "local"
+local_storage_dir = "{root}/storage"
+server_port = {server_port}
+web_dashboard_local_port = {web_port}
+telnet_local_port = {telnet_port}
+frpc_exe_path = "{root}/frpc/frpc"
+frpc_toml_path = "{root}/frpc/frpc.toml"
+frps_addr = "127.0.0.1"
+frps_token = "e2e"
+data_server_url = "{data_server}"
+node_registration = false
+webhook_urls = ["{webhook_url}"]
+webhook_secret = "e2e"
+webhook_outbox_path = "{root}/webhook_outbox.json"
+auth_mode = "token"
+api_tokens = ["e2e:{API_TOKEN}:*"]
+audit_log_path = "{root}/audit.log"
+"#, root = root.display(), folk_server = env!("CARGO_BIN_EXE_folk_server"), web_port = free_port(), telnet_port = free_port(),
+                             data_server = mock.base_url);
+        let config_path = root.join("game_master.toml");
+        std::fs::write(&config_path, config).unwrap();
+
+        let log = std::fs::File::create(root.join("game_master.log")).unwrap();
+        let game_master = Command::new(env!("CARGO_BIN_EXE_game_master"))
+            .arg("--config")
+            .arg(&config_path)
+            .stdout(log.try_clone().unwrap())
+            .stderr(log)
+            .spawn()
+            .unwrap();
+
+        let harness = Harness {
+            temp,
+            root,
+            base_url: format!("http://127.0.0.1:{}", listen_port),
+            server_port,
+            game_master,
+            mock,
+            events,
+            client: reqwest::Client::builder().default_headers(HeaderMap::from_iter([
+                (AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {}", API_TOKEN)).unwrap()),
+            ])).build().unwrap(),
+        };
+        harness.wait_for("game_master to listen", |h| async move { h.status().await.is_some() }).await;
+        harness
+    }
+
+    fn server_path(&self) -> PathBuf {
+        self.root.join("7DaysToDieServer")
+    }
+
+    async fn status(&self) -> Option<Value> {
+        let response = self.client.get(format!("{}/status", self.base_url)).send().await.ok()?;
+        response.json().await.ok()
+    }
+
+    async fn running(&self) -> bool {
+        self.status().await.map(|s| s["days7server_running"] == true).unwrap_or(false)
+    }
+
+    async fn get(&self, path: &str) -> reqwest::Response {
+        self.client.get(format!("{}{}", self.base_url, path)).send().await.unwrap()
+    }
+
+    async fn event_kinds(&self) -> Vec<String> {
+        self.events.lock().await.iter().map(|e| e["kind"].as_str().unwrap_or_default().to_string()).collect()
+    }
+
+    async fn wait_for<'a, F, Fut>(&'a self, what: &str, check: F)
+    where F: Fn(&'a Harness) -> Fut, Fut: Future<Output = bool> {
+        let deadline = Instant::now() + Duration::from_secs(20);
+        while !check(self).await {
+            if Instant::now() > deadline {
+                let log = std::fs::read_to_string(self.root.join("game_master.log")).unwrap_or_default();
+                panic!("timed out waiting for {}\ngame_master log:\n{}", what, log);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
+
+fn sha256_hex(path: &Path) -> String {
+    Sha256::digest(std::fs::read(path).unwrap()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[tokio::test]
+async fn start_stop_lifecycle_test() {
+    let h = Harness::start().await;
+    let status = h.status().await.unwrap();
+    assert_eq!((status["index"].as_u64(), status["days7server_running"].as_bool()), (Some(1), Some(false)));
+    // frpc 配置按节点编号生成
+    let frpc_toml = std::fs::read_to_string(h.root.join("frpc/frpc.toml")).unwrap();
+    assert!(frpc_toml.contains("7daysTodieServer-1") && frpc_toml.contains(&format!("localPort = {}", h.server_port)));
+
+    // 没有 token 的请求被拒绝并写进审计日志
+    let anonymous = reqwest::get(format!("{}/start_7days?serverconfig_id=1", h.base_url)).await.unwrap();
+    assert_eq!(anonymous.status(), 401);
+    assert!(std::fs::read_to_string(h.root.join("audit.log")).unwrap().contains(r#""outcome":"unauthorized""#));
+
+    // 用 data server 的配置 1 启动新档
+    let response = h.get("/start_7days?serverconfig_id=1").await;
+    assert!(response.status().is_success(), "{}", response.text().await.unwrap());
+    let resolved: Value = response.json().await.unwrap();
+    assert_eq!(resolved["settings"]["server_name"], "Mock Server");
+    h.wait_for("game server running", |h| h.running()).await;
+    // 已在运行时拒绝再次启动
+    assert!(!h.get("/start_7days?serverconfig_id=1").await.status().is_success());
+
+    let serverconfig = std::fs::read_to_string(h.server_path().join("serverconfig.xml")).unwrap();
+    assert!(serverconfig.contains("Mock Server") && serverconfig.contains(&h.server_port.to_string()));
+    let save_dir = h.server_path().join("Data/Saves/Navezgane/MockGame");
+    h.wait_for("folk_server ready", |h| async move {
+        std::fs::read_to_string(h.server_path().join("output_log.txt")).unwrap_or_default().contains("StartGame done")
+    }).await;
+    assert!(save_dir.join("main.ttw").is_file());
+
+    // 一个玩家进出
+    let mut player = tokio::net::TcpStream::connect(("127.0.0.1", h.server_port)).await.unwrap();
+    player.write_all(b"Alice\n").await.unwrap();
+    h.wait_for("player_joined event", |h| async move { h.event_kinds().await.contains(&"player_joined".to_string()) }).await;
+    drop(player);
+
+    // 停服并备份到存档 1
+    let response = h.get("/stop_7days?save_file_id=1").await;
+    assert!(response.status().is_success(), "{}", response.text().await.unwrap());
+    h.wait_for("game server stopped", |h| async move { !h.running().await }).await;
+
+    let object = h.root.join("storage/days7server/000001/MockGame.zip");
+    assert!(object.is_file());
+    let uploads = h.mock.uploads.lock().await.clone();
+    assert_eq!(uploads.len(), 1);
+    assert_eq!(uploads[0]["object_key"], "000001/MockGame.zip");
+    assert_eq!(uploads[0]["sha256"], sha256_hex(&object));
+    assert_eq!(uploads[0]["size_bytes"].as_u64(), Some(std::fs::metadata(&object).unwrap().len()));
+    assert_eq!(uploads[0]["game_name"], "MockGame");
+    assert_eq!(uploads[0]["game_time"]["day"], 1);
+
+    h.wait_for("lifecycle events", |h| async move {
+        let kinds = h.event_kinds().await;
+        ["server_ready", "player_joined", "player_left", "backup_uploaded", "server_stopped"].iter().all(|k| kinds.contains(&k.to_string()))
+    }).await;
+    assert!(!h.event_kinds().await.contains(&"server_crashed".to_string()));
+
+    // 从刚上传的存档恢复启动
+    std::fs::remove_dir_all(&save_dir).unwrap();
+    let response = h.get("/start_7days?serverconfig_id=1&save_file_id=1").await;
+    assert!(response.status().is_success(), "{}", response.text().await.unwrap());
+    h.wait_for("game server running again", |h| h.running()).await;
+    assert!(std::fs::read_to_string(save_dir.join("main.ttw")).unwrap().contains("world_minutes="));
+
+    assert!(h.get("/stop_7days").await.status().is_success());
+    h.wait_for("game server stopped again", |h| async move { !h.running().await }).await;
+    assert_eq!(h.mock.uploads.lock().await.len(), 1);
+}
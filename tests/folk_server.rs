@@ -0,0 +1,118 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+use tempfile::tempdir;
+
+// 模拟器的集成测试：按 game_master 的方式启动 folk_server，检查日志、玩家、控制台和关服
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+fn start(dir: &Path, game_port: u16, telnet_port: u16) -> Child {
+    let config = format!(r#"<?xml version="1.0"?>
+<ServerSettings>
+	<property name="ServerPort" value="{}"/>
+	<property name="ServerMaxPlayerCount" value="2"/>
+	<property name="TelnetEnabled" value="true"/>
+	<property name="TelnetPort" value="{}"/>
+	<property name="TelnetPassword" value="secret"/>
+	<property name="UserDataFolder" value="{}"/>
+	<property name="GameWorld" value="Navezgane"/>
+	<property name="GameName" value="FolkGame"/>
+</ServerSettings>
+"#, game_port, telnet_port, dir.join("Data").display());
+    std::fs::write(dir.join("serverconfig.xml"), config).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_folk_server"))
+        .args(["-logfile", "output_log.txt", "-quit", "-batchmode", "-nographics", "-dedicated", "-configfile=serverconfig.xml",
+               "-folk-shutdown-delay=0"])
+        .current_dir(dir)
+        .spawn()
+        .unwrap()
+}
+
+fn wait_for_log(dir: &Path, pattern: &str) -> String {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let log = std::fs::read_to_string(dir.join("output_log.txt")).unwrap_or_default();
+        if log.contains(pattern) {
+            return log;
+        }
+        assert!(Instant::now() < deadline, "{:?} not in log:\n{}", pattern, log);
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn read_until(stream: &mut TcpStream, pattern: &str) -> String {
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut output = String::new();
+    let mut buf = [0u8; 1024];
+    while !output.contains(pattern) {
+        let n = stream.read(&mut buf).unwrap();
+        assert!(n > 0, "connection closed before {:?}: {}", pattern, output);
+        output.push_str(&String::from_utf8_lossy(&buf[..n]));
+    }
+    output
+}
+
+fn wait_exit(child: &mut Child) -> std::process::ExitStatus {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            return status;
+        }
+        assert!(Instant::now() < deadline, "folk_server did not exit");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn folk_server_console_test() {
+    let temp = tempdir().unwrap();
+    let dir = temp.path();
+    let (game_port, telnet_port) = (free_port(), free_port());
+    let mut child = start(dir, game_port, telnet_port);
+    wait_for_log(dir, "INF StartGame done");
+
+    let mut player = TcpStream::connect(("127.0.0.1", game_port)).unwrap();
+    player.write_all(b"Alice Smith\n").unwrap();
+    let mut welcome = String::new();
+    BufReader::new(&player).read_line(&mut welcome).unwrap();
+    assert_eq!(welcome.trim(), "Welcome Alice Smith");
+    wait_for_log(dir, "INF Player connected, entityid=171, name=Alice Smith, pltfmid=Steam_");
+
+    let mut console = TcpStream::connect(("127.0.0.1", telnet_port)).unwrap();
+    read_until(&mut console, "password");
+    console.write_all(b"secret\r\n").unwrap();
+    read_until(&mut console, "*** Connected with 7DTD server.");
+    console.write_all(b"lp\r\n").unwrap();
+    assert!(read_until(&mut console, "in the game").contains("Total of 1 in the game"));
+    console.write_all(b"gettime\r\n").unwrap();
+    read_until(&mut console, "Day 1, 07:");
+
+    drop(player);
+    wait_for_log(dir, "PlayerName='Alice Smith'");
+
+    console.write_all(b"shutdown\r\n").unwrap();
+    assert!(wait_exit(&mut child).success());
+    let log = wait_for_log(dir, "INF Game server stopped");
+    assert!(log.contains("INF Shutdown game from console"));
+    let world = std::fs::read_to_string(dir.join("Data/Saves/Navezgane/FolkGame/main.ttw")).unwrap();
+    assert!(world.contains("world_minutes=4"));
+}
+
+#[test]
+fn folk_server_sigint_test() {
+    let temp = tempdir().unwrap();
+    let dir = temp.path();
+    let mut child = start(dir, free_port(), free_port());
+    wait_for_log(dir, "INF StartGame done");
+
+    // game_master 用 kill -2 停服
+    Command::new("kill").arg("-2").arg(child.id().to_string()).status().unwrap();
+    assert!(wait_exit(&mut child).success());
+    let log = wait_for_log(dir, "INF Game server stopped");
+    assert!(log.contains("INF Shutdown game from SIGINT"));
+}